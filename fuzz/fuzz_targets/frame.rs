@@ -0,0 +1,43 @@
+//! Frame payloads: `Payload::decode` must not panic, and a payload that decodes must encode to a
+//! frame that decodes to the same payload again.
+//!
+//! The input is a frame as it would arrive, header and all, except that the length in the header
+//! is ignored in favour of the length of the payload actually there.
+
+#![no_main]
+
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate teepee;
+
+use teepee::ByteTendril;
+use teepee::http2::frame::{ErrorCode, Header, Payload};
+
+fn decode_header(b: &[u8]) -> Header<u8> {
+    Header::decode([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7], b[8]])
+}
+
+fn decode(header: Header<u8>, payload: &[u8]) -> Result<Payload, ErrorCode> {
+    Payload::decode(header, ByteTendril::from_slice(payload))
+}
+
+fuzz_target!(|data: &[u8]| {
+    if data.len() < 9 {
+        return;
+    }
+    let mut header = decode_header(data);
+    header.length = (data.len() - 9) as u32;
+    let payload = match decode(header, &data[9..]) {
+        Ok(payload) => payload,
+        Err(_) => return,
+    };
+
+    let mut encoded = vec![];
+    payload.write_frame(header.stream_identifier, &mut encoded).unwrap();
+    let encoded_header = decode_header(&encoded);
+    assert_eq!(encoded_header.length as usize, encoded.len() - 9);
+    assert_eq!(encoded_header.type_, header.type_);
+    assert_eq!(encoded_header.stream_identifier, header.stream_identifier);
+    // The payload was consumed in the encoding, so it is decoded afresh to compare with.
+    assert_eq!(decode(encoded_header, &encoded[9..]), decode(header, &data[9..]));
+});
@@ -0,0 +1,30 @@
+//! Header blocks: decoding with `InstructionDecoder` and executing the instructions against the
+//! tables must not panic, and instructions that decode must encode to a block that decodes to the
+//! same instructions again.
+
+#![no_main]
+
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate teepee;
+
+use teepee::ByteTendril;
+use teepee::http2::frame::hpack::{Decoder, Fragment, Instruction, InstructionDecoder, Tables};
+
+fuzz_target!(|data: &[u8]| {
+    let input = ByteTendril::from_slice(data);
+    let mut tables = Tables::new();
+    for entry in Decoder::new(input.clone(), &mut tables) {
+        if entry.is_err() {
+            break;
+        }
+    }
+
+    let instructions = match InstructionDecoder::new(input).collect::<Result<Vec<_>, _>>() {
+        Ok(instructions) => instructions,
+        Err(_) => return,
+    };
+    let encoded = Fragment::Instructions(instructions.clone()).into_tendril();
+    let decoded: Vec<Instruction> = InstructionDecoder::new(encoded).map(Result::unwrap).collect();
+    assert_eq!(decoded, instructions);
+});
@@ -0,0 +1,25 @@
+//! Header field values: `ToHeader::parse` must not panic, and a value that parses must format
+//! to a field value that parses to the same value again.
+
+#![no_main]
+
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate teepee;
+
+use std::fmt;
+
+use teepee::headers::{Header, ToHeader};
+use teepee::headers::priority::Priority;
+
+fn round_trip<H: Header + ToHeader + PartialEq + fmt::Debug>(raw: &[u8]) {
+    if let Some(parsed) = H::parse(raw) {
+        let formatted = parsed.to_raw();
+        assert_eq!(H::parse(&formatted), Some(parsed));
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    round_trip::<usize>(data);
+    round_trip::<Priority>(data);
+});
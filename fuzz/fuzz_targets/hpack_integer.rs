@@ -0,0 +1,44 @@
+//! HPACK integers: decoding with any prefix size must not panic, and an integer that decodes must
+//! encode to its shortest representation, which decodes to the same integer again.
+
+#![no_main]
+
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate teepee;
+
+use std::io;
+
+use teepee::ByteTendril;
+use teepee::http2::frame::hpack::DecodeError;
+use teepee::http2::frame::hpack::primitives::{decode4, decode5, decode6, decode7};
+use teepee::http2::frame::hpack::primitives::{encode4, encode5, encode6, encode7};
+
+type Decode = fn(&mut ByteTendril) -> Result<u32, DecodeError>;
+type Encode = fn(&mut Vec<u8>, u8, u32) -> io::Result<()>;
+
+fuzz_target!(|data: &[u8]| {
+    let codecs: [(u8, Decode, Encode); 4] = [
+        (0b00001111, decode4, encode4),
+        (0b00011111, decode5, encode5),
+        (0b00111111, decode6, encode6),
+        (0b01111111, decode7, encode7),
+    ];
+    for &(mask, decode, encode) in &codecs {
+        let mut input = ByteTendril::from_slice(data);
+        let i = match decode(&mut input) {
+            Ok(i) => i,
+            Err(_) => continue,
+        };
+        let consumed = data.len() - input.len32() as usize;
+
+        // The bits above the prefix belong to whatever representation the integer is part of.
+        let mut encoded = vec![];
+        encode(&mut encoded, data[0] & !mask, i).unwrap();
+        assert_eq!(encoded[0], data[0]);
+        assert!(encoded.len() <= consumed);
+        let mut input = ByteTendril::from_slice(&encoded);
+        assert_eq!(decode(&mut input), Ok(i));
+        assert_eq!(input.len32(), 0);
+    }
+});
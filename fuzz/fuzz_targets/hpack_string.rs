@@ -0,0 +1,32 @@
+//! HPACK string literals: decoding must not panic, and a string that decodes must encode, with
+//! Huffman coding or without, to a literal that decodes to the same string again.
+
+#![no_main]
+
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate teepee;
+
+use std::io;
+
+use teepee::ByteTendril;
+use teepee::http2::frame::hpack::primitives::{decode_string, encode_huffman, encode_plain,
+                                               encode_string};
+
+fuzz_target!(|data: &[u8]| {
+    let mut input = ByteTendril::from_slice(data);
+    let decoded = match decode_string(&mut input, None) {
+        Ok(decoded) => decoded,
+        Err(_) => return,
+    };
+
+    let encoders: [fn(&mut Vec<u8>, &[u8]) -> io::Result<()>; 3] =
+        [encode_string, encode_plain, encode_huffman];
+    for encode in &encoders {
+        let mut encoded = vec![];
+        encode(&mut encoded, &decoded).unwrap();
+        let mut input = ByteTendril::from_slice(&encoded);
+        assert_eq!(decode_string(&mut input, None), Ok(decoded.clone()));
+        assert_eq!(input.len32(), 0);
+    }
+});
@@ -0,0 +1,69 @@
+//! Print the HTTP/2 frames in a captured byte stream, for seeing what crossed the wire.
+//!
+//! ```text
+//! h2dump FILE
+//! ```
+//!
+//! The file holds what one end of a connection sent, from the start; if it begins with the client
+//! connection preface, that is skipped. Each frame is printed as `frame::trace::Tracer` shows it,
+//! header blocks being decoded as they go by.
+
+extern crate teepee;
+
+use std::env;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::process;
+
+use teepee::http2::frame::CONNECTION_PRELUDE;
+use teepee::http2::frame::codec::{FrameReader, ReadError};
+use teepee::http2::frame::trace::Tracer;
+
+/// The largest frame payload that the frame header can describe. What the receiver allowed with
+/// SETTINGS_MAX_FRAME_SIZE is another matter, but it isn’t in the capture.
+const MAX_FRAME_SIZE: u32 = 0xffffff;
+
+fn main() {
+    let path = match (env::args_os().nth(1), env::args_os().nth(2)) {
+        (Some(path), None) => path,
+        _ => {
+            let _ = writeln!(io::stderr(), "usage: h2dump FILE");
+            process::exit(2);
+        },
+    };
+    let mut input = vec![];
+    if let Err(e) = File::open(&path).and_then(|mut file| file.read_to_end(&mut input)) {
+        let _ = writeln!(io::stderr(), "h2dump: {}: {}", path.to_string_lossy(), e);
+        process::exit(1);
+    }
+    let frames = if input.starts_with(CONNECTION_PRELUDE) {
+        &input[CONNECTION_PRELUDE.len()..]
+    } else {
+        &input[..]
+    };
+
+    let mut reader = FrameReader::new(frames);
+    reader.set_max_frame_size(MAX_FRAME_SIZE);
+    let mut tracer = Tracer::new();
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    loop {
+        let mut trace = String::new();
+        match reader.read_frame() {
+            Ok(Some((header, payload))) => {
+                // Writing to a String can’t fail.
+                tracer.trace(&mut trace, header.stream_identifier, &payload).unwrap();
+            },
+            Ok(None) => break,
+            // The bad frame has been read all the same, so the next one can still be found.
+            Err(error @ ReadError::Frame(..)) => trace = format!("{}\n", error),
+            Err(error) => {
+                let _ = writeln!(io::stderr(), "h2dump: {}", error);
+                process::exit(1);
+            },
+        }
+        if stdout.write_all(trace.as_bytes()).is_err() {
+            process::exit(1);
+        }
+    }
+}
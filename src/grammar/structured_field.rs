@@ -0,0 +1,414 @@
+//! Structured field values for HTTP, as defined in [RFC 8941][spec].
+//!
+//! Newer header fields (such as `Priority`) are defined in terms of these rather than of ad hoc
+//! ABNF. Only parsing is done here; the three top-level types are lists, dictionaries and items,
+//! of which only dictionaries are implemented as yet, there being nothing that needs the others.
+//!
+//! [spec]: http://tools.ietf.org/html/rfc8941
+
+use grammar::token::is_tchar;
+
+/// Like `try!`, but for `Option`.
+macro_rules! try_opt {
+    ($e:expr) => {
+        match $e {
+            Some(value) => value,
+            None => return None,
+        }
+    }
+}
+
+/// A bare item: the value of an item or a parameter.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum BareItem {
+    /// `sf-integer`: up to fifteen decimal digits, optionally negative.
+    Integer(i64),
+
+    /// `sf-decimal`: up to twelve integral and three fractional digits. This is stored in
+    /// thousandths, so that `1.5` is `Decimal(1500)`.
+    Decimal(i64),
+
+    /// `sf-string`: a quoted string of printable ASCII, unescaped.
+    String(String),
+
+    /// `sf-token`: a token, which is a bit more liberal than an RFC 7230 `token`.
+    Token(String),
+
+    /// `sf-binary`: a base64-encoded byte sequence, decoded.
+    ByteSequence(Vec<u8>),
+
+    /// `sf-boolean`: `?1` or `?0`.
+    Boolean(bool),
+}
+
+/// Parameters on an item or inner list, in order.
+pub type Parameters = Vec<(String, BareItem)>;
+
+/// An item: a bare item with parameters.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Item {
+    /// The value.
+    pub bare_item: BareItem,
+    /// Its parameters.
+    pub parameters: Parameters,
+}
+
+/// A member of a list or dictionary.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum Member {
+    /// A single item.
+    Item(Item),
+    /// A parenthesised list of items, with parameters of its own.
+    InnerList(Vec<Item>, Parameters),
+}
+
+/// A dictionary: keys and their values, in order.
+///
+/// A key appearing more than once keeps its first position, but takes its last value.
+pub type Dictionary = Vec<(String, Member)>;
+
+/// Parse a field value as a dictionary, returning `None` if it is not one.
+///
+/// > If parsing fails -- including when calling another algorithm -- the entire field value MUST
+/// > be ignored (i.e., treated as if the field were not present in the section).
+pub fn parse_dictionary(input: &[u8]) -> Option<Dictionary> {
+    let mut parser = Parser { input: input, position: 0 };
+    parser.skip_sp();
+    let mut dictionary = Dictionary::new();
+    while !parser.at_end() {
+        let key = try_opt!(parser.parse_key());
+        let member = if parser.eat(b'=') {
+            try_opt!(parser.parse_member())
+        } else {
+            // > A Dictionary member whose value is Boolean true … MUST omit that value.
+            Member::Item(Item {
+                bare_item: BareItem::Boolean(true),
+                parameters: try_opt!(parser.parse_parameters()),
+            })
+        };
+        match dictionary.iter().position(|&(ref k, _)| *k == key) {
+            Some(i) => dictionary[i].1 = member,
+            None => dictionary.push((key, member)),
+        }
+        parser.skip_ows();
+        if parser.at_end() {
+            break;
+        }
+        if !parser.eat(b',') {
+            return None;
+        }
+        parser.skip_ows();
+        // A trailing comma is not allowed.
+        if parser.at_end() {
+            return None;
+        }
+    }
+    Some(dictionary)
+}
+
+struct Parser<'a> {
+    input: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn at_end(&self) -> bool {
+        self.position == self.input.len()
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.input.get(self.position).cloned()
+    }
+
+    fn eat(&mut self, byte: u8) -> bool {
+        if self.peek() == Some(byte) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn skip_sp(&mut self) {
+        while self.eat(b' ') { }
+    }
+
+    fn skip_ows(&mut self) {
+        while self.eat(b' ') || self.eat(b'\t') { }
+    }
+
+    fn parse_member(&mut self) -> Option<Member> {
+        if self.peek() == Some(b'(') {
+            self.parse_inner_list()
+        } else {
+            self.parse_item().map(Member::Item)
+        }
+    }
+
+    fn parse_inner_list(&mut self) -> Option<Member> {
+        let _ = self.eat(b'(');
+        let mut items = vec![];
+        loop {
+            self.skip_sp();
+            if self.eat(b')') {
+                let parameters = try_opt!(self.parse_parameters());
+                return Some(Member::InnerList(items, parameters));
+            }
+            items.push(try_opt!(self.parse_item()));
+            // Items must be separated by spaces.
+            match self.peek() {
+                Some(b' ') | Some(b')') => (),
+                _ => return None,
+            }
+        }
+    }
+
+    fn parse_item(&mut self) -> Option<Item> {
+        let bare_item = try_opt!(self.parse_bare_item());
+        let parameters = try_opt!(self.parse_parameters());
+        Some(Item {
+            bare_item: bare_item,
+            parameters: parameters,
+        })
+    }
+
+    fn parse_parameters(&mut self) -> Option<Parameters> {
+        let mut parameters = Parameters::new();
+        while self.eat(b';') {
+            self.skip_sp();
+            let key = try_opt!(self.parse_key());
+            let value = if self.eat(b'=') {
+                try_opt!(self.parse_bare_item())
+            } else {
+                BareItem::Boolean(true)
+            };
+            match parameters.iter().position(|&(ref k, _)| *k == key) {
+                Some(i) => parameters[i].1 = value,
+                None => parameters.push((key, value)),
+            }
+        }
+        Some(parameters)
+    }
+
+    /// `key = ( lcalpha / "*" ) *( lcalpha / DIGIT / "_" / "-" / "." / "*" )`
+    fn parse_key(&mut self) -> Option<String> {
+        let start = self.position;
+        match self.peek() {
+            Some(b'a'...b'z') | Some(b'*') => self.position += 1,
+            _ => return None,
+        }
+        loop {
+            match self.peek() {
+                Some(b'a'...b'z') | Some(b'0'...b'9') | Some(b'_') | Some(b'-') | Some(b'.') |
+                Some(b'*') => self.position += 1,
+                _ => break,
+            }
+        }
+        Some(ascii(&self.input[start..self.position]))
+    }
+
+    fn parse_bare_item(&mut self) -> Option<BareItem> {
+        match self.peek() {
+            Some(b'-') | Some(b'0'...b'9') => self.parse_number(),
+            Some(b'"') => self.parse_string(),
+            Some(b'A'...b'Z') | Some(b'a'...b'z') | Some(b'*') => self.parse_token(),
+            Some(b':') => self.parse_byte_sequence(),
+            Some(b'?') => self.parse_boolean(),
+            _ => None,
+        }
+    }
+
+    fn parse_number(&mut self) -> Option<BareItem> {
+        let negative = self.eat(b'-');
+        let mut integral = 0i64;
+        let mut integral_digits = 0;
+        while let Some(digit @ b'0'...b'9') = self.peek() {
+            self.position += 1;
+            integral = integral * 10 + (digit - b'0') as i64;
+            integral_digits += 1;
+            if integral_digits > 15 {
+                return None;
+            }
+        }
+        if integral_digits == 0 {
+            return None;
+        }
+        let sign = if negative { -1 } else { 1 };
+        if !self.eat(b'.') {
+            return Some(BareItem::Integer(sign * integral));
+        }
+        if integral_digits > 12 {
+            return None;
+        }
+        let mut fraction = 0i64;
+        let mut fraction_digits = 0;
+        while let Some(digit @ b'0'...b'9') = self.peek() {
+            self.position += 1;
+            fraction = fraction * 10 + (digit - b'0') as i64;
+            fraction_digits += 1;
+            if fraction_digits > 3 {
+                return None;
+            }
+        }
+        if fraction_digits == 0 {
+            return None;
+        }
+        for _ in fraction_digits..3 {
+            fraction *= 10;
+        }
+        Some(BareItem::Decimal(sign * (integral * 1000 + fraction)))
+    }
+
+    fn parse_string(&mut self) -> Option<BareItem> {
+        let _ = self.eat(b'"');
+        let mut string = String::new();
+        loop {
+            match self.peek() {
+                Some(b'"') => {
+                    self.position += 1;
+                    return Some(BareItem::String(string));
+                },
+                Some(b'\\') => {
+                    self.position += 1;
+                    match self.peek() {
+                        Some(c @ b'"') | Some(c @ b'\\') => string.push(c as char),
+                        _ => return None,
+                    }
+                },
+                Some(c @ 0x20...0x7e) => string.push(c as char),
+                _ => return None,
+            }
+            self.position += 1;
+        }
+    }
+
+    fn parse_token(&mut self) -> Option<BareItem> {
+        let start = self.position;
+        self.position += 1;
+        while let Some(c) = self.peek() {
+            if !is_tchar(c) && c != b':' && c != b'/' {
+                break;
+            }
+            self.position += 1;
+        }
+        Some(BareItem::Token(ascii(&self.input[start..self.position])))
+    }
+
+    fn parse_byte_sequence(&mut self) -> Option<BareItem> {
+        let _ = self.eat(b':');
+        let start = self.position;
+        while let Some(c) = self.peek() {
+            if c == b':' {
+                let bytes = try_opt!(decode_base64(&self.input[start..self.position]));
+                self.position += 1;
+                return Some(BareItem::ByteSequence(bytes));
+            }
+            self.position += 1;
+        }
+        None
+    }
+
+    fn parse_boolean(&mut self) -> Option<BareItem> {
+        let _ = self.eat(b'?');
+        if self.eat(b'1') {
+            Some(BareItem::Boolean(true))
+        } else if self.eat(b'0') {
+            Some(BareItem::Boolean(false))
+        } else {
+            None
+        }
+    }
+}
+
+/// Turn bytes already checked to be ASCII into a string.
+fn ascii(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| b as char).collect()
+}
+
+/// Decode base64 (RFC 4648, section 4), with optional padding.
+fn decode_base64(input: &[u8]) -> Option<Vec<u8>> {
+    let input = match input.iter().position(|&c| c == b'=') {
+        Some(i) if input[i..].iter().all(|&c| c == b'=') && input.len() % 4 == 0 => &input[..i],
+        Some(_) => return None,
+        None => input,
+    };
+    let mut output = Vec::with_capacity(input.len() * 3 / 4);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for &c in input {
+        let value = match c {
+            b'A'...b'Z' => c - b'A',
+            b'a'...b'z' => c - b'a' + 26,
+            b'0'...b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        buffer = buffer << 6 | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            output.push((buffer >> bits) as u8);
+        }
+    }
+    // One character left over can’t make up a byte.
+    if bits == 6 {
+        return None;
+    }
+    Some(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BareItem, Item, Member, parse_dictionary};
+
+    fn item(bare_item: BareItem) -> Member {
+        Member::Item(Item {
+            bare_item: bare_item,
+            parameters: vec![],
+        })
+    }
+
+    #[test]
+    fn dictionaries() {
+        assert_eq!(parse_dictionary(b""), Some(vec![]));
+        assert_eq!(parse_dictionary(b"u=3, i"), Some(vec![
+            ("u".to_owned(), item(BareItem::Integer(3))),
+            ("i".to_owned(), item(BareItem::Boolean(true))),
+        ]));
+        assert_eq!(parse_dictionary(b"a=1,b=2, a=?0"), Some(vec![
+            ("a".to_owned(), item(BareItem::Boolean(false))),
+            ("b".to_owned(), item(BareItem::Integer(2))),
+        ]));
+        assert_eq!(parse_dictionary(b"a=(1 \"two\");x, b;y=tok/en"), Some(vec![
+            ("a".to_owned(), Member::InnerList(vec![
+                Item { bare_item: BareItem::Integer(1), parameters: vec![] },
+                Item { bare_item: BareItem::String("two".to_owned()), parameters: vec![] },
+            ], vec![("x".to_owned(), BareItem::Boolean(true))])),
+            ("b".to_owned(), Member::Item(Item {
+                bare_item: BareItem::Boolean(true),
+                parameters: vec![("y".to_owned(), BareItem::Token("tok/en".to_owned()))],
+            })),
+        ]));
+        assert_eq!(parse_dictionary(b"a=-1.5, b=:aGk=:, c=\"q\\\"\""), Some(vec![
+            ("a".to_owned(), item(BareItem::Decimal(-1500))),
+            ("b".to_owned(), item(BareItem::ByteSequence(b"hi".to_vec()))),
+            ("c".to_owned(), item(BareItem::String("q\"".to_owned()))),
+        ]));
+    }
+
+    #[test]
+    fn bad_dictionaries() {
+        assert_eq!(parse_dictionary(b"U=1"), None);
+        assert_eq!(parse_dictionary(b"a=1,"), None);
+        assert_eq!(parse_dictionary(b"a=1 b=2"), None);
+        assert_eq!(parse_dictionary(b"a="), None);
+        assert_eq!(parse_dictionary(b"a=1234567890123456"), None);
+        assert_eq!(parse_dictionary(b"a=1.2345"), None);
+        assert_eq!(parse_dictionary(b"a=1."), None);
+        assert_eq!(parse_dictionary(b"a=?2"), None);
+        assert_eq!(parse_dictionary(b"a=\"unterminated"), None);
+        assert_eq!(parse_dictionary(b"a=:not base64:"), None);
+        assert_eq!(parse_dictionary(b"a=(1 2"), None);
+    }
+}
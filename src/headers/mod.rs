@@ -18,6 +18,7 @@ pub use self::internals::TypedListRef;
 
 mod internals;
 mod implementations;
+pub mod priority;
 
 /// A trait defining the parsing of a header from a raw value.
 pub trait ToHeader: Sized {
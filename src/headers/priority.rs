@@ -0,0 +1,143 @@
+//! The `Priority` header field. See [RFC 9218, section 5][spec].
+//!
+//! > The Priority HTTP header field carries priority parameters (see Section 4).  It can appear in
+//! > requests and responses.
+//!
+//! The same value is also carried by HTTP/2 PRIORITY_UPDATE frames.
+//!
+//! [spec]: http://tools.ietf.org/html/rfc9218#section-5
+
+use std::borrow::Cow;
+use std::fmt;
+
+use grammar::structured_field::{self, BareItem, Member};
+use super::{Header, Marker, Ref, ToHeader};
+
+/// The urgency of a response that doesn’t say otherwise.
+pub const DEFAULT_URGENCY: u8 = 3;
+
+/// The lowest urgency (the highest number) permitted.
+pub const MAX_URGENCY: u8 = 7;
+
+/// The priority parameters of a response.
+///
+/// A field that doesn’t mention a parameter, or gives it an invalid value, leaves it at its
+/// default; unknown parameters are ignored.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Priority {
+    /// > The urgency (u) parameter value is Integer (see Section 3.3.1 of [STRUCTURED-FIELDS]),
+    /// > between 0 and 7 inclusive, in descending order of priority.  The default is 3.
+    pub urgency: u8,
+
+    /// > The incremental (i) parameter value is Boolean (see Section 3.3.6 of
+    /// > [STRUCTURED-FIELDS]).  It indicates if an HTTP response can be processed incrementally,
+    /// > i.e., provide some meaningful output as chunks of the response arrive.  The default
+    /// > value of the incremental parameter is false (0).
+    pub incremental: bool,
+}
+
+impl Default for Priority {
+    fn default() -> Priority {
+        Priority {
+            urgency: DEFAULT_URGENCY,
+            incremental: false,
+        }
+    }
+}
+
+impl ToHeader for Priority {
+    fn parse(raw: &[u8]) -> Option<Priority> {
+        let dictionary = match structured_field::parse_dictionary(raw) {
+            Some(dictionary) => dictionary,
+            None => return None,
+        };
+        let mut priority = Priority::default();
+        for (key, member) in dictionary {
+            let bare_item = match member {
+                Member::Item(item) => item.bare_item,
+                Member::InnerList(..) => continue,
+            };
+            match (&*key, bare_item) {
+                // > The urgency (u) parameter … Endpoints MUST ignore the parameter if the value
+                // > is out of range.
+                ("u", BareItem::Integer(urgency)) if urgency >= 0 &&
+                                                     urgency <= MAX_URGENCY as i64 => {
+                    priority.urgency = urgency as u8;
+                },
+                ("i", BareItem::Boolean(incremental)) => priority.incremental = incremental,
+                _ => (),
+            }
+        }
+        Some(priority)
+    }
+}
+
+impl Header for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Defaults are omitted, but something has to be written.
+        if self.urgency != DEFAULT_URGENCY || !self.incremental {
+            try!(write!(f, "u={}", self.urgency));
+            if self.incremental {
+                try!(f.write_str(", "));
+            }
+        }
+        if self.incremental {
+            try!(f.write_str("i"));
+        }
+        Ok(())
+    }
+}
+
+/// The marker for the `Priority` header field.
+#[allow(non_camel_case_types)]
+pub struct PRIORITY;
+
+impl<'a> Marker<'a> for PRIORITY {
+    type Base = Priority;
+    type Get = Option<Ref<'a, Cow<'a, Priority>>>;
+    type GetMut = Option<&'a mut Priority>;
+    type Set = Priority;
+
+    fn header_name() -> &'static str {
+        "priority"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Priority;
+    use headers::{HeaderDisplayAdapter, ToHeader};
+
+    fn priority(urgency: u8, incremental: bool) -> Priority {
+        Priority {
+            urgency: urgency,
+            incremental: incremental,
+        }
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(Priority::parse(b""), Some(Priority::default()));
+        assert_eq!(Priority::parse(b"u=5"), Some(priority(5, false)));
+        assert_eq!(Priority::parse(b"i"), Some(priority(3, true)));
+        assert_eq!(Priority::parse(b"u=0, i"), Some(priority(0, true)));
+        assert_eq!(Priority::parse(b"i=?0, u=1;foo"), Some(priority(1, false)));
+        // Invalid values and unknown parameters are ignored…
+        assert_eq!(Priority::parse(b"u=8, i=1, x=(1 2)"), Some(priority(3, false)));
+        assert_eq!(Priority::parse(b"u=-1, i=\"yes\""), Some(priority(3, false)));
+        // … but syntax errors are not.
+        assert_eq!(Priority::parse(b"u=1,"), None);
+        assert_eq!(Priority::parse(b"U=1"), None);
+    }
+
+    #[test]
+    fn fmt() {
+        for &(priority, raw) in &[(Priority::default(), "u=3"),
+                                  (priority(0, false), "u=0"),
+                                  (priority(3, true), "i"),
+                                  (priority(7, true), "u=7, i")] {
+            assert_eq!(format!("{}", HeaderDisplayAdapter(&priority)), raw);
+            assert_eq!(Priority::parse(raw.as_bytes()), Some(priority));
+        }
+    }
+}
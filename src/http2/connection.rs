@@ -0,0 +1,2999 @@
+//! The HTTP/2 connection state machine.
+//!
+//! `Connection` does no I/O of its own. You feed it the frames you receive (from a `FrameReader`,
+//! say) and it gives you back events for the application to deal with, and frames to send (with a
+//! `FrameWriter`, say). This keeps it independent of any particular I/O model; blocking, non-
+//! blocking and in-memory test harnesses are all the same to it.
+//!
+//! Of the connection preface, the magic octets that the client starts with are for the
+//! `FrameWriter` and `FrameReader` to deal with; the SETTINGS frames that follow are dealt with
+//! here, a connection starting by sending one and requiring the peer to have done likewise.
+
+use std::cmp;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::mem;
+
+use {ByteTendril, TendrilSliceExt};
+use http2::frame::{ErrorCode, Frame, Header, Payload};
+use http2::frame::altsvc::AltSvc;
+use http2::frame::data::Data;
+use http2::frame::extension::ExtensionFrame;
+use http2::frame::goaway::GoAway;
+use http2::frame::headers::Headers;
+use http2::frame::hpack::{self, Entry, Fragment};
+use http2::frame::origin::Origin;
+use http2::frame::ping::Ping;
+use http2::frame::priority::Priority;
+use http2::frame::priority_update::PriorityUpdate;
+use http2::frame::push_promise::PushPromise;
+use http2::frame::rst_stream::RstStream;
+use http2::frame::settings::{Settings, Values};
+use http2::frame::window_update::WindowUpdate;
+use http2::flow_control::{DEFAULT_WINDOW_SIZE, ReceiveWindow, UpdatePolicy, Window};
+use http2::header_block::{Assembler, HeaderBlock, Pushed};
+use http2::padding::{Padder, Padding};
+use http2::priority::{DEFAULT_WEIGHT, Tree};
+use http2::protection::Protection;
+use http2::Error;
+use http2::stream::{Closed, Kind, State, StreamId, StreamIdAllocator};
+
+/// The default limit on the size of an encoded header block, in octets.
+pub const DEFAULT_MAX_HEADER_BLOCK_SIZE: u32 = 65536;
+
+/// The payload of the PING that `shut_down` sends to time the round trip.
+pub const SHUTDOWN_PING_DATA: [u8; 8] = *b"shutdown";
+
+/// The default limit on how many closed streams are remembered, with how they were closed, for
+/// dealing with frames that were on their way when they closed.
+pub const DEFAULT_MAX_CLOSED_STREAMS: u32 = 256;
+
+/// Which end of the connection we are.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Role {
+    /// We initiate odd-numbered streams and receive pushes.
+    Client,
+    /// We receive odd-numbered streams and initiate even-numbered ones by pushing.
+    Server,
+}
+
+/// Something that has happened on a connection which the application needs to know about.
+#[derive(Debug, Eq, PartialEq)]
+pub enum Event {
+    /// A complete header list was received in HEADERS (and perhaps CONTINUATION) frames.
+    Headers {
+        /// The stream the headers were received on.
+        stream_id: StreamId,
+        /// The priority given in the HEADERS frame, if any.
+        priority: Option<Priority>,
+        /// The decoded header list.
+        headers: Vec<Entry>,
+        /// Whether this is the last frame the peer will send on the stream.
+        end_stream: bool,
+    },
+
+    /// A server has promised to push a response to the given request.
+    PushPromise {
+        /// The stream with which the push is associated.
+        stream_id: StreamId,
+        /// The stream which the pushed response will be sent on.
+        promised_stream_id: StreamId,
+        /// The decoded header list of the promised request.
+        headers: Vec<Entry>,
+    },
+
+    /// Data was received.
+    Data {
+        /// The stream the data was received on.
+        stream_id: StreamId,
+        /// The data (without padding).
+        data: ByteTendril,
+        /// Whether this is the last frame the peer will send on the stream.
+        end_stream: bool,
+    },
+
+    /// The peer reset a stream.
+    Reset {
+        /// The stream that was reset.
+        stream_id: StreamId,
+        /// Why it was reset.
+        error_code: ErrorCode,
+    },
+
+    /// The peer is shutting the connection down.
+    GoAway {
+        /// The last stream that the peer might have acted upon.
+        last_stream_id: StreamId,
+        /// Why the connection is being shut down.
+        error_code: ErrorCode,
+        /// Diagnostic data; take care, it could contain anything.
+        additional_debug_data: ByteTendril,
+        /// The streams we initiated which the peer has not acted upon and never will, in order.
+        /// They are now closed, and their requests can be retried on another connection.
+        ///
+        /// > The GOAWAY frame indicates the highest stream number that might
+        /// > have been processed.  Requests on streams with higher numbers are
+        /// > therefore guaranteed to be safe to retry.
+        unprocessed: Vec<StreamId>,
+    },
+
+    /// The peer changed its settings; these are its new values, now in force.
+    SettingsChanged(Values),
+
+    /// The peer acknowledged our settings; these are our new values, now in force.
+    SettingsAcknowledged(Values),
+
+    /// The peer responded to a PING that we sent.
+    PingAcknowledged([u8; 8]),
+
+    /// The client changed the priority of a request with a PRIORITY_UPDATE frame.
+    ///
+    /// The value can be parsed with `headers::priority::Priority`.
+    PriorityUpdate {
+        /// The stream whose priority changed.
+        stream_id: StreamId,
+        /// The new priority, in the format of the `Priority` header field.
+        priority_field_value: ByteTendril,
+    },
+
+    /// The server advertised alternative services with an ALTSVC frame (RFC 7838).
+    ///
+    /// The value can be parsed as the `Alt-Svc` header field.
+    AltSvc {
+        /// The stream of the request the services apply to, or stream 0 for `origin`.
+        stream_id: StreamId,
+        /// The origin the services apply to; empty for a stream other than stream 0.
+        origin: ByteTendril,
+        /// The alternative services, in the format of the `Alt-Svc` header field.
+        field_value: ByteTendril,
+    },
+
+    /// The server sent the origins it is authoritative for with an ORIGIN frame (RFC 8336).
+    Origin(Vec<ByteTendril>),
+
+    /// The peer gave us more flow-control window, so more data may be sent.
+    WindowUpdate {
+        /// The stream whose window was increased, or stream 0 for the connection’s window.
+        stream_id: StreamId,
+        /// How much the window was increased by.
+        window_size_increment: u32,
+    },
+
+    /// A frame of a type registered with the `Extensions` of the frame reader was received.
+    ///
+    /// Such frames are only passed on; they do nothing to the state of the connection.
+    Extension {
+        /// The stream the frame was received on.
+        stream_id: StreamId,
+        /// The frame, which can be downcast to the registered type.
+        frame: Box<ExtensionFrame>,
+    },
+}
+
+/// A request whose response a server is to push, for `Connection::push`.
+#[derive(Debug, Eq, PartialEq)]
+pub struct PushRequest {
+    /// The method, which must be safe and cacheable; that is, `GET` or `HEAD`.
+    pub method: ByteTendril,
+    /// The scheme, such as `https`.
+    pub scheme: ByteTendril,
+    /// The authority, which the server must be authoritative for.
+    pub authority: ByteTendril,
+    /// The path, with any query.
+    pub path: ByteTendril,
+    /// Any other request header fields.
+    pub headers: Vec<Entry>,
+}
+
+/// Why `Connection::push` refused to push.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum PushError {
+    /// Push is disabled: the client said so with SETTINGS_ENABLE_PUSH or is going away, or we
+    /// are the client.
+    Disabled,
+    /// The method is not safe and cacheable.
+    UnsafeMethod,
+    /// The associated stream is not one that the client opened and that we can still send on.
+    AssociatedStream,
+    /// All the even-numbered stream identifiers have been used.
+    Exhausted,
+}
+
+/// Why `Connection::send_data` refused to send.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum SendDataError {
+    /// The stream state does not permit DATA, as with the other `send_*` methods.
+    Stream(Error),
+    /// The data does not fit within the flow-control windows (see `send_capacity`). This is our
+    /// own mistake rather than the peer’s, so it is no FLOW_CONTROL_ERROR to tell the peer about.
+    Capacity,
+}
+
+impl From<Error> for SendDataError {
+    fn from(error: Error) -> SendDataError {
+        SendDataError::Stream(error)
+    }
+}
+
+/// A stream promised with PUSH_PROMISE, on which the pushed response is sent.
+///
+/// The methods are those of `Connection`, applied to the promised stream.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Push {
+    associated_stream_id: StreamId,
+    promised_stream_id: StreamId,
+}
+
+impl Push {
+    /// The stream of the request with which the push is associated.
+    pub fn associated_stream_id(&self) -> StreamId {
+        self.associated_stream_id
+    }
+
+    /// The stream on which the pushed response is sent.
+    pub fn promised_stream_id(&self) -> StreamId {
+        self.promised_stream_id
+    }
+
+    /// Send the header list of the pushed response.
+    pub fn send_headers(&self, connection: &mut Connection, headers: Vec<Entry>,
+                        end_stream: bool) -> Result<(), Error> {
+        connection.send_headers(self.promised_stream_id, headers, end_stream)
+    }
+
+    /// Send data of the pushed response.
+    pub fn send_data(&self, connection: &mut Connection, data: ByteTendril, end_stream: bool)
+            -> Result<(), SendDataError> {
+        connection.send_data(self.promised_stream_id, data, end_stream)
+    }
+
+    /// Give up on the push, resetting the promised stream with CANCEL.
+    pub fn cancel(&self, connection: &mut Connection) -> Result<(), Error> {
+        connection.send_reset(self.promised_stream_id, ErrorCode::CANCEL)
+    }
+}
+
+/// How far we have got in shutting the connection down.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Shutdown {
+    /// We haven’t started.
+    Running,
+    /// We have sent a GOAWAY with the largest possible last stream identifier and a PING, and are
+    /// waiting for the PING to be acknowledged.
+    Draining,
+    /// We have sent a GOAWAY with this last stream identifier; newer streams will be refused.
+    Closing(StreamId),
+}
+
+/// What is kept about each stream that has been used.
+struct Stream {
+    state: State,
+    /// How much DATA we may send.
+    send_window: Window,
+    /// How much DATA the peer may send.
+    receive_window: ReceiveWindow,
+}
+
+/// An HTTP/2 connection, as seen from one end.
+///
+/// Frames received are given to `receive_frame`; the events they produce can then be taken with
+/// `next_event`. Frames to be sent, whether because the application asked for them (`send_*`) or
+/// because the protocol demands them (acknowledging SETTINGS, answering PINGs, RST_STREAM and
+/// GOAWAY frames for errors), are taken with `next_frame`.
+pub struct Connection {
+    role: Role,
+    /// Our settings, as acknowledged by the peer.
+    local_settings: Values,
+    /// SETTINGS frames we have sent which have not been acknowledged yet.
+    pending_settings: VecDeque<Settings>,
+    /// The peer’s settings.
+    remote_settings: Values,
+    /// Whether the peer’s first SETTINGS frame has been received.
+    received_settings: bool,
+    /// The latest values of settings the peer has sent which we know nothing of.
+    remote_unknown_settings: HashMap<u16, u32>,
+    encoder: hpack::Encoder,
+    decoder_tables: hpack::Tables,
+    limits: hpack::Limits,
+    assembler: Assembler,
+    /// Each stream that has been used and is not yet closed.
+    streams: HashMap<StreamId, Stream>,
+    /// How the most recently closed streams were closed, up to `max_closed_streams` of them.
+    closed_streams: HashMap<StreamId, Closed>,
+    /// The streams in `closed_streams`, in the order they were closed.
+    closed_order: VecDeque<StreamId>,
+    /// The most recent ranges of stream identifiers skipped over, from the first skipped up to
+    /// the one used, up to `max_closed_streams` of them.
+    skipped_stream_ids: VecDeque<(StreamId, StreamId)>,
+    max_closed_streams: u32,
+    /// How much DATA we may send on the connection as a whole.
+    send_window: Window,
+    /// How much DATA the peer may send on the connection as a whole.
+    receive_window: ReceiveWindow,
+    /// The initial window size for streams we receive on. This follows our
+    /// SETTINGS_INITIAL_WINDOW_SIZE, except that increases apply as soon as they are sent.
+    initial_receive_window_size: u32,
+    update_policy: UpdatePolicy,
+    /// How DATA, HEADERS and PUSH_PROMISE frames that we send are padded.
+    padder: Padder,
+    /// The priorities the peer has given streams, by which we schedule the sending of data.
+    priority: Tree,
+    /// The identifiers of the streams we initiate (requests for a client, pushes for a server).
+    local_stream_ids: StreamIdAllocator,
+    /// The identifiers of the streams the peer initiates.
+    peer_stream_ids: StreamIdAllocator,
+    /// The highest-numbered stream that the peer initiated, for GOAWAY.
+    last_peer_stream_id: StreamId,
+    shutdown: Shutdown,
+    /// The last stream identifier of the peer’s latest GOAWAY, if it has sent one.
+    peer_last_stream_id: Option<StreamId>,
+    protection: Protection,
+    /// Streams the peer has reset before we were done with them, less those that closed normally.
+    rapid_resets: u32,
+    /// CONTINUATION frames received in the header block under way.
+    continuation_frames: u32,
+    /// PING and SETTINGS acknowledgments and RST_STREAM frames in `frames`.
+    pending_responses: u32,
+    /// DATA frames with no data and without END_STREAM received since the last with data.
+    empty_data_frames: u32,
+    /// Streams in the reserved (remote) state.
+    reserved_streams: u32,
+    /// Idle streams in the priority tree, which are closed when a higher identifier is used.
+    idle_streams: HashSet<StreamId>,
+    /// Set once a connection error has occurred, whereupon the connection is useless.
+    error: Option<ErrorCode>,
+    events: VecDeque<Event>,
+    frames: VecDeque<(StreamId, Payload)>,
+}
+
+impl Connection {
+    /// Constructs a new connection, which will start by sending the given SETTINGS frame.
+    ///
+    /// # Panics
+    ///
+    /// If `settings` is `Settings::Acknowledgment`.
+    pub fn new(role: Role, settings: Settings) -> Connection {
+        let mut connection = Connection {
+            role: role,
+            local_settings: Values::default(),
+            pending_settings: VecDeque::new(),
+            remote_settings: Values::default(),
+            received_settings: false,
+            remote_unknown_settings: HashMap::new(),
+            encoder: hpack::Encoder::new(),
+            decoder_tables: hpack::Tables::new(),
+            limits: hpack::Limits::default(),
+            assembler: Assembler::new(DEFAULT_MAX_HEADER_BLOCK_SIZE),
+            streams: HashMap::new(),
+            closed_streams: HashMap::new(),
+            closed_order: VecDeque::new(),
+            skipped_stream_ids: VecDeque::new(),
+            max_closed_streams: DEFAULT_MAX_CLOSED_STREAMS,
+            send_window: Window::new(DEFAULT_WINDOW_SIZE),
+            receive_window: ReceiveWindow::new(DEFAULT_WINDOW_SIZE),
+            initial_receive_window_size: DEFAULT_WINDOW_SIZE,
+            update_policy: UpdatePolicy::default(),
+            padder: Padder::new(Padding::None),
+            priority: Tree::new(),
+            local_stream_ids: match role {
+                Role::Client => StreamIdAllocator::client(),
+                Role::Server => StreamIdAllocator::server(),
+            },
+            peer_stream_ids: match role {
+                Role::Client => StreamIdAllocator::server(),
+                Role::Server => StreamIdAllocator::client(),
+            },
+            last_peer_stream_id: StreamId(0),
+            shutdown: Shutdown::Running,
+            peer_last_stream_id: None,
+            protection: Protection::default(),
+            rapid_resets: 0,
+            continuation_frames: 0,
+            pending_responses: 0,
+            empty_data_frames: 0,
+            reserved_streams: 0,
+            idle_streams: HashSet::new(),
+            error: None,
+            events: VecDeque::new(),
+            frames: VecDeque::new(),
+        };
+        connection.send_settings(settings);
+        connection
+    }
+
+    /// Constructs a connection upgraded from HTTP/1.1 with `Upgrade: h2c`. See [RFC 7540,
+    /// section 3.2][spec].
+    ///
+    /// `settings` is the SETTINGS frame to start with, as for `new`. `http2_settings` is what the
+    /// client sent in its HTTP2-Settings header field (see `upgrade::decode_settings`), which the
+    /// 101 response acknowledges: for a client, these are its own settings, in force from the
+    /// start; for a server, they are the peer’s, which it must not acknowledge again.
+    ///
+    /// > The HTTP/1.1 request that is sent prior to upgrade is assigned a
+    /// > stream identifier of 1 (see Section 5.1.1) with default priority
+    /// > values (Section 5.3.5).  Stream 1 is implicitly "half-closed" from
+    /// > the client toward the server (see Section 5.1), since the request is
+    /// > completed as an HTTP/1.1 request.
+    ///
+    /// So stream 1 starts half-closed (local) for the client and half-closed (remote) for the
+    /// server, which sends its response on it.
+    ///
+    /// # Panics
+    ///
+    /// If `settings` or `http2_settings` is `Settings::Acknowledgment`.
+    ///
+    /// [spec]: http://tools.ietf.org/html/rfc7540#section-3.2
+    pub fn upgrade(role: Role, settings: Settings, http2_settings: Settings)
+            -> Result<Connection, Error> {
+        if let Settings::Acknowledgment = http2_settings {
+            panic!("Connection::upgrade called with Settings::Acknowledgment");
+        }
+        let mut connection = Connection::new(role, settings);
+        let stream_id = StreamId(1);
+        let _ = connection.first_use(stream_id);
+        match role {
+            Role::Client => {
+                connection.apply_local_settings(&http2_settings);
+                connection.stream(stream_id).state = State::HalfClosedLocal;
+            },
+            Role::Server => {
+                try!(connection.apply_remote_settings(&http2_settings));
+                connection.stream(stream_id).state = State::HalfClosedRemote;
+                connection.last_peer_stream_id = stream_id;
+            },
+        }
+        connection.priority.insert(stream_id);
+        Ok(connection)
+    }
+
+    /// Which end of the connection we are.
+    pub fn role(&self) -> Role {
+        self.role
+    }
+
+    /// Our settings, as acknowledged by the peer.
+    pub fn local_settings(&self) -> &Values {
+        &self.local_settings
+    }
+
+    /// The peer’s settings.
+    ///
+    /// When these change (signalled by `Event::SettingsChanged`), you should apply its
+    /// `max_frame_size` to your `FrameWriter`.
+    pub fn remote_settings(&self) -> &Values {
+        &self.remote_settings
+    }
+
+    /// The value of a setting that the peer has sent but which has no place in `Values` (one
+    /// defined by an extension this doesn’t know about, say), if it has been sent.
+    pub fn remote_unknown_setting(&self, identifier: u16) -> Option<u32> {
+        self.remote_unknown_settings.get(&identifier).cloned()
+    }
+
+    /// The state of a stream.
+    ///
+    /// Idle streams with a lower identifier than one that the same end has used are implicitly
+    /// closed, being in the state `State::Closed(Closed::Skipped)`.
+    ///
+    /// Only the latest streams to close, and the latest identifiers to be skipped, are remembered
+    /// (see `set_max_closed_streams`); those before are in the state
+    /// `State::Closed(Closed::Forgotten)`, and frames that arrive on them are ignored.
+    pub fn stream_state(&self, stream_id: StreamId) -> State {
+        match self.streams.get(&stream_id) {
+            Some(stream) => stream.state,
+            None => match self.closed_streams.get(&stream_id) {
+                Some(&closed) => State::Closed(closed),
+                None if self.local_stream_ids.is_used(stream_id) ||
+                        self.peer_stream_ids.is_used(stream_id) => {
+                    let skipped = self.skipped_stream_ids.iter().any(|&(first, used)| {
+                        first <= stream_id && stream_id < used && first.0 % 2 == stream_id.0 % 2
+                    });
+                    State::Closed(if skipped { Closed::Skipped } else { Closed::Forgotten })
+                },
+                None => State::Idle,
+            },
+        }
+    }
+
+    /// The identifier to give the next stream we initiate, or `None` if they have all been used,
+    /// in which case a new connection is needed for new streams.
+    ///
+    /// For a client, this is the stream to send the next request on with `send_headers`; any
+    /// higher odd-numbered identifier may be used instead, but the streams skipped over can then
+    /// never be used. For a server, this is the stream that `push` will promise next.
+    pub fn next_stream_id(&self) -> Option<StreamId> {
+        self.local_stream_ids.peek()
+    }
+
+    /// How much DATA may be sent on a stream now, as allowed by both the stream’s and the
+    /// connection’s flow-control windows.
+    pub fn send_capacity(&self, stream_id: StreamId) -> u32 {
+        let stream_window = match self.streams.get(&stream_id) {
+            Some(stream) => stream.send_window.available(),
+            None => self.remote_settings.initial_window_size,
+        };
+        cmp::min(stream_window, self.send_window.available())
+    }
+
+    /// The stream dependency tree, as the peer has described it.
+    pub fn priority_tree(&self) -> &Tree {
+        &self.priority
+    }
+
+    /// Set whether the application has data waiting to be sent on a stream.
+    pub fn set_ready(&mut self, stream_id: StreamId, ready: bool) {
+        if !self.stream_state(stream_id).is_closed() {
+            self.priority.set_ready(stream_id, ready);
+        }
+    }
+
+    /// Choose the stream that data should be sent on next, according to the peer’s priorities,
+    /// from among those marked ready (with `set_ready`) that have some flow-control window left.
+    ///
+    /// After sending on it, ask again: `send_data` records what was sent, so that each stream
+    /// gets its fair share. This is where a large response is kept from starving a small one.
+    pub fn next_ready_stream(&self) -> Option<StreamId> {
+        self.priority.next(|stream_id| self.send_capacity(stream_id) > 0)
+    }
+
+    /// Set when WINDOW_UPDATE frames are sent for DATA received. The default is
+    /// `UpdatePolicy::HalfWindow`.
+    pub fn set_update_policy(&mut self, policy: UpdatePolicy) {
+        self.update_policy = policy;
+    }
+
+    /// Set how the frames we send are padded. The default is `Padding::None`.
+    ///
+    /// The padding of DATA frames counts against flow control, so it is limited to what the
+    /// flow-control windows allow once the data itself is accounted for; when padding is on, data
+    /// is split into frames of no more than the peer’s SETTINGS_MAX_FRAME_SIZE here rather than by
+    /// the frame writer, so that each frame gets its own padding. A header block too large for a
+    /// single frame is not padded.
+    pub fn set_padding(&mut self, padding: Padding) {
+        self.padder = Padder::new(padding);
+    }
+
+    /// Declare that the application is done with data received on a stream, so that the peer can
+    /// be allowed to send more.
+    ///
+    /// This is only for `UpdatePolicy::Manual`; otherwise, data is released as it is received.
+    pub fn release_capacity(&mut self, stream_id: StreamId, len: u32) {
+        self.receive_window.release(len);
+        if let Some(stream) = self.streams.get_mut(&stream_id) {
+            stream.receive_window.release(len);
+        }
+        self.queue_window_updates(stream_id);
+    }
+
+    /// Set the limits on decoded header lists.
+    ///
+    /// `max_header_list_size` is overridden by our SETTINGS_MAX_HEADER_LIST_SIZE whenever the
+    /// peer acknowledges a change to it. A header list exceeding a limit is a stream error of
+    /// type ENHANCE_YOUR_CALM.
+    pub fn set_header_limits(&mut self, limits: hpack::Limits) {
+        self.limits = limits;
+    }
+
+    /// Set the largest encoded header block that will be accepted.
+    ///
+    /// Anything larger is a connection error of type ENHANCE_YOUR_CALM. The default is
+    /// `DEFAULT_MAX_HEADER_BLOCK_SIZE`.
+    pub fn set_max_header_block_size(&mut self, max_size: u32) {
+        self.assembler.set_max_size(max_size);
+    }
+
+    /// Set how many closed streams are remembered, with how they were closed, and how many ranges
+    /// of skipped stream identifiers.
+    ///
+    /// Frames on a stream that is no longer remembered are ignored, whether they are legitimately
+    /// late or not; frames on one that was skipped are otherwise a connection error of type
+    /// PROTOCOL_ERROR. The default is `DEFAULT_MAX_CLOSED_STREAMS`, which should be well above
+    /// the SETTINGS_MAX_CONCURRENT_STREAMS of either end.
+    pub fn set_max_closed_streams(&mut self, max: u32) {
+        self.max_closed_streams = max;
+        while self.closed_order.len() as u32 > max {
+            if let Some(oldest) = self.closed_order.pop_front() {
+                let _ = self.closed_streams.remove(&oldest);
+            }
+        }
+        while self.skipped_stream_ids.len() as u32 > max {
+            let _ = self.skipped_stream_ids.pop_front();
+        }
+    }
+
+    /// Set the limits on abuse by the peer, beyond which it is sent a GOAWAY with
+    /// ENHANCE_YOUR_CALM. The default is `Protection::default()`.
+    pub fn set_protection(&mut self, protection: Protection) {
+        self.protection = protection;
+    }
+
+    /// Take the next event for the application.
+    pub fn next_event(&mut self) -> Option<Event> {
+        self.events.pop_front()
+    }
+
+    /// Take the next frame to be sent, with the stream it is to be sent on.
+    pub fn next_frame(&mut self) -> Option<(StreamId, Payload)> {
+        let frame = self.frames.pop_front();
+        if let Some((_, ref payload)) = frame {
+            if is_response(payload) {
+                self.pending_responses -= 1;
+            }
+        }
+        frame
+    }
+
+    /// Process a frame received from the peer.
+    ///
+    /// If this produces an error, the RST_STREAM or GOAWAY frame it calls for is queued before it
+    /// is returned. After a connection error, all that remains is to send that GOAWAY and close
+    /// the connection; any further frames received will produce the same error again.
+    pub fn receive_frame(&mut self, stream_id: StreamId, payload: Payload) -> Result<(), Error> {
+        if let Some(error_code) = self.error {
+            return Err(Error::Connection(error_code));
+        }
+        let result = self.handle_frame(stream_id, payload).and_then(|()| {
+            // The peer may be sending us frames to respond to faster than it reads the
+            // responses, or not reading them at all.
+            if self.pending_responses > self.protection.max_pending_responses {
+                Err(Error::Connection(ErrorCode::ENHANCE_YOUR_CALM))
+            } else {
+                Ok(())
+            }
+        });
+        if let Err(error) = result {
+            self.fail(error);
+        }
+        result
+    }
+
+    /// Process a frame received from the peer that could not be decoded, as reported by
+    /// `FrameReader` with `ReadError::Frame`, returning the error it amounts to.
+    ///
+    /// As with `receive_frame`, the RST_STREAM or GOAWAY frame the error calls for is queued.
+    /// Almost every such frame is a connection error, the exceptions being these two:
+    ///
+    /// > A PRIORITY frame with a length other than 5 octets MUST be treated as a stream error
+    /// > (Section 5.4.2) of type FRAME_SIZE_ERROR.
+    ///
+    /// > A receiver MUST treat the receipt of a WINDOW_UPDATE frame with an flow-control window
+    /// > increment of 0 as a stream error (Section 5.4.2) of type PROTOCOL_ERROR; errors on the
+    /// > connection flow-control window MUST be treated as a connection error (Section 5.4.1).
+    ///
+    /// A frame larger than SETTINGS_MAX_FRAME_SIZE is a connection error whatever its type, for
+    /// its payload has not been read; so is any frame in the middle of a header block or in place
+    /// of the SETTINGS frame that the peer must start with.
+    pub fn receive_invalid_frame(&mut self, header: Header<u8>, error_code: ErrorCode) -> Error {
+        if let Some(error_code) = self.error {
+            return Error::Connection(error_code);
+        }
+        let stream_id = header.stream_identifier;
+        let stream_error = stream_id != StreamId(0) &&
+            header.length <= self.local_settings.max_frame_size &&
+            self.received_settings &&
+            !self.assembler.is_pending() &&
+            (header.type_ == Priority::TYPE && error_code == ErrorCode::FRAME_SIZE_ERROR ||
+             header.type_ == WindowUpdate::TYPE && error_code == ErrorCode::PROTOCOL_ERROR);
+        let error = if stream_error {
+            Error::Stream(stream_id, error_code)
+        } else {
+            Error::Connection(error_code)
+        };
+        self.fail(error);
+        error
+    }
+
+    /// Handle an error: queue the RST_STREAM or GOAWAY frame.
+    fn fail(&mut self, error: Error) {
+        match error {
+            Error::Stream(stream_id, error_code) => {
+                let before = self.stream_state(stream_id);
+                self.stream(stream_id).state.reset_locally();
+                self.stream_changed(stream_id, before);
+                self.queue(stream_id, Payload::RstStream(RstStream { error_code: error_code }));
+            },
+            Error::Connection(error_code) => {
+                self.error = Some(error_code);
+                let last_stream_id = self.last_peer_stream_id;
+                self.queue(StreamId(0), Payload::GoAway(GoAway {
+                    last_stream_id: last_stream_id,
+                    error_code: error_code,
+                    additional_debug_data: ByteTendril::new(),
+                }));
+            },
+        }
+    }
+
+    fn handle_frame(&mut self, stream_id: StreamId, payload: Payload) -> Result<(), Error> {
+        // > That is, the connection preface starts with the string "PRI *
+        // > HTTP/2.0\r\n\r\nSM\r\n\r\n").  This sequence MUST be followed by a
+        // > SETTINGS frame (Section 6.5), which MAY be empty.
+        //
+        // > The server connection preface consists of a potentially empty
+        // > SETTINGS frame (Section 6.5) that MUST be the first frame the server
+        // > sends in the HTTP/2 connection.
+        //
+        // > Clients and servers MUST treat an invalid connection preface as a
+        // > connection error (Section 5.4.1) of type PROTOCOL_ERROR.
+        if !self.received_settings {
+            match payload {
+                Payload::Settings(Settings::Parameters { .. }) => (),
+                _ => return Err(Error::Connection(ErrorCode::PROTOCOL_ERROR)),
+            }
+        }
+        // A header block may be split into any number of CONTINUATION frames, however little
+        // each carries.
+        match payload {
+            Payload::Headers(_) | Payload::PushPromise(_) => self.continuation_frames = 0,
+            Payload::Continuation(_) => {
+                self.continuation_frames += 1;
+                if self.continuation_frames > self.protection.max_continuation_frames {
+                    return Err(Error::Connection(ErrorCode::ENHANCE_YOUR_CALM));
+                }
+            },
+            _ => (),
+        }
+        let payload = match self.assembler.push(stream_id, payload) {
+            Ok(Pushed::Other(payload)) => payload,
+            Ok(Pushed::Incomplete) => return Ok(()),
+            Ok(Pushed::Complete(block)) => return self.receive_header_block(block),
+            Err(error_code) => return Err(Error::Connection(error_code)),
+        };
+        match payload {
+            Payload::Data(frame) => {
+                // An empty DATA frame is of no use but to carry END_STREAM.
+                if frame.data.len32() > 0 {
+                    self.empty_data_frames = 0;
+                } else if !frame.end_stream {
+                    self.empty_data_frames += 1;
+                    if self.empty_data_frames > self.protection.max_empty_data_frames {
+                        return Err(Error::Connection(ErrorCode::ENHANCE_YOUR_CALM));
+                    }
+                }
+                // > The entire DATA frame payload is included in flow control, including the Pad
+                // > Length and Padding fields if present.
+                let padding = frame.pad_length.map_or(0, |pad_length| pad_length as u32 + 1);
+                let len = frame.data.len32() + padding;
+                try!(self.receive_window.receive(len).map_err(Error::Connection));
+                // > A receiver that receives a flow-controlled frame MUST always account for its
+                // > contribution against the connection flow-control window, unless the receiver
+                // > treats this as a connection error (Section 5.4.1).
+                //
+                // So whenever the data is not going to be delivered, it is released at once.
+                //
+                // The stream is done with if this closes it, so its window is seen to first.
+                let stream_window = self.streams.get_mut(&stream_id)
+                    .map_or(Ok(()), |stream| stream.receive_window.receive(len));
+                let deliver = match self.receive_on_stream(stream_id, Kind::Data,
+                                                           frame.end_stream) {
+                    Ok(deliver) => deliver,
+                    Err(error) => {
+                        self.receive_window.release(len);
+                        self.queue_window_updates(StreamId(0));
+                        return Err(error);
+                    },
+                };
+                if !deliver {
+                    self.receive_window.release(len);
+                    self.queue_window_updates(StreamId(0));
+                    return Ok(());
+                }
+                if let Err(error_code) = stream_window {
+                    self.receive_window.release(len);
+                    self.queue_window_updates(StreamId(0));
+                    return Err(Error::Stream(stream_id, error_code));
+                }
+                let released = match self.update_policy {
+                    UpdatePolicy::Manual => padding,
+                    _ => len,
+                };
+                self.receive_window.release(released);
+                if let Some(stream) = self.streams.get_mut(&stream_id) {
+                    stream.receive_window.release(released);
+                }
+                self.queue_window_updates(stream_id);
+                self.events.push_back(Event::Data {
+                    stream_id: stream_id,
+                    data: frame.data,
+                    end_stream: frame.end_stream,
+                });
+            },
+            Payload::Headers(_) | Payload::PushPromise(_) | Payload::Continuation(_) => {
+                unreachable!();
+            },
+            Payload::Priority(frame) => {
+                let _ = try!(self.receive_on_stream(stream_id, Kind::Priority, false));
+                // Streams which have closed have left the tree, and there is no use in putting
+                // them back. (Idle streams are another matter: they can be used for grouping.)
+                if !self.stream_state(stream_id).is_closed() &&
+                        !self.remote_settings.no_rfc7540_priorities {
+                    let new_idle_stream = self.stream_state(stream_id) == State::Idle &&
+                        !self.priority.contains(stream_id);
+                    try!(self.priority.reprioritise(stream_id, &frame)
+                             .map_err(|error_code| Error::Stream(stream_id, error_code)));
+                    if new_idle_stream {
+                        let _ = self.idle_streams.insert(stream_id);
+                        if self.idle_streams.len() as u32 > self.protection.max_idle_streams {
+                            return Err(Error::Connection(ErrorCode::ENHANCE_YOUR_CALM));
+                        }
+                    }
+                }
+            },
+            Payload::RstStream(frame) => {
+                // A peer which resets the streams it opens as fast as it opens them makes the
+                // application do work that no one will see.
+                match self.stream_state(stream_id) {
+                    State::Open | State::HalfClosedRemote if self.is_peer_initiated(stream_id) => {
+                        self.rapid_resets += 1;
+                        if self.rapid_resets > self.protection.max_rapid_resets {
+                            return Err(Error::Connection(ErrorCode::ENHANCE_YOUR_CALM));
+                        }
+                    },
+                    _ => (),
+                }
+                if try!(self.receive_on_stream(stream_id, Kind::RstStream, false)) {
+                    self.events.push_back(Event::Reset {
+                        stream_id: stream_id,
+                        error_code: frame.error_code,
+                    });
+                }
+            },
+            Payload::Settings(settings) => try!(self.receive_settings(settings)),
+            Payload::Ping(ping) => {
+                if ping.is_response && self.shutdown == Shutdown::Draining &&
+                        ping.data == SHUTDOWN_PING_DATA {
+                    // The peer has had a round trip to get any streams it was opening to us.
+                    let last_stream_id = self.last_peer_stream_id;
+                    self.send_goaway_frame(last_stream_id, ErrorCode::NO_ERROR,
+                                           ByteTendril::new());
+                } else if ping.is_response {
+                    self.events.push_back(Event::PingAcknowledged(ping.data));
+                } else {
+                    // > Receivers of a PING frame that does not include an ACK flag MUST send
+                    // > a PING frame with the ACK flag set in response, with an identical
+                    // > payload.  PING responses SHOULD be given higher priority than any
+                    // > other frame.
+                    //
+                    // Short of going ahead of any SETTINGS frame of ours not yet sent, though: the
+                    // first of them is our connection preface, which must come first (section
+                    // 3.5), and the peer may be expecting the others to apply to what follows.
+                    let position = self.frames.iter()
+                        .rposition(|&(_, ref payload)| match *payload {
+                            Payload::Settings(Settings::Parameters { .. }) => true,
+                            _ => false,
+                        })
+                        .map_or(0, |i| i + 1);
+                    self.frames.insert(position, (StreamId(0), Payload::Ping(Ping {
+                        is_response: true,
+                        data: ping.data,
+                    })));
+                    self.pending_responses += 1;
+                }
+            },
+            Payload::GoAway(frame) => {
+                // > Receivers of a GOAWAY frame MUST NOT open additional streams on the
+                // > connection, although a new connection can be established for new streams.
+                let last_stream_id = match self.peer_last_stream_id {
+                    Some(previous) => cmp::min(previous, frame.last_stream_id),
+                    None => frame.last_stream_id,
+                };
+                self.peer_last_stream_id = Some(last_stream_id);
+                let mut unprocessed = self.streams.iter()
+                    .filter(|&(&id, stream)| {
+                        id > last_stream_id && !self.is_peer_initiated(id) &&
+                            stream.state != State::Idle && !stream.state.is_closed()
+                    })
+                    .map(|(&id, _)| id)
+                    .collect::<Vec<_>>();
+                unprocessed.sort();
+                for &id in &unprocessed {
+                    let before = self.stream_state(id);
+                    self.stream(id).state = State::Closed(Closed::ResetRemotely);
+                    self.stream_changed(id, before);
+                }
+                self.events.push_back(Event::GoAway {
+                    last_stream_id: frame.last_stream_id,
+                    error_code: frame.error_code,
+                    additional_debug_data: frame.additional_debug_data,
+                    unprocessed: unprocessed,
+                });
+            },
+            Payload::WindowUpdate(frame) => {
+                let increment = frame.window_size_increment;
+                if stream_id.0 == 0 {
+                    try!(self.send_window.increase(increment).map_err(Error::Connection));
+                } else if try!(self.receive_on_stream(stream_id, Kind::WindowUpdate, false)) {
+                    try!(self.stream(stream_id).send_window.increase(increment)
+                             .map_err(|error_code| Error::Stream(stream_id, error_code)));
+                } else {
+                    return Ok(());
+                }
+                self.events.push_back(Event::WindowUpdate {
+                    stream_id: stream_id,
+                    window_size_increment: increment,
+                });
+            },
+            Payload::PriorityUpdate(frame) => {
+                // > Servers MUST NOT send PRIORITY_UPDATE frames.  If a client receives a
+                // > PRIORITY_UPDATE frame, it MUST respond with a connection error of type
+                // > PROTOCOL_ERROR.
+                if self.role == Role::Client {
+                    return Err(Error::Connection(ErrorCode::PROTOCOL_ERROR));
+                }
+                // > Servers can discard frames where the Prioritized Stream ID refers to a stream
+                // > in the "half-closed (local)" or "closed" state.
+                let stream_id = frame.prioritized_stream_id;
+                match self.stream_state(stream_id) {
+                    State::HalfClosedLocal | State::Closed(_) => (),
+                    _ => self.events.push_back(Event::PriorityUpdate {
+                        stream_id: stream_id,
+                        priority_field_value: frame.priority_field_value,
+                    }),
+                }
+            },
+            Payload::AltSvc(frame) => {
+                // > The ALTSVC frame is intended for receipt by clients.  A device acting as a
+                // > server MUST ignore it.
+                if self.role == Role::Client && frame.is_valid_on(stream_id) {
+                    self.events.push_back(Event::AltSvc {
+                        stream_id: stream_id,
+                        origin: frame.origin,
+                        field_value: frame.field_value,
+                    });
+                }
+            },
+            Payload::Origin(frame) => {
+                // > The ORIGIN frame MUST be sent on stream 0; an ORIGIN frame on any other stream
+                // > is invalid and MUST be ignored.
+                // The origin set is only of interest to clients, too.
+                if self.role == Role::Client && stream_id.0 == 0 {
+                    self.events.push_back(Event::Origin(frame.origins));
+                }
+            },
+            Payload::Extension(frame) => {
+                self.events.push_back(Event::Extension {
+                    stream_id: stream_id,
+                    frame: frame,
+                });
+            },
+            // > Implementations MUST ignore and discard any frame that has a type that is unknown.
+            Payload::Unknown(_) => (),
+        }
+        Ok(())
+    }
+
+    fn receive_settings(&mut self, settings: Settings) -> Result<(), Error> {
+        match settings {
+            Settings::Acknowledgment => {
+                // An acknowledgment of nothing is not mentioned by the spec; ignoring it seems
+                // the most reasonable thing to do.
+                if let Some(settings) = self.pending_settings.pop_front() {
+                    self.apply_local_settings(&settings);
+                    self.events.push_back(Event::SettingsAcknowledged(self.local_settings));
+                }
+            },
+            Settings::Parameters { .. } => {
+                try!(self.apply_remote_settings(&settings));
+                self.received_settings = true;
+                // > Upon receiving the SETTINGS frame, the recipient MUST
+                // > immediately emit a SETTINGS frame with the ACK flag set.
+                self.queue(StreamId(0), Payload::Settings(Settings::Acknowledgment));
+                self.events.push_back(Event::SettingsChanged(self.remote_settings));
+            },
+        }
+        Ok(())
+    }
+
+    /// Put our settings into force, now that the peer has acknowledged them.
+    fn apply_local_settings(&mut self, settings: &Settings) {
+        self.local_settings.apply(settings);
+        if let Settings::Parameters { header_table_size, max_header_list_size,
+                                      initial_window_size, .. } = *settings {
+            if let Some(size) = header_table_size {
+                self.decoder_tables.set_protocol_max_size(size);
+            }
+            if let Some(size) = max_header_list_size {
+                self.limits.max_header_list_size = Some(size);
+            }
+            // Increases were applied when the settings were sent.
+            if let Some(size) = initial_window_size {
+                if size < self.initial_receive_window_size {
+                    self.set_initial_receive_window_size(size);
+                }
+            }
+        }
+    }
+
+    /// Put the peer’s settings into force.
+    fn apply_remote_settings(&mut self, settings: &Settings) -> Result<(), Error> {
+        match *settings {
+            Settings::Acknowledgment => (),
+            Settings::Parameters { header_table_size, initial_window_size,
+                                   enable_connect_protocol, no_rfc7540_priorities,
+                                   ref unknown, .. } => {
+                // > A sender MUST NOT send a SETTINGS_ENABLE_CONNECT_PROTOCOL parameter with the
+                // > value of 0 after previously sending a value of 1.
+                if enable_connect_protocol == Some(false) &&
+                        self.remote_settings.enable_connect_protocol {
+                    return Err(Error::Connection(ErrorCode::PROTOCOL_ERROR));
+                }
+                // > Senders MUST NOT change the SETTINGS_NO_RFC7540_PRIORITIES value after the
+                // > first SETTINGS frame.  Receivers that detect a change MAY treat it as a
+                // > connection error of type PROTOCOL_ERROR.
+                if let Some(value) = no_rfc7540_priorities {
+                    if self.received_settings &&
+                            value != self.remote_settings.no_rfc7540_priorities {
+                        return Err(Error::Connection(ErrorCode::PROTOCOL_ERROR));
+                    }
+                }
+                for &(identifier, value) in unknown {
+                    let _ = self.remote_unknown_settings.insert(identifier, value);
+                }
+                if let Some(size) = initial_window_size {
+                    // > When the value of SETTINGS_INITIAL_WINDOW_SIZE changes, a receiver MUST
+                    // > adjust the size of all stream flow-control windows that it maintains by
+                    // > the difference between the new value and the old value.
+                    //
+                    // > An endpoint MUST treat a change to SETTINGS_INITIAL_WINDOW_SIZE that
+                    // > causes any flow-control window to exceed the maximum size as a
+                    // > connection error (Section 5.4.1) of type FLOW_CONTROL_ERROR.
+                    let delta = size as i64 - self.remote_settings.initial_window_size as i64;
+                    for stream in self.streams.values_mut() {
+                        try!(stream.send_window.adjust(delta).map_err(Error::Connection));
+                    }
+                }
+                self.remote_settings.apply(settings);
+                if let Some(size) = header_table_size {
+                    self.encoder.set_protocol_max_size(size);
+                }
+            },
+        }
+        Ok(())
+    }
+
+    /// Change the size of the windows of streams we receive on, now and in the future.
+    fn set_initial_receive_window_size(&mut self, size: u32) {
+        for stream in self.streams.values_mut() {
+            // A window that would be pushed beyond the limits just stays as it is; the peer
+            // can only send less than it otherwise might.
+            let _ = stream.receive_window.set_size(size);
+        }
+        self.initial_receive_window_size = size;
+    }
+
+    /// Queue any WINDOW_UPDATE frames that the update policy calls for, for the connection and
+    /// for a stream (unless that is stream 0).
+    fn queue_window_updates(&mut self, stream_id: StreamId) {
+        let policy = self.update_policy;
+        if let Some(increment) = self.receive_window.take_update(policy) {
+            self.queue(StreamId(0), Payload::WindowUpdate(WindowUpdate {
+                window_size_increment: increment,
+            }));
+        }
+        let increment = match self.streams.get_mut(&stream_id) {
+            // There’s no point in letting the peer send more if it has finished sending.
+            Some(ref mut stream) if stream.state == State::Open ||
+                                    stream.state == State::HalfClosedLocal => {
+                stream.receive_window.take_update(policy)
+            },
+            _ => None,
+        };
+        if let Some(increment) = increment {
+            self.queue(stream_id, Payload::WindowUpdate(WindowUpdate {
+                window_size_increment: increment,
+            }));
+        }
+    }
+
+    fn receive_header_block(&mut self, block: HeaderBlock) -> Result<(), Error> {
+        let stream_id = block.stream_id();
+        // > If a HEADERS frame is received whose stream identifier field is 0x0, the
+        // > recipient MUST respond with a connection error (Section 5.4.1) of type
+        // > PROTOCOL_ERROR.
+        //
+        // (The same goes for PUSH_PROMISE.)
+        if stream_id.0 == 0 {
+            return Err(Error::Connection(ErrorCode::PROTOCOL_ERROR));
+        }
+        match block {
+            HeaderBlock::Headers(_, frame) => {
+                let refused = match self.shutdown {
+                    Shutdown::Closing(last_stream_id) => {
+                        self.is_peer_initiated(stream_id) && stream_id > last_stream_id
+                    },
+                    _ => false,
+                };
+                if !refused && self.is_peer_initiated(stream_id) &&
+                        stream_id > self.last_peer_stream_id {
+                    self.last_peer_stream_id = stream_id;
+                }
+                // The block must be decoded whatever else happens, to keep the decoding context
+                // in sync; but a decoding error trumps anything else.
+                let decoded = self.decode_header_block(stream_id, frame.header_block);
+                if let Err(error @ Error::Connection(_)) = decoded {
+                    return Err(error);
+                }
+                // > After sending a GOAWAY frame, the sender can discard frames for streams
+                // > initiated by the receiver with identifiers higher than the identified last
+                // > stream.
+                //
+                // Rather than leave the peer hanging, we refuse them; then it knows for sure
+                // that they can be retried elsewhere.
+                if refused {
+                    // The identifier is used up all the same.
+                    if self.stream_state(stream_id) == State::Idle {
+                        let _ = self.first_use(stream_id);
+                    }
+                    return Err(Error::Stream(stream_id, ErrorCode::REFUSED_STREAM));
+                }
+                let deliver = try!(self.receive_on_stream(stream_id, Kind::Headers,
+                                                          frame.end_stream));
+                let headers = try!(decoded);
+                if deliver {
+                    match frame.priority {
+                        // The peer said it wouldn’t be using these, so pay them no heed.
+                        Some(_) if self.remote_settings.no_rfc7540_priorities => {
+                            self.priority.insert(stream_id);
+                        },
+                        Some(ref priority) => {
+                            try!(self.priority.reprioritise(stream_id, priority)
+                                     .map_err(|error_code| Error::Stream(stream_id, error_code)));
+                        },
+                        None => self.priority.insert(stream_id),
+                    }
+                    self.events.push_back(Event::Headers {
+                        stream_id: stream_id,
+                        priority: frame.priority,
+                        headers: headers,
+                        end_stream: frame.end_stream,
+                    });
+                }
+            },
+            HeaderBlock::PushPromise(_, frame) => {
+                // > A client cannot push.  Thus, servers MUST treat the receipt of a
+                // > PUSH_PROMISE frame as a connection error (Section 5.4.1) of type
+                // > PROTOCOL_ERROR.
+                //
+                // > PUSH_PROMISE MUST NOT be sent if the SETTINGS_ENABLE_PUSH setting of the
+                // > peer endpoint is set to 0.  An endpoint that has set this setting and has
+                // > received acknowledgement MUST treat the receipt of a PUSH_PROMISE frame as
+                // > a connection error (Section 5.4.1) of type PROTOCOL_ERROR.
+                if self.role == Role::Server || !self.local_settings.enable_push {
+                    return Err(Error::Connection(ErrorCode::PROTOCOL_ERROR));
+                }
+                let promised_stream_id = frame.promised_stream_id;
+                if !self.is_peer_initiated(promised_stream_id) ||
+                        self.stream_state(promised_stream_id) == State::Idle &&
+                        !self.first_use(promised_stream_id) {
+                    return Err(Error::Connection(ErrorCode::PROTOCOL_ERROR));
+                }
+                if promised_stream_id > self.last_peer_stream_id {
+                    self.last_peer_stream_id = promised_stream_id;
+                }
+                let decoded = self.decode_header_block(promised_stream_id, frame.header_block);
+                if let Err(error @ Error::Connection(_)) = decoded {
+                    return Err(error);
+                }
+                let deliver = try!(self.receive_on_stream(stream_id, Kind::PushPromise, false));
+                try!(self.stream(promised_stream_id).state.reserve_remote());
+                self.stream_changed(promised_stream_id, State::Idle);
+                if self.reserved_streams > self.protection.max_reserved_streams {
+                    return Err(Error::Connection(ErrorCode::ENHANCE_YOUR_CALM));
+                }
+                let headers = try!(decoded);
+                if deliver {
+                    self.events.push_back(Event::PushPromise {
+                        stream_id: stream_id,
+                        promised_stream_id: promised_stream_id,
+                        headers: headers,
+                    });
+                } else {
+                    // We reset the associated stream, so we don’t want the push either.
+                    self.stream(promised_stream_id).state.reset_locally();
+                    self.stream_changed(promised_stream_id, State::ReservedRemote);
+                    self.queue(promised_stream_id, Payload::RstStream(RstStream {
+                        error_code: ErrorCode::CANCEL,
+                    }));
+                }
+            },
+        }
+        Ok(())
+    }
+
+    /// Decode a complete header block for the given stream.
+    ///
+    /// Even if the header list is going to be rejected, the whole block is decoded so that the
+    /// decoding context stays in sync with the peer’s.
+    fn decode_header_block(&mut self, stream_id: StreamId, block: Fragment)
+            -> Result<Vec<Entry>, Error> {
+        let mut headers = vec![];
+        let decoder = hpack::Decoder::with_limits(block.into_tendril(), &mut self.decoder_tables,
+                                                  self.limits);
+        for entry in decoder {
+            match entry {
+                Ok(entry) => headers.push(entry),
+                // > A receiver MUST terminate the connection with a connection error (Section
+                // > 5.4.1) of type COMPRESSION_ERROR if it does not decompress a header block.
+                Err(hpack::ExecuteError::Decode(_)) => {
+                    return Err(Error::Connection(ErrorCode::COMPRESSION_ERROR));
+                },
+                Err(hpack::ExecuteError::LimitExceeded(_)) => {
+                    return Err(Error::Stream(stream_id, ErrorCode::ENHANCE_YOUR_CALM));
+                },
+            }
+        }
+        Ok(headers)
+    }
+
+    fn stream(&mut self, stream_id: StreamId) -> &mut Stream {
+        let state = self.stream_state(stream_id);
+        let send_window = Window::new(self.remote_settings.initial_window_size);
+        let receive_window = ReceiveWindow::new(self.initial_receive_window_size);
+        self.streams.entry(stream_id).or_insert(Stream {
+            state: state,
+            send_window: send_window,
+            receive_window: receive_window,
+        })
+    }
+
+    /// Apply a frame received to the stream’s state, returning whether to act upon it.
+    fn receive_on_stream(&mut self, stream_id: StreamId, kind: Kind, end_stream: bool)
+            -> Result<bool, Error> {
+        // Only clients open streams with HEADERS (servers reserve them with PUSH_PROMISE), and
+        // only odd-numbered ones, in increasing order.
+        if kind == Kind::Headers && self.stream_state(stream_id) == State::Idle &&
+                !(self.role == Role::Server && stream_id.0 % 2 == 1 &&
+                  self.first_use(stream_id)) {
+            return Err(Error::Connection(ErrorCode::PROTOCOL_ERROR));
+        }
+        // PRIORITY is allowed in every state and changes none, so it needs no record of the
+        // stream; an idle stream with a record would not be closed when it is skipped.
+        if kind == Kind::Priority {
+            return Ok(true);
+        }
+        let before = self.stream_state(stream_id);
+        let result = self.stream(stream_id).state.receive(stream_id, kind, end_stream);
+        self.stream_changed(stream_id, before);
+        result
+    }
+
+    /// Apply a frame to be sent to the stream’s state, failing if it may not be sent.
+    fn send_on_stream(&mut self, stream_id: StreamId, kind: Kind, end_stream: bool)
+            -> Result<(), Error> {
+        if stream_id.0 == 0 {
+            return Err(Error::Connection(ErrorCode::PROTOCOL_ERROR));
+        }
+        // As for `receive_on_stream`.
+        if kind == Kind::Priority {
+            return Ok(());
+        }
+        if kind == Kind::Headers && self.peer_last_stream_id.is_some() &&
+                self.stream_state(stream_id) == State::Idle {
+            return Err(Error::Stream(stream_id, ErrorCode::REFUSED_STREAM));
+        }
+        if kind == Kind::Headers && self.stream_state(stream_id) == State::Idle &&
+                !(self.role == Role::Client && stream_id.0 % 2 == 1 &&
+                  self.first_use(stream_id)) {
+            return Err(Error::Stream(stream_id, ErrorCode::PROTOCOL_ERROR));
+        }
+        let before = self.stream_state(stream_id);
+        let result = self.stream(stream_id).state.send(stream_id, kind, end_stream);
+        self.stream_changed(stream_id, before);
+        result
+    }
+
+    /// Bring the priority tree and the counts kept for `Protection` up to date with a change in
+    /// a stream’s state, and let the stream go if it has closed.
+    fn stream_changed(&mut self, stream_id: StreamId, before: State) {
+        let after = self.stream_state(stream_id);
+        if let State::Closed(closed) = after {
+            self.forget_stream(stream_id, closed);
+        }
+        if after == before {
+            return;
+        }
+        if before == State::Idle {
+            let _ = self.idle_streams.remove(&stream_id);
+        }
+        if before == State::ReservedRemote {
+            self.reserved_streams -= 1;
+        }
+        if after == State::ReservedRemote {
+            self.reserved_streams += 1;
+        }
+        if after.is_closed() {
+            self.priority.remove(stream_id);
+            // The peer let us finish, which makes up for a stream it reset.
+            match after {
+                State::Closed(Closed::ReceivedEndStream) | State::Closed(Closed::SentEndStream)
+                        if self.is_peer_initiated(stream_id) => {
+                    self.rapid_resets = self.rapid_resets.saturating_sub(1);
+                },
+                _ => (),
+            }
+        }
+    }
+
+    /// Let a closed stream go, remembering how it was closed for as long as `max_closed_streams`
+    /// allows.
+    fn forget_stream(&mut self, stream_id: StreamId, closed: Closed) {
+        let _ = self.streams.remove(&stream_id);
+        // `stream_state` knows skipped and forgotten streams without being told.
+        if closed == Closed::Skipped || closed == Closed::Forgotten {
+            return;
+        }
+        if self.closed_streams.insert(stream_id, closed).is_none() {
+            self.closed_order.push_back(stream_id);
+            if self.closed_order.len() as u32 > self.max_closed_streams {
+                if let Some(oldest) = self.closed_order.pop_front() {
+                    let _ = self.closed_streams.remove(&oldest);
+                }
+            }
+        }
+    }
+
+    /// Record the first use of a stream identifier, by either end, returning false if it may not
+    /// be used: if it is stream 0, or a lower identifier than the same end has already used.
+    ///
+    /// > The first use of a new stream identifier implicitly closes all streams in the "idle"
+    /// > state that might have been initiated by that peer with a lower-valued stream
+    /// > identifier.
+    ///
+    /// `stream_state` takes care of their state; this takes those that have been given a priority
+    /// out of the priority tree.
+    fn first_use(&mut self, stream_id: StreamId) -> bool {
+        // Once the identifier is claimed, `stream_state` would take the stream for a skipped one
+        // if it were not already known.
+        let _ = self.stream(stream_id);
+        let (claimed, first_skipped) = {
+            let ids = if self.is_peer_initiated(stream_id) {
+                &mut self.peer_stream_ids
+            } else {
+                &mut self.local_stream_ids
+            };
+            let first_skipped = match ids.peek() {
+                Some(next) if next != stream_id => Some(next),
+                _ => None,
+            };
+            (ids.claim(stream_id), first_skipped)
+        };
+        if let (true, Some(first_skipped)) = (claimed, first_skipped) {
+            self.skipped_stream_ids.push_back((first_skipped, stream_id));
+            if self.skipped_stream_ids.len() as u32 > self.max_closed_streams {
+                let _ = self.skipped_stream_ids.pop_front();
+            }
+            let skipped = self.idle_streams.iter()
+                .filter(|&&id| id < stream_id && id.0 % 2 == stream_id.0 % 2)
+                .cloned()
+                .collect::<Vec<_>>();
+            for id in skipped {
+                self.stream_changed(id, State::Idle);
+            }
+        }
+        claimed
+    }
+
+    fn is_peer_initiated(&self, stream_id: StreamId) -> bool {
+        match self.role {
+            Role::Client => stream_id.0 % 2 == 0,
+            Role::Server => stream_id.0 % 2 == 1,
+        }
+    }
+
+    fn queue(&mut self, stream_id: StreamId, payload: Payload) {
+        if is_response(&payload) {
+            self.pending_responses += 1;
+        }
+        self.frames.push_back((stream_id, payload));
+    }
+
+    /// Send a header list on a stream.
+    ///
+    /// This and the other `send_*` methods for stream-level frames fail if the stream state does
+    /// not permit the frame (RFC 7540, section 5.1); in that case nothing is sent.
+    pub fn send_headers(&mut self, stream_id: StreamId, headers: Vec<Entry>, end_stream: bool)
+            -> Result<(), Error> {
+        try!(self.send_on_stream(stream_id, Kind::Headers, end_stream));
+        let header_block = self.encoder.encode_fragment(headers);
+        let pad_length = self.header_block_padding(&header_block, 0);
+        self.queue(stream_id, Payload::Headers(Headers {
+            pad_length: pad_length,
+            end_stream: end_stream,
+            end_headers: true,
+            priority: None,
+            header_block: header_block,
+        }));
+        Ok(())
+    }
+
+    /// Send data on a stream.
+    ///
+    /// The data must fit within the flow-control windows (see `send_capacity`); if it doesn’t,
+    /// this fails with `SendDataError::Capacity` and nothing is sent. Any padding only takes what
+    /// room the windows have left over.
+    pub fn send_data(&mut self, stream_id: StreamId, mut data: ByteTendril, end_stream: bool)
+            -> Result<(), SendDataError> {
+        let mut capacity = self.send_capacity(stream_id);
+        if data.len32() > capacity {
+            return Err(SendDataError::Capacity);
+        }
+        try!(self.send_on_stream(stream_id, Kind::Data, end_stream));
+        let max_frame_size = match self.padder.padding() {
+            Padding::None => data.len32(),
+            _ => self.remote_settings.max_frame_size,
+        };
+        loop {
+            let chunk = if data.len32() <= max_frame_size {
+                mem::replace(&mut data, ByteTendril::new())
+            } else {
+                let chunk = data.subtendril(0, max_frame_size);
+                data.pop_front(max_frame_size);
+                chunk
+            };
+            let last = data.len32() == 0;
+            // > The entire DATA frame payload is included in flow control, including the Pad
+            // > Length and Padding fields if present.
+            let room = cmp::min(max_frame_size, capacity - data.len32());
+            let pad_length = self.padder.pad_length(chunk.len32(), room);
+            let len = chunk.len32() + pad_length.map_or(0, |pad_length| pad_length as u32 + 1);
+            capacity -= len;
+            self.send_window.consume(len).unwrap();
+            // (Unless END_STREAM has closed the stream, and it is done with.)
+            if let Some(stream) = self.streams.get_mut(&stream_id) {
+                stream.send_window.consume(len).unwrap();
+            }
+            self.priority.sent(stream_id, len);
+            self.queue(stream_id, Payload::Data(Data {
+                pad_length: pad_length,
+                end_stream: end_stream && last,
+                data: chunk,
+            }));
+            if last {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Choose the padding for a HEADERS or PUSH_PROMISE frame, which has `prefix_len` octets
+    /// before the header block.
+    fn header_block_padding(&mut self, header_block: &Fragment, prefix_len: u32) -> Option<u8> {
+        let len = prefix_len + header_block.encoded_len() as u32;
+        self.padder.pad_length(len, self.remote_settings.max_frame_size)
+    }
+
+    /// Tell the peer how we would like it to prioritise a stream.
+    pub fn send_priority(&mut self, stream_id: StreamId, priority: Priority)
+            -> Result<(), Error> {
+        if priority.stream_dependency == stream_id {
+            return Err(Error::Stream(stream_id, ErrorCode::PROTOCOL_ERROR));
+        }
+        try!(self.send_on_stream(stream_id, Kind::Priority, false));
+        self.queue(stream_id, Payload::Priority(priority));
+        Ok(())
+    }
+
+    /// Change the priority of a request, with a PRIORITY_UPDATE frame (RFC 9218).
+    ///
+    /// The value is in the format of the `Priority` header field. Only clients may send this.
+    pub fn send_priority_update(&mut self, stream_id: StreamId, priority_field_value: ByteTendril)
+            -> Result<(), Error> {
+        if self.role == Role::Server || stream_id.0 == 0 {
+            return Err(Error::Stream(stream_id, ErrorCode::PROTOCOL_ERROR));
+        }
+        self.queue(StreamId(0), Payload::PriorityUpdate(PriorityUpdate {
+            prioritized_stream_id: stream_id,
+            priority_field_value: priority_field_value,
+        }));
+        Ok(())
+    }
+
+    /// Advertise alternative services with an ALTSVC frame (RFC 7838).
+    ///
+    /// On stream 0, `origin` says which origin the services are for; on any other stream it must be
+    /// empty, the services being for the origin of the request. Only servers may send this.
+    pub fn send_altsvc(&mut self, stream_id: StreamId, origin: ByteTendril,
+                       field_value: ByteTendril) -> Result<(), Error> {
+        let frame = AltSvc {
+            origin: origin,
+            field_value: field_value,
+        };
+        if self.role == Role::Client || !frame.is_valid_on(stream_id) {
+            return Err(Error::Stream(stream_id, ErrorCode::PROTOCOL_ERROR));
+        }
+        self.queue(stream_id, Payload::AltSvc(frame));
+        Ok(())
+    }
+
+    /// Send the origins we are authoritative for with an ORIGIN frame (RFC 8336).
+    ///
+    /// Only servers may send this.
+    pub fn send_origin(&mut self, origins: Vec<ByteTendril>) -> Result<(), Error> {
+        if self.role == Role::Client {
+            return Err(Error::Stream(StreamId(0), ErrorCode::PROTOCOL_ERROR));
+        }
+        self.queue(StreamId(0), Payload::Origin(Origin { origins: origins }));
+        Ok(())
+    }
+
+    /// Push a response, promising it with a PUSH_PROMISE frame on the associated stream.
+    ///
+    /// The associated stream is that of a request from the client which is still open for us to
+    /// send on; the request is what the client would have sent to get the response being
+    /// pushed. The pushed response is then sent with the returned `Push`.
+    ///
+    /// > Promised requests MUST be cacheable (see [RFC7231], Section 4.2.3),
+    /// > MUST be safe (see [RFC7231], Section 4.2.1), and MUST NOT include a
+    /// > request body.
+    ///
+    /// Of the methods defined for HTTP, only `GET` and `HEAD` are both safe and cacheable.
+    pub fn push(&mut self, stream_id: StreamId, request: PushRequest) -> Result<Push, PushError> {
+        // > PUSH_PROMISE MUST NOT be sent if the SETTINGS_ENABLE_PUSH setting of the peer
+        // > endpoint is set to 0.
+        if self.role == Role::Client || !self.remote_settings.enable_push ||
+                self.peer_last_stream_id.is_some() {
+            return Err(PushError::Disabled);
+        }
+        if &*request.method != b"GET" && &*request.method != b"HEAD" {
+            return Err(PushError::UnsafeMethod);
+        }
+        // > PUSH_PROMISE frames MUST only be sent on a peer-initiated stream that is in either
+        // > the "open" or "half-closed (remote)" state.
+        match self.stream_state(stream_id) {
+            State::Open | State::HalfClosedRemote if self.is_peer_initiated(stream_id) => (),
+            _ => return Err(PushError::AssociatedStream),
+        }
+        let promised_stream_id = match self.local_stream_ids.peek() {
+            Some(promised_stream_id) => promised_stream_id,
+            None => return Err(PushError::Exhausted),
+        };
+        let _ = self.first_use(promised_stream_id);
+        self.send_on_stream(stream_id, Kind::PushPromise, false).unwrap();
+        self.stream(promised_stream_id).state.reserve_local().unwrap();
+        self.stream_changed(promised_stream_id, State::Idle);
+        self.depend_on_associated_stream(promised_stream_id, stream_id);
+
+        let mut headers = Vec::with_capacity(4 + request.headers.len());
+        headers.push(Entry { name: b":method".to_tendril(), value: request.method });
+        headers.push(Entry { name: b":scheme".to_tendril(), value: request.scheme });
+        headers.push(Entry { name: b":authority".to_tendril(), value: request.authority });
+        headers.push(Entry { name: b":path".to_tendril(), value: request.path });
+        headers.extend(request.headers);
+        let header_block = self.encoder.encode_fragment(headers);
+        // The promised stream identifier takes four octets.
+        let pad_length = self.header_block_padding(&header_block, 4);
+        self.queue(stream_id, Payload::PushPromise(PushPromise {
+            pad_length: pad_length,
+            end_headers: true,
+            promised_stream_id: promised_stream_id,
+            header_block: header_block,
+        }));
+        Ok(Push {
+            associated_stream_id: stream_id,
+            promised_stream_id: promised_stream_id,
+        })
+    }
+
+    /// > Pushed streams (Section 8.2) initially depend on their associated
+    /// > stream.  In both cases, streams are assigned a default weight of 16.
+    fn depend_on_associated_stream(&mut self, promised_stream_id: StreamId,
+                                   stream_id: StreamId) {
+        self.priority.reprioritise(promised_stream_id, &Priority {
+            exclusive: false,
+            stream_dependency: stream_id,
+            weight: DEFAULT_WEIGHT,
+        }).unwrap();
+    }
+
+    /// Reset a stream.
+    pub fn send_reset(&mut self, stream_id: StreamId, error_code: ErrorCode)
+            -> Result<(), Error> {
+        try!(self.send_on_stream(stream_id, Kind::RstStream, false));
+        self.queue(stream_id, Payload::RstStream(RstStream { error_code: error_code }));
+        Ok(())
+    }
+
+    /// Change our settings. They take effect when the peer acknowledges them.
+    ///
+    /// # Panics
+    ///
+    /// If `settings` is `Settings::Acknowledgment`; acknowledgments are sent automatically.
+    pub fn send_settings(&mut self, settings: Settings) {
+        if let Settings::Acknowledgment = settings {
+            panic!("Connection::send_settings called with Settings::Acknowledgment");
+        }
+        if let Settings::Parameters { initial_window_size: Some(size), .. } = settings {
+            // The peer may use a larger window as soon as it receives this, before we get its
+            // acknowledgment; a smaller one has to wait for that.
+            if size > self.initial_receive_window_size {
+                self.set_initial_receive_window_size(size);
+            }
+        }
+        self.pending_settings.push_back(settings.clone());
+        self.queue(StreamId(0), Payload::Settings(settings));
+    }
+
+    /// Send a PING. The response will appear as `Event::PingAcknowledged`.
+    ///
+    /// To keep an idle connection alive and notice when it has died, see `keepalive::Keepalive`.
+    pub fn send_ping(&mut self, data: [u8; 8]) {
+        self.queue(StreamId(0), Payload::Ping(Ping {
+            is_response: false,
+            data: data,
+        }));
+    }
+
+    /// Tell the peer that the connection is being shut down, straight away.
+    ///
+    /// The last stream identifier is that of the latest stream the peer has opened. Those streams
+    /// carry on as usual, but any that the peer opens after this are refused. For a graceful
+    /// shutdown, which doesn’t catch streams that are on their way, see `shut_down`.
+    pub fn send_goaway(&mut self, error_code: ErrorCode, additional_debug_data: ByteTendril) {
+        let last_stream_id = self.last_peer_stream_id;
+        self.send_goaway_frame(last_stream_id, error_code, additional_debug_data);
+    }
+
+    fn send_goaway_frame(&mut self, last_stream_id: StreamId, error_code: ErrorCode,
+                         additional_debug_data: ByteTendril) {
+        self.shutdown = Shutdown::Closing(last_stream_id);
+        self.queue(StreamId(0), Payload::GoAway(GoAway {
+            last_stream_id: last_stream_id,
+            error_code: error_code,
+            additional_debug_data: additional_debug_data,
+        }));
+    }
+
+    /// Start shutting the connection down gracefully. See [RFC 7540, section 6.8][spec].
+    ///
+    /// > A server that is attempting to gracefully shut down a
+    /// > connection SHOULD send an initial GOAWAY frame with the last stream
+    /// > identifier set to 2^31-1 and a NO_ERROR code.  This signals to the
+    /// > client that a shutdown is imminent and that initiating further
+    /// > requests is prohibited.  After allowing time for any in-flight stream
+    /// > creation (at least one round-trip time), the server can send another
+    /// > GOAWAY frame with an updated last stream identifier.
+    ///
+    /// The round trip is timed with a PING (carrying `SHUTDOWN_PING_DATA`, its acknowledgment
+    /// not producing an event); when it comes back, the second GOAWAY is sent. After that, as
+    /// with `send_goaway`, the streams up to the last stream identifier carry on and any newer
+    /// ones are refused with REFUSED_STREAM. When `is_shut_down` says so, the connection can be
+    /// closed.
+    ///
+    /// This does nothing if the connection is already being shut down.
+    ///
+    /// [spec]: http://tools.ietf.org/html/rfc7540#section-6.8
+    pub fn shut_down(&mut self) {
+        if self.shutdown != Shutdown::Running {
+            return;
+        }
+        self.shutdown = Shutdown::Draining;
+        self.queue(StreamId(0), Payload::GoAway(GoAway {
+            last_stream_id: StreamId(0x7fffffff),
+            error_code: ErrorCode::NO_ERROR,
+            additional_debug_data: ByteTendril::new(),
+        }));
+        self.send_ping(SHUTDOWN_PING_DATA);
+    }
+
+    /// Whether we have sent the final GOAWAY and all the streams it left open are now closed,
+    /// so that there is nothing left to do but close the connection once the frames queued are
+    /// sent.
+    pub fn is_shut_down(&self) -> bool {
+        match self.shutdown {
+            Shutdown::Closing(_) => self.streams.values().all(|stream| {
+                stream.state == State::Idle || stream.state.is_closed()
+            }),
+            _ => false,
+        }
+    }
+}
+
+/// Whether a frame is of a kind that the peer can make us send at will, for
+/// `Protection::max_pending_responses`.
+fn is_response(payload: &Payload) -> bool {
+    match *payload {
+        Payload::Ping(ref ping) => ping.is_response,
+        Payload::Settings(Settings::Acknowledgment) | Payload::RstStream(_) => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Connection, Event, PushError, PushRequest, Role};
+    use super::SendDataError;
+    use super::SHUTDOWN_PING_DATA;
+    use http2::Error;
+    use http2::frame::{ErrorCode, Header, Payload};
+    use http2::frame::altsvc::AltSvc;
+    use http2::frame::codec::{FrameReader, FrameWriter};
+    use http2::frame::continuation::Continuation;
+    use http2::frame::data::Data;
+    use http2::frame::extension::Unknown;
+    use http2::frame::goaway::GoAway;
+    use http2::frame::headers::Headers;
+    use http2::frame::hpack::{Entry, Fragment, InstructionDecoder, Limits};
+    use http2::frame::origin::Origin;
+    use http2::frame::ping::Ping;
+    use http2::frame::priority::Priority;
+    use http2::frame::push_promise::PushPromise;
+    use http2::frame::rst_stream::RstStream;
+    use http2::frame::settings::{Settings, Values};
+    use http2::frame::window_update::WindowUpdate;
+    use http2::flow_control::{DEFAULT_WINDOW_SIZE, MAX_WINDOW_SIZE, UpdatePolicy};
+    use http2::padding::Padding;
+    use http2::protection::Protection;
+    use http2::stream::{Closed, State, StreamId};
+    use {ByteTendril, TendrilSliceExt};
+
+    fn settings() -> Settings {
+        Settings::Parameters {
+            header_table_size: None,
+            enable_push: None,
+            max_concurrent_streams: None,
+            initial_window_size: None,
+            max_frame_size: None,
+            max_header_list_size: None,
+            enable_connect_protocol: None,
+            no_rfc7540_priorities: None,
+            unknown: vec![],
+        }
+    }
+
+    /// Send all the frames queued on one connection to the other, by way of their encoded form.
+    fn transfer(from: &mut Connection, to: &mut Connection) -> Result<(), Error> {
+        let mut writer = FrameWriter::new(vec![]);
+        while let Some((stream_id, payload)) = from.next_frame() {
+            writer.write_payload(stream_id, payload).unwrap();
+        }
+        let written = writer.into_inner();
+        let mut reader = FrameReader::new(&*written);
+        while let Some((header, payload)) = reader.read_frame().unwrap() {
+            try!(to.receive_frame(header.stream_identifier, payload));
+        }
+        Ok(())
+    }
+
+    /// A client and server which have exchanged their initial SETTINGS frames and acknowledged
+    /// them, with no events left outstanding.
+    fn connected() -> (Connection, Connection) {
+        let mut client = Connection::new(Role::Client, settings());
+        let mut server = Connection::new(Role::Server, settings());
+        transfer(&mut client, &mut server).unwrap();
+        transfer(&mut server, &mut client).unwrap();
+        transfer(&mut client, &mut server).unwrap();
+        while client.next_event().is_some() { }
+        while server.next_event().is_some() { }
+        (client, server)
+    }
+
+    fn headers() -> Vec<Entry> {
+        vec![
+            Entry { name: b":method".to_tendril(), value: b"GET".to_tendril() },
+            Entry { name: b":path".to_tendril(), value: b"/".to_tendril() },
+            Entry { name: b"user-agent".to_tendril(), value: b"teepee".to_tendril() },
+        ]
+    }
+
+    #[test]
+    fn settings_exchange() {
+        let mut client = Connection::new(Role::Client, Settings::Parameters {
+            header_table_size: Some(0),
+            enable_push: Some(false),
+            max_concurrent_streams: None,
+            initial_window_size: None,
+            max_frame_size: None,
+            max_header_list_size: Some(8192),
+            enable_connect_protocol: None,
+            no_rfc7540_priorities: None,
+            unknown: vec![],
+        });
+        let mut server = Connection::new(Role::Server, settings());
+
+        transfer(&mut client, &mut server).unwrap();
+        let expected = Values {
+            header_table_size: 0,
+            enable_push: false,
+            max_header_list_size: Some(8192),
+            ..Values::default()
+        };
+        assert_eq!(server.next_event(), Some(Event::SettingsChanged(expected)));
+        assert_eq!(server.remote_settings(), &expected);
+        // The server’s own SETTINGS, then the acknowledgment.
+        let (stream_id, own_settings) = server.next_frame().unwrap();
+        assert!(match (stream_id, &own_settings) {
+            (StreamId(0), &Payload::Settings(Settings::Parameters { .. })) => true,
+            _ => false,
+        });
+        assert_eq!(server.next_frame(),
+                   Some((StreamId(0), Payload::Settings(Settings::Acknowledgment))));
+        assert_eq!(server.next_frame(), None);
+
+        client.receive_frame(StreamId(0), own_settings).unwrap();
+        client.receive_frame(StreamId(0), Payload::Settings(Settings::Acknowledgment)).unwrap();
+        assert_eq!(client.next_event(), Some(Event::SettingsChanged(Values::default())));
+        assert_eq!(client.next_event(), Some(Event::SettingsAcknowledged(expected)));
+        assert_eq!(client.local_settings(), &expected);
+        assert_eq!(client.next_event(), None);
+    }
+
+    #[test]
+    fn ping() {
+        let (mut client, mut server) = connected();
+        client.send_ping(*b"abcdefgh");
+        transfer(&mut client, &mut server).unwrap();
+        assert_eq!(server.next_event(), None);
+        assert_eq!(server.next_frame(), Some((StreamId(0), Payload::Ping(Ping {
+            is_response: true,
+            data: *b"abcdefgh",
+        }))));
+        server.send_ping(*b"abcdefgh");
+        transfer(&mut server, &mut client).unwrap();
+        assert_eq!(client.next_frame(), Some((StreamId(0), Payload::Ping(Ping {
+            is_response: true,
+            data: *b"abcdefgh",
+        }))));
+    }
+
+    #[test]
+    fn ping_before_preface_sent() {
+        let mut server = Connection::new(Role::Server, settings());
+        server.receive_frame(StreamId(0), Payload::Settings(settings())).unwrap();
+        server.receive_frame(StreamId(0), Payload::Ping(Ping {
+            is_response: false,
+            data: *b"abcdefgh",
+        })).unwrap();
+        // The acknowledgment jumps the queue, but not ahead of the server’s connection preface.
+        assert!(match server.next_frame() {
+            Some((StreamId(0), Payload::Settings(Settings::Parameters { .. }))) => true,
+            _ => false,
+        });
+        assert_eq!(server.next_frame(), Some((StreamId(0), Payload::Ping(Ping {
+            is_response: true,
+            data: *b"abcdefgh",
+        }))));
+        assert_eq!(server.next_frame(),
+                   Some((StreamId(0), Payload::Settings(Settings::Acknowledgment))));
+        assert_eq!(server.next_frame(), None);
+    }
+
+    #[test]
+    fn headers_and_data() {
+        let (mut client, mut server) = connected();
+        client.send_headers(StreamId(1), headers(), false).unwrap();
+        client.send_data(StreamId(1), b"hello".to_tendril(), true).unwrap();
+        client.send_headers(StreamId(3), headers(), true).unwrap();
+        transfer(&mut client, &mut server).unwrap();
+        assert_eq!(server.next_event(), Some(Event::Headers {
+            stream_id: StreamId(1),
+            priority: None,
+            headers: headers(),
+            end_stream: false,
+        }));
+        assert_eq!(server.next_event(), Some(Event::Data {
+            stream_id: StreamId(1),
+            data: b"hello".to_tendril(),
+            end_stream: true,
+        }));
+        // This one is all indexed, thanks to the dynamic table.
+        assert_eq!(server.next_event(), Some(Event::Headers {
+            stream_id: StreamId(3),
+            priority: None,
+            headers: headers(),
+            end_stream: true,
+        }));
+        assert_eq!(server.next_event(), None);
+    }
+
+    #[test]
+    fn header_table_size() {
+        let (mut client, mut server) = connected();
+        client.send_headers(StreamId(1), headers(), true).unwrap();
+        // The server’s encoder must honour the client’s table size as soon as it is received.
+        client.send_settings(Settings::Parameters {
+            header_table_size: Some(0),
+            enable_push: None,
+            max_concurrent_streams: None,
+            initial_window_size: None,
+            max_frame_size: None,
+            max_header_list_size: None,
+            enable_connect_protocol: None,
+            no_rfc7540_priorities: None,
+            unknown: vec![],
+        });
+        transfer(&mut client, &mut server).unwrap();
+        server.send_headers(StreamId(1), headers(), true).unwrap();
+        match server.frames.back() {
+            Some(&(_, Payload::Headers(Headers { header_block: Fragment::Instructions(ref i),
+                                                 .. }))) => {
+                assert_eq!(i[0], ::http2::frame::hpack::Instruction::DynamicTableSizeUpdate {
+                    max_size: 0,
+                });
+            },
+            other => panic!("unexpected {:?}", other),
+        }
+        transfer(&mut server, &mut client).unwrap();
+        assert!(match client.next_event() {
+            Some(Event::SettingsAcknowledged(values)) => values.header_table_size == 0,
+            _ => false,
+        });
+        assert!(match client.next_event() {
+            Some(Event::Headers { stream_id: StreamId(1), .. }) => true,
+            _ => false,
+        });
+    }
+
+    #[test]
+    fn compression_error() {
+        let (_, mut server) = connected();
+        let result = server.receive_frame(StreamId(1), Payload::Headers(Headers {
+            pad_length: None,
+            end_stream: true,
+            end_headers: true,
+            priority: None,
+            // Index 62 doesn’t exist.
+            header_block: Fragment::Decoder(InstructionDecoder::new(b"\xbe".to_tendril())),
+        }));
+        assert_eq!(result, Err(Error::Connection(ErrorCode::COMPRESSION_ERROR)));
+        assert_eq!(server.next_frame(), Some((StreamId(0), Payload::GoAway(GoAway {
+            last_stream_id: StreamId(1),
+            error_code: ErrorCode::COMPRESSION_ERROR,
+            additional_debug_data: ByteTendril::new(),
+        }))));
+        assert_eq!(server.receive_frame(StreamId(0), Payload::Ping(Ping {
+            is_response: false,
+            data: [0; 8],
+        })), Err(Error::Connection(ErrorCode::COMPRESSION_ERROR)));
+        assert_eq!(server.next_frame(), None);
+    }
+
+    #[test]
+    fn header_list_too_large() {
+        let (mut client, mut server) = connected();
+        server.set_header_limits(Limits { max_entries: Some(2), ..Limits::default() });
+        client.send_headers(StreamId(1), headers(), true).unwrap();
+        client.send_headers(StreamId(3), headers()[..2].to_vec(), true).unwrap();
+        let (stream_id, payload) = client.next_frame().unwrap();
+        assert_eq!(server.receive_frame(stream_id, payload),
+                   Err(Error::Stream(StreamId(1), ErrorCode::ENHANCE_YOUR_CALM)));
+        assert_eq!(server.next_frame(), Some((StreamId(1), Payload::RstStream(RstStream {
+            error_code: ErrorCode::ENHANCE_YOUR_CALM,
+        }))));
+        // The decoding context is still intact.
+        transfer(&mut client, &mut server).unwrap();
+        assert!(match server.next_event() {
+            Some(Event::Headers { stream_id: StreamId(3), ref headers, .. }) => headers.len() == 2,
+            _ => false,
+        });
+    }
+
+    #[test]
+    fn stream_states() {
+        let (mut client, mut server) = connected();
+        client.send_headers(StreamId(1), headers(), true).unwrap();
+        transfer(&mut client, &mut server).unwrap();
+        assert_eq!(server.stream_state(StreamId(1)), State::HalfClosedRemote);
+        assert_eq!(client.stream_state(StreamId(1)), State::HalfClosedLocal);
+        assert_eq!(client.send_data(StreamId(1), ByteTendril::new(), false),
+                   Err(SendDataError::Stream(Error::Stream(StreamId(1),
+                                                           ErrorCode::STREAM_CLOSED))));
+
+        // DATA after END_STREAM.
+        assert_eq!(server.receive_frame(StreamId(1), Payload::Data(Data {
+            pad_length: None,
+            end_stream: false,
+            data: ByteTendril::new(),
+        })), Err(Error::Stream(StreamId(1), ErrorCode::STREAM_CLOSED)));
+        assert_eq!(server.next_frame(), Some((StreamId(1), Payload::RstStream(RstStream {
+            error_code: ErrorCode::STREAM_CLOSED,
+        }))));
+        assert_eq!(server.stream_state(StreamId(1)), State::Closed(Closed::ResetLocally));
+
+        // Now that it has been reset, frames are ignored.
+        assert_eq!(server.receive_frame(StreamId(1), Payload::Data(Data {
+            pad_length: None,
+            end_stream: true,
+            data: ByteTendril::new(),
+        })), Ok(()));
+        while server.next_event().is_some() { }
+        assert_eq!(server.next_event(), None);
+
+        // DATA on an idle stream.
+        assert_eq!(server.receive_frame(StreamId(3), Payload::Data(Data {
+            pad_length: None,
+            end_stream: false,
+            data: ByteTendril::new(),
+        })), Err(Error::Connection(ErrorCode::PROTOCOL_ERROR)));
+    }
+
+    #[test]
+    fn server_initiated_headers() {
+        let (mut client, mut server) = connected();
+        assert_eq!(server.send_headers(StreamId(1), headers(), true),
+                   Err(Error::Stream(StreamId(1), ErrorCode::PROTOCOL_ERROR)));
+        // Servers can only use even-numbered streams after reserving them.
+        assert_eq!(server.send_headers(StreamId(2), headers(), true),
+                   Err(Error::Stream(StreamId(2), ErrorCode::PROTOCOL_ERROR)));
+        assert_eq!(server.next_frame(), None);
+        assert_eq!(client.receive_frame(StreamId(2), Payload::Headers(Headers {
+            pad_length: None,
+            end_stream: true,
+            end_headers: true,
+            priority: None,
+            header_block: Fragment::Instructions(vec![]),
+        })), Err(Error::Connection(ErrorCode::PROTOCOL_ERROR)));
+    }
+
+    #[test]
+    fn push_promise_to_server() {
+        let (_, mut server) = connected();
+        let result = server.receive_frame(StreamId(1), Payload::PushPromise(
+            ::http2::frame::push_promise::PushPromise {
+                pad_length: None,
+                end_headers: true,
+                promised_stream_id: StreamId(2),
+                header_block: Fragment::Instructions(vec![]),
+            }));
+        assert_eq!(result, Err(Error::Connection(ErrorCode::PROTOCOL_ERROR)));
+    }
+
+    fn data(len: usize) -> ByteTendril {
+        vec![0; len].to_tendril()
+    }
+
+    #[test]
+    fn send_window() {
+        let (mut client, mut server) = connected();
+        server.set_update_policy(UpdatePolicy::Threshold(DEFAULT_WINDOW_SIZE));
+        client.send_headers(StreamId(1), headers(), false).unwrap();
+        assert_eq!(client.send_capacity(StreamId(1)), DEFAULT_WINDOW_SIZE);
+        client.send_data(StreamId(1), data(65000), false).unwrap();
+        assert_eq!(client.send_data(StreamId(1), data(536), false),
+                   Err(SendDataError::Capacity));
+        client.send_data(StreamId(1), data(535), false).unwrap();
+        assert_eq!(client.send_capacity(StreamId(1)), 0);
+        assert_eq!(client.send_capacity(StreamId(3)), 0);
+
+        transfer(&mut client, &mut server).unwrap();
+        transfer(&mut server, &mut client).unwrap();
+        assert_eq!(client.next_event(), Some(Event::WindowUpdate {
+            stream_id: StreamId(0),
+            window_size_increment: DEFAULT_WINDOW_SIZE,
+        }));
+        assert_eq!(client.next_event(), Some(Event::WindowUpdate {
+            stream_id: StreamId(1),
+            window_size_increment: DEFAULT_WINDOW_SIZE,
+        }));
+        assert_eq!(client.send_capacity(StreamId(1)), DEFAULT_WINDOW_SIZE);
+    }
+
+    #[test]
+    fn receive_window() {
+        let (mut client, mut server) = connected();
+        server.send_settings(Settings::Parameters {
+            header_table_size: None,
+            enable_push: None,
+            max_concurrent_streams: None,
+            initial_window_size: Some(10),
+            max_frame_size: None,
+            max_header_list_size: None,
+            enable_connect_protocol: None,
+            no_rfc7540_priorities: None,
+            unknown: vec![],
+        });
+        transfer(&mut server, &mut client).unwrap();
+        transfer(&mut client, &mut server).unwrap();
+        client.send_headers(StreamId(1), headers(), false).unwrap();
+        transfer(&mut client, &mut server).unwrap();
+        while server.next_event().is_some() { }
+
+        // The peer ignored our window; the padding counts too.
+        assert_eq!(server.receive_frame(StreamId(1), Payload::Data(Data {
+            pad_length: Some(0),
+            end_stream: false,
+            data: data(10),
+        })), Err(Error::Stream(StreamId(1), ErrorCode::FLOW_CONTROL_ERROR)));
+        assert_eq!(server.next_frame(), Some((StreamId(1), Payload::RstStream(RstStream {
+            error_code: ErrorCode::FLOW_CONTROL_ERROR,
+        }))));
+
+        // Data beyond the connection’s window is a connection error, even on a reset stream;
+        // those eleven octets haven’t been given back yet.
+        assert_eq!(server.receive_frame(StreamId(1), Payload::Data(Data {
+            pad_length: None,
+            end_stream: false,
+            data: data(DEFAULT_WINDOW_SIZE as usize),
+        })), Err(Error::Connection(ErrorCode::FLOW_CONTROL_ERROR)));
+    }
+
+    #[test]
+    fn update_policies() {
+        let (mut client, mut server) = connected();
+        server.set_update_policy(UpdatePolicy::Immediate);
+        client.send_headers(StreamId(1), headers(), false).unwrap();
+        client.send_data(StreamId(1), data(1), false).unwrap();
+        transfer(&mut client, &mut server).unwrap();
+        assert_eq!(server.next_frame(), Some((StreamId(0), Payload::WindowUpdate(WindowUpdate {
+            window_size_increment: 1,
+        }))));
+        assert_eq!(server.next_frame(), Some((StreamId(1), Payload::WindowUpdate(WindowUpdate {
+            window_size_increment: 1,
+        }))));
+
+        server.set_update_policy(UpdatePolicy::Manual);
+        client.send_data(StreamId(1), data(100), false).unwrap();
+        transfer(&mut client, &mut server).unwrap();
+        assert_eq!(server.next_frame(), None);
+        server.release_capacity(StreamId(1), 100);
+        assert_eq!(server.next_frame(), Some((StreamId(0), Payload::WindowUpdate(WindowUpdate {
+            window_size_increment: 100,
+        }))));
+        assert_eq!(server.next_frame(), Some((StreamId(1), Payload::WindowUpdate(WindowUpdate {
+            window_size_increment: 100,
+        }))));
+
+        // Data on a reset stream still counts against the connection.
+        server.send_reset(StreamId(1), ErrorCode::CANCEL).unwrap();
+        let _ = server.next_frame();
+        client.send_data(StreamId(1), data(100), false).unwrap();
+        transfer(&mut client, &mut server).unwrap();
+        assert_eq!(server.next_frame(), Some((StreamId(0), Payload::WindowUpdate(WindowUpdate {
+            window_size_increment: 100,
+        }))));
+        assert_eq!(server.next_frame(), None);
+    }
+
+    #[test]
+    fn initial_window_size_change() {
+        let (mut client, mut server) = connected();
+        client.send_headers(StreamId(1), headers(), false).unwrap();
+        client.send_data(StreamId(1), data(1000), false).unwrap();
+        server.send_settings(Settings::Parameters {
+            header_table_size: None,
+            enable_push: None,
+            max_concurrent_streams: None,
+            initial_window_size: Some(100),
+            max_frame_size: None,
+            max_header_list_size: None,
+            enable_connect_protocol: None,
+            no_rfc7540_priorities: None,
+            unknown: vec![],
+        });
+        transfer(&mut server, &mut client).unwrap();
+        assert_eq!(client.send_capacity(StreamId(1)), 0);
+        assert_eq!(client.send_capacity(StreamId(3)), 100);
+        assert_eq!(client.send_data(StreamId(1), data(1), false),
+                   Err(SendDataError::Capacity));
+
+        // Too big a WINDOW_UPDATE on a stream is a stream error…
+        assert_eq!(client.receive_frame(StreamId(1), Payload::WindowUpdate(WindowUpdate {
+            window_size_increment: MAX_WINDOW_SIZE,
+        })), Ok(()));
+        assert_eq!(client.receive_frame(StreamId(1), Payload::WindowUpdate(WindowUpdate {
+            window_size_increment: 1000,
+        })), Err(Error::Stream(StreamId(1), ErrorCode::FLOW_CONTROL_ERROR)));
+
+        // … but when a settings change does it, it’s a connection error.
+        client.send_headers(StreamId(3), headers(), false).unwrap();
+        assert_eq!(client.receive_frame(StreamId(3), Payload::WindowUpdate(WindowUpdate {
+            window_size_increment: MAX_WINDOW_SIZE - 100,
+        })), Ok(()));
+        assert_eq!(client.receive_frame(StreamId(0), Payload::Settings(Settings::Parameters {
+            header_table_size: None,
+            enable_push: None,
+            max_concurrent_streams: None,
+            initial_window_size: Some(101),
+            max_frame_size: None,
+            max_header_list_size: None,
+            enable_connect_protocol: None,
+            no_rfc7540_priorities: None,
+            unknown: vec![],
+        })), Err(Error::Connection(ErrorCode::FLOW_CONTROL_ERROR)));
+    }
+
+    #[test]
+    fn priority() {
+        let (mut client, mut server) = connected();
+        client.send_headers(StreamId(1), headers(), true).unwrap();
+        client.send_headers(StreamId(3), headers(), true).unwrap();
+        client.send_priority(StreamId(3), Priority {
+            exclusive: true,
+            stream_dependency: StreamId(0),
+            weight: 255,
+        }).unwrap();
+        assert_eq!(client.send_priority(StreamId(5), Priority {
+            exclusive: false,
+            stream_dependency: StreamId(5),
+            weight: 0,
+        }), Err(Error::Stream(StreamId(5), ErrorCode::PROTOCOL_ERROR)));
+        transfer(&mut client, &mut server).unwrap();
+        assert_eq!(server.priority_tree().parent(StreamId(1)), Some(StreamId(3)));
+
+        server.set_ready(StreamId(1), true);
+        server.set_ready(StreamId(3), true);
+        assert_eq!(server.next_ready_stream(), Some(StreamId(3)));
+        server.send_headers(StreamId(3), headers(), false).unwrap();
+        server.send_data(StreamId(3), data(65535), true).unwrap();
+        // Stream 3 is closed now, and there is no window left for stream 1 anyway.
+        assert!(!server.priority_tree().contains(StreamId(3)));
+        assert_eq!(server.next_ready_stream(), None);
+
+        assert_eq!(server.receive_frame(StreamId(1), Payload::Priority(Priority {
+            exclusive: false,
+            stream_dependency: StreamId(1),
+            weight: 0,
+        })), Err(Error::Stream(StreamId(1), ErrorCode::PROTOCOL_ERROR)));
+    }
+
+    #[test]
+    fn priority_update() {
+        let mut client = Connection::new(Role::Client, Settings::Parameters {
+            header_table_size: None,
+            enable_push: None,
+            max_concurrent_streams: None,
+            initial_window_size: None,
+            max_frame_size: None,
+            max_header_list_size: None,
+            enable_connect_protocol: None,
+            no_rfc7540_priorities: Some(true),
+            unknown: vec![],
+        });
+        let mut server = Connection::new(Role::Server, settings());
+        transfer(&mut client, &mut server).unwrap();
+        transfer(&mut server, &mut client).unwrap();
+        assert!(server.remote_settings().no_rfc7540_priorities);
+
+        // RFC 7540 priorities are now ignored.
+        client.send_headers(StreamId(1), headers(), true).unwrap();
+        client.send_headers(StreamId(3), headers(), true).unwrap();
+        client.send_priority(StreamId(3), Priority {
+            exclusive: true,
+            stream_dependency: StreamId(0),
+            weight: 255,
+        }).unwrap();
+        client.send_priority_update(StreamId(1), b"u=0".to_tendril()).unwrap();
+        transfer(&mut client, &mut server).unwrap();
+        assert_eq!(server.priority_tree().parent(StreamId(1)), Some(StreamId(0)));
+        let mut events = vec![];
+        while let Some(event) = server.next_event() {
+            events.push(event);
+        }
+        assert_eq!(events.pop(), Some(Event::PriorityUpdate {
+            stream_id: StreamId(1),
+            priority_field_value: b"u=0".to_tendril(),
+        }));
+        assert_eq!(server.send_priority_update(StreamId(1), b"u=0".to_tendril()),
+                   Err(Error::Stream(StreamId(1), ErrorCode::PROTOCOL_ERROR)));
+
+        // The setting can’t be changed.
+        client.send_settings(Settings::Parameters {
+            header_table_size: None,
+            enable_push: None,
+            max_concurrent_streams: None,
+            initial_window_size: None,
+            max_frame_size: None,
+            max_header_list_size: None,
+            enable_connect_protocol: None,
+            no_rfc7540_priorities: Some(false),
+            unknown: vec![],
+        });
+        assert_eq!(transfer(&mut client, &mut server),
+                   Err(Error::Connection(ErrorCode::PROTOCOL_ERROR)));
+    }
+
+    #[test]
+    fn extension_settings() {
+        let (mut client, mut server) = connected();
+        server.send_settings(Settings::Parameters {
+            header_table_size: None,
+            enable_push: None,
+            max_concurrent_streams: None,
+            initial_window_size: None,
+            max_frame_size: None,
+            max_header_list_size: None,
+            enable_connect_protocol: Some(true),
+            no_rfc7540_priorities: None,
+            unknown: vec![(0xf00d, 1), (0xf00d, 2)],
+        });
+        transfer(&mut server, &mut client).unwrap();
+        assert!(client.remote_settings().enable_connect_protocol);
+        assert_eq!(client.remote_unknown_setting(0xf00d), Some(2));
+        assert_eq!(client.remote_unknown_setting(0xbeef), None);
+
+        server.send_settings(Settings::Parameters {
+            header_table_size: None,
+            enable_push: None,
+            max_concurrent_streams: None,
+            initial_window_size: None,
+            max_frame_size: None,
+            max_header_list_size: None,
+            enable_connect_protocol: Some(false),
+            no_rfc7540_priorities: None,
+            unknown: vec![],
+        });
+        assert_eq!(transfer(&mut server, &mut client),
+                   Err(Error::Connection(ErrorCode::PROTOCOL_ERROR)));
+    }
+
+    #[test]
+    fn extension_frames() {
+        let (_, mut server) = connected();
+        // Frames of unknown type are discarded, even in the middle of a stream.
+        assert_eq!(server.receive_frame(StreamId(1), Payload::Unknown(Unknown {
+            type_: 0xf0,
+            flags: 0xff,
+            payload: b"abc".to_tendril(),
+        })), Ok(()));
+        assert_eq!(server.next_event(), None);
+        assert_eq!(server.next_frame(), None);
+        assert_eq!(server.stream_state(StreamId(1)), State::Idle);
+
+        // Those of registered types are handed on without further ado.
+        let ping = Ping { is_response: false, data: *b"abcdefgh" };
+        assert_eq!(server.receive_frame(StreamId(3), Payload::Extension(Box::new(ping.clone()))),
+                   Ok(()));
+        match server.next_event() {
+            Some(Event::Extension { stream_id: StreamId(3), ref frame }) => {
+                assert_eq!(frame.downcast_ref::<Ping>(), Some(&ping));
+            },
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(server.next_frame(), None);
+    }
+
+    #[test]
+    fn altsvc_and_origin() {
+        let (mut client, mut server) = connected();
+        assert_eq!(client.send_altsvc(StreamId(0), ByteTendril::new(), b"clear".to_tendril()),
+                   Err(Error::Stream(StreamId(0), ErrorCode::PROTOCOL_ERROR)));
+        assert_eq!(client.send_origin(vec![]),
+                   Err(Error::Stream(StreamId(0), ErrorCode::PROTOCOL_ERROR)));
+        assert_eq!(server.send_altsvc(StreamId(1), b"https://a".to_tendril(),
+                                      b"clear".to_tendril()),
+                   Err(Error::Stream(StreamId(1), ErrorCode::PROTOCOL_ERROR)));
+
+        server.send_altsvc(StreamId(0), b"https://a".to_tendril(), b"h2=\":8443\"".to_tendril())
+              .unwrap();
+        server.send_origin(vec![b"https://a".to_tendril(), b"https://b".to_tendril()]).unwrap();
+        transfer(&mut server, &mut client).unwrap();
+        assert_eq!(client.next_event(), Some(Event::AltSvc {
+            stream_id: StreamId(0),
+            origin: b"https://a".to_tendril(),
+            field_value: b"h2=\":8443\"".to_tendril(),
+        }));
+        assert_eq!(client.next_event(),
+                   Some(Event::Origin(vec![b"https://a".to_tendril(), b"https://b".to_tendril()])));
+
+        // Invalid frames are ignored, as are any the server receives.
+        assert_eq!(client.receive_frame(StreamId(0), Payload::AltSvc(AltSvc {
+            origin: ByteTendril::new(),
+            field_value: b"clear".to_tendril(),
+        })), Ok(()));
+        assert_eq!(client.receive_frame(StreamId(1), Payload::Origin(Origin {
+            origins: vec![],
+        })), Ok(()));
+        assert_eq!(client.next_event(), None);
+        assert_eq!(server.receive_frame(StreamId(0), Payload::Origin(Origin {
+            origins: vec![],
+        })), Ok(()));
+        assert_eq!(server.next_event(), None);
+    }
+
+    #[test]
+    fn malformed_altsvc_and_origin() {
+        // An ALTSVC frame with an origin longer than the frame, and an ORIGIN frame cut short.
+        let input = b"\x00\x00\x03\x0a\x00\x00\x00\x00\x00\x00\x10a\
+                      \x00\x00\x01\x0c\x00\x00\x00\x00\x00\x00";
+        for &role in &[Role::Client, Role::Server] {
+            let (client, server) = connected();
+            let mut connection = if role == Role::Client { client } else { server };
+            let mut reader = FrameReader::new(&input[..]);
+            while let Some((header, payload)) = reader.read_frame().unwrap() {
+                match payload {
+                    Payload::Unknown(_) => (),
+                    other => panic!("unexpected {:?}", other),
+                }
+                // Neither is critical, so the connection carries on without them.
+                assert_eq!(connection.receive_frame(header.stream_identifier, payload), Ok(()));
+            }
+            assert_eq!(connection.next_event(), None);
+            assert_eq!(connection.next_frame(), None);
+        }
+    }
+
+    #[test]
+    fn settings_first() {
+        let mut server = Connection::new(Role::Server, settings());
+        assert_eq!(server.receive_frame(StreamId(0), Payload::Ping(Ping {
+            is_response: false,
+            data: [0; 8],
+        })), Err(Error::Connection(ErrorCode::PROTOCOL_ERROR)));
+
+        let mut client = Connection::new(Role::Client, settings());
+        assert_eq!(client.receive_frame(StreamId(0), Payload::Settings(Settings::Acknowledgment)),
+                   Err(Error::Connection(ErrorCode::PROTOCOL_ERROR)));
+    }
+
+    #[test]
+    fn upgrade() {
+        let http2_settings = Settings::Parameters {
+            header_table_size: None,
+            enable_push: None,
+            max_concurrent_streams: Some(100),
+            initial_window_size: Some(1000),
+            max_frame_size: None,
+            max_header_list_size: None,
+            enable_connect_protocol: None,
+            no_rfc7540_priorities: None,
+            unknown: vec![],
+        };
+        let mut client = Connection::upgrade(Role::Client, settings(), http2_settings.clone())
+                                    .unwrap();
+        let mut server = Connection::upgrade(Role::Server, settings(), http2_settings).unwrap();
+        assert_eq!(client.local_settings().max_concurrent_streams, Some(100));
+        assert_eq!(server.remote_settings().max_concurrent_streams, Some(100));
+        assert_eq!(client.stream_state(StreamId(1)), State::HalfClosedLocal);
+        assert_eq!(server.stream_state(StreamId(1)), State::HalfClosedRemote);
+        assert_eq!(server.send_capacity(StreamId(1)), 1000);
+        // The 101 response acknowledged the settings, so nothing is sent but the preface.
+        assert_eq!(server.frames.len(), 1);
+
+        transfer(&mut client, &mut server).unwrap();
+        transfer(&mut server, &mut client).unwrap();
+        transfer(&mut client, &mut server).unwrap();
+        server.send_headers(StreamId(1), vec![
+            Entry { name: b":status".to_tendril(), value: b"200".to_tendril() },
+        ], true).unwrap();
+        assert_eq!(server.stream_state(StreamId(1)), State::Closed(Closed::SentEndStream));
+        transfer(&mut server, &mut client).unwrap();
+        assert_eq!(client.stream_state(StreamId(1)), State::Closed(Closed::ReceivedEndStream));
+    }
+
+    fn push_request(method: &[u8]) -> PushRequest {
+        PushRequest {
+            method: method.to_tendril(),
+            scheme: b"https".to_tendril(),
+            authority: b"example.com".to_tendril(),
+            path: b"/style.css".to_tendril(),
+            headers: vec![Entry { name: b"accept".to_tendril(), value: b"text/css".to_tendril() }],
+        }
+    }
+
+    #[test]
+    fn push() {
+        let (mut client, mut server) = connected();
+        client.send_headers(StreamId(1), headers(), true).unwrap();
+        transfer(&mut client, &mut server).unwrap();
+        let _ = server.next_event();
+
+        let push = server.push(StreamId(1), push_request(b"GET")).unwrap();
+        assert_eq!(push.associated_stream_id(), StreamId(1));
+        assert_eq!(push.promised_stream_id(), StreamId(2));
+        assert_eq!(server.stream_state(StreamId(2)), State::ReservedLocal);
+        assert_eq!(server.priority_tree().parent(StreamId(2)), Some(StreamId(1)));
+        let second = server.push(StreamId(1), push_request(b"HEAD")).unwrap();
+        assert_eq!(second.promised_stream_id(), StreamId(4));
+        transfer(&mut server, &mut client).unwrap();
+        assert_eq!(client.next_event(), Some(Event::PushPromise {
+            stream_id: StreamId(1),
+            promised_stream_id: StreamId(2),
+            headers: vec![
+                Entry { name: b":method".to_tendril(), value: b"GET".to_tendril() },
+                Entry { name: b":scheme".to_tendril(), value: b"https".to_tendril() },
+                Entry { name: b":authority".to_tendril(), value: b"example.com".to_tendril() },
+                Entry { name: b":path".to_tendril(), value: b"/style.css".to_tendril() },
+                Entry { name: b"accept".to_tendril(), value: b"text/css".to_tendril() },
+            ],
+        }));
+        assert_eq!(client.stream_state(StreamId(2)), State::ReservedRemote);
+
+        let status = vec![Entry { name: b":status".to_tendril(), value: b"200".to_tendril() }];
+        push.send_headers(&mut server, status.clone(), false).unwrap();
+        push.send_data(&mut server, b"p {}".to_tendril(), true).unwrap();
+        second.cancel(&mut server).unwrap();
+        transfer(&mut server, &mut client).unwrap();
+        let _ = client.next_event();
+        assert_eq!(client.next_event(), Some(Event::Headers {
+            stream_id: StreamId(2),
+            priority: None,
+            headers: status,
+            end_stream: false,
+        }));
+        assert_eq!(client.next_event(), Some(Event::Data {
+            stream_id: StreamId(2),
+            data: b"p {}".to_tendril(),
+            end_stream: true,
+        }));
+        assert_eq!(client.next_event(), Some(Event::Reset {
+            stream_id: StreamId(4),
+            error_code: ErrorCode::CANCEL,
+        }));
+    }
+
+    #[test]
+    fn push_refused() {
+        let (mut client, mut server) = connected();
+        client.send_headers(StreamId(1), headers(), false).unwrap();
+        transfer(&mut client, &mut server).unwrap();
+
+        assert_eq!(client.push(StreamId(1), push_request(b"GET")), Err(PushError::Disabled));
+        assert_eq!(server.push(StreamId(1), push_request(b"POST")),
+                   Err(PushError::UnsafeMethod));
+        assert_eq!(server.push(StreamId(3), push_request(b"GET")),
+                   Err(PushError::AssociatedStream));
+        server.send_headers(StreamId(1), headers(), true).unwrap();
+        assert_eq!(server.push(StreamId(1), push_request(b"GET")),
+                   Err(PushError::AssociatedStream));
+        assert_eq!(server.next_frame().map(|(stream_id, _)| stream_id), Some(StreamId(1)));
+        assert_eq!(server.next_frame(), None);
+
+        client.send_settings(Settings::Parameters {
+            header_table_size: None,
+            enable_push: Some(false),
+            max_concurrent_streams: None,
+            initial_window_size: None,
+            max_frame_size: None,
+            max_header_list_size: None,
+            enable_connect_protocol: None,
+            no_rfc7540_priorities: None,
+            unknown: vec![],
+        });
+        client.send_headers(StreamId(3), headers(), true).unwrap();
+        transfer(&mut client, &mut server).unwrap();
+        assert_eq!(server.push(StreamId(3), push_request(b"GET")), Err(PushError::Disabled));
+    }
+
+    #[test]
+    fn graceful_shutdown() {
+        let (mut client, mut server) = connected();
+        client.send_headers(StreamId(1), headers(), true).unwrap();
+        transfer(&mut client, &mut server).unwrap();
+
+        server.shut_down();
+        server.shut_down();
+        assert_eq!(server.frames.len(), 2);
+        assert_eq!(server.frames[0], (StreamId(0), Payload::GoAway(GoAway {
+            last_stream_id: StreamId(0x7fffffff),
+            error_code: ErrorCode::NO_ERROR,
+            additional_debug_data: ByteTendril::new(),
+        })));
+        assert_eq!(server.frames[1], (StreamId(0), Payload::Ping(Ping {
+            is_response: false,
+            data: SHUTDOWN_PING_DATA,
+        })));
+
+        // A request already on its way when the GOAWAY was sent is still served.
+        client.send_headers(StreamId(3), headers(), true).unwrap();
+        transfer(&mut client, &mut server).unwrap();
+        transfer(&mut server, &mut client).unwrap();
+        while server.next_event().is_some() { }
+        assert_eq!(client.next_event(), Some(Event::GoAway {
+            last_stream_id: StreamId(0x7fffffff),
+            error_code: ErrorCode::NO_ERROR,
+            additional_debug_data: ByteTendril::new(),
+            unprocessed: vec![],
+        }));
+        assert_eq!(client.send_headers(StreamId(5), headers(), true),
+                   Err(Error::Stream(StreamId(5), ErrorCode::REFUSED_STREAM)));
+
+        // When the PING comes back, the real last stream identifier goes out.
+        transfer(&mut client, &mut server).unwrap();
+        assert_eq!(server.next_event(), None);
+        assert_eq!(server.next_frame(), Some((StreamId(0), Payload::GoAway(GoAway {
+            last_stream_id: StreamId(3),
+            error_code: ErrorCode::NO_ERROR,
+            additional_debug_data: ByteTendril::new(),
+        }))));
+
+        // Anything newer is refused.
+        let result = server.receive_frame(StreamId(5), Payload::Headers(Headers {
+            pad_length: None,
+            end_stream: true,
+            end_headers: true,
+            priority: None,
+            header_block: Fragment::Instructions(vec![]),
+        }));
+        assert_eq!(result, Err(Error::Stream(StreamId(5), ErrorCode::REFUSED_STREAM)));
+        assert_eq!(server.next_frame(), Some((StreamId(5), Payload::RstStream(RstStream {
+            error_code: ErrorCode::REFUSED_STREAM,
+        }))));
+
+        assert!(!server.is_shut_down());
+        server.send_headers(StreamId(1), headers(), true).unwrap();
+        assert!(!server.is_shut_down());
+        server.send_headers(StreamId(3), headers(), true).unwrap();
+        assert!(server.is_shut_down());
+    }
+
+    #[test]
+    fn goaway_unprocessed() {
+        let (mut client, mut server) = connected();
+        client.send_headers(StreamId(1), headers(), true).unwrap();
+        transfer(&mut client, &mut server).unwrap();
+        client.send_headers(StreamId(3), headers(), true).unwrap();
+        client.send_headers(StreamId(5), headers(), false).unwrap();
+
+        server.send_goaway(ErrorCode::NO_ERROR, ByteTendril::new());
+        transfer(&mut server, &mut client).unwrap();
+        assert_eq!(client.next_event(), Some(Event::GoAway {
+            last_stream_id: StreamId(1),
+            error_code: ErrorCode::NO_ERROR,
+            additional_debug_data: ByteTendril::new(),
+            unprocessed: vec![StreamId(3), StreamId(5)],
+        }));
+        assert_eq!(client.stream_state(StreamId(1)), State::HalfClosedLocal);
+        assert_eq!(client.stream_state(StreamId(3)), State::Closed(Closed::ResetRemotely));
+        assert_eq!(client.stream_state(StreamId(5)), State::Closed(Closed::ResetRemotely));
+
+        assert_eq!(transfer(&mut client, &mut server),
+                   Err(Error::Stream(StreamId(3), ErrorCode::REFUSED_STREAM)));
+    }
+
+    /// Check that the connection has been told to calm down, with a GOAWAY as its last frame.
+    fn assert_calm(connection: &mut Connection, result: Result<(), Error>,
+                   last_stream_id: StreamId) {
+        assert_eq!(result, Err(Error::Connection(ErrorCode::ENHANCE_YOUR_CALM)));
+        let mut last = None;
+        while let Some(frame) = connection.next_frame() {
+            last = Some(frame);
+        }
+        assert_eq!(last, Some((StreamId(0), Payload::GoAway(GoAway {
+            last_stream_id: last_stream_id,
+            error_code: ErrorCode::ENHANCE_YOUR_CALM,
+            additional_debug_data: ByteTendril::new(),
+        }))));
+    }
+
+    #[test]
+    fn rapid_reset() {
+        let (mut client, mut server) = connected();
+        server.set_protection(Protection { max_rapid_resets: 2, ..Protection::default() });
+        for &stream_id in &[1, 3] {
+            client.send_headers(StreamId(stream_id), headers(), true).unwrap();
+            client.send_reset(StreamId(stream_id), ErrorCode::CANCEL).unwrap();
+        }
+        transfer(&mut client, &mut server).unwrap();
+        // A stream seen through to the end makes up for one of them.
+        client.send_headers(StreamId(5), headers(), true).unwrap();
+        transfer(&mut client, &mut server).unwrap();
+        server.send_headers(StreamId(5), headers(), true).unwrap();
+        for &stream_id in &[7, 9] {
+            client.send_headers(StreamId(stream_id), headers(), true).unwrap();
+            client.send_reset(StreamId(stream_id), ErrorCode::CANCEL).unwrap();
+        }
+        let result = transfer(&mut client, &mut server);
+        assert_calm(&mut server, result, StreamId(9));
+    }
+
+    fn continuation(end_headers: bool) -> Payload {
+        Payload::Continuation(Continuation {
+            end_headers: end_headers,
+            header_block: Fragment::Instructions(vec![]),
+        })
+    }
+
+    #[test]
+    fn continuation_flood() {
+        let (_, mut server) = connected();
+        server.set_protection(Protection { max_continuation_frames: 2, ..Protection::default() });
+        for &stream_id in &[1, 3] {
+            server.receive_frame(StreamId(stream_id), Payload::Headers(Headers {
+                pad_length: None,
+                end_stream: true,
+                end_headers: false,
+                priority: None,
+                header_block: Fragment::Instructions(vec![]),
+            })).unwrap();
+            server.receive_frame(StreamId(stream_id), continuation(false)).unwrap();
+            if stream_id == 1 {
+                server.receive_frame(StreamId(stream_id), continuation(true)).unwrap();
+            } else {
+                server.receive_frame(StreamId(stream_id), continuation(false)).unwrap();
+                let result = server.receive_frame(StreamId(stream_id), continuation(true));
+                assert_calm(&mut server, result, StreamId(1));
+            }
+        }
+    }
+
+    #[test]
+    fn control_frame_flood() {
+        let (_, mut server) = connected();
+        server.set_protection(Protection { max_pending_responses: 3, ..Protection::default() });
+        let ping = || Payload::Ping(Ping { is_response: false, data: [0; 8] });
+        for _ in 0..3 {
+            server.receive_frame(StreamId(0), ping()).unwrap();
+        }
+        // Sending the responses makes room for more.
+        assert!(server.next_frame().is_some());
+        server.receive_frame(StreamId(0), ping()).unwrap();
+        let result = server.receive_frame(StreamId(0), Payload::Settings(settings()));
+        assert_calm(&mut server, result, StreamId(0));
+    }
+
+    #[test]
+    fn empty_data_flood() {
+        let (mut client, mut server) = connected();
+        server.set_protection(Protection { max_empty_data_frames: 2, ..Protection::default() });
+        client.send_headers(StreamId(1), headers(), false).unwrap();
+        transfer(&mut client, &mut server).unwrap();
+        for &len in &[0, 0, 1, 0, 0] {
+            server.receive_frame(StreamId(1), Payload::Data(Data {
+                pad_length: None,
+                end_stream: false,
+                data: data(len),
+            })).unwrap();
+        }
+        let result = server.receive_frame(StreamId(1), Payload::Data(Data {
+            pad_length: Some(10),
+            end_stream: false,
+            data: ByteTendril::new(),
+        }));
+        assert_calm(&mut server, result, StreamId(1));
+    }
+
+    #[test]
+    fn idle_streams() {
+        let (mut client, mut server) = connected();
+        server.set_protection(Protection { max_idle_streams: 2, ..Protection::default() });
+        let priority = Priority {
+            exclusive: false,
+            stream_dependency: StreamId(0),
+            weight: 16,
+        };
+        for &stream_id in &[3, 5, 3] {
+            client.send_priority(StreamId(stream_id), priority.clone()).unwrap();
+        }
+        transfer(&mut client, &mut server).unwrap();
+        // Once a stream is used it is no longer idle.
+        client.send_headers(StreamId(3), headers(), true).unwrap();
+        client.send_priority(StreamId(7), priority.clone()).unwrap();
+        transfer(&mut client, &mut server).unwrap();
+        client.send_priority(StreamId(9), priority).unwrap();
+        let result = transfer(&mut client, &mut server);
+        assert_calm(&mut server, result, StreamId(3));
+    }
+
+    #[test]
+    fn skipped_idle_streams() {
+        let (mut client, mut server) = connected();
+        server.set_protection(Protection { max_idle_streams: 2, ..Protection::default() });
+        let priority = Priority {
+            exclusive: false,
+            stream_dependency: StreamId(0),
+            weight: 16,
+        };
+        for &stream_id in &[3, 5] {
+            client.send_priority(StreamId(stream_id), priority.clone()).unwrap();
+        }
+        // Skipping over idle streams closes them, and they are idle no longer.
+        client.send_headers(StreamId(7), headers(), true).unwrap();
+        for &stream_id in &[9, 11] {
+            client.send_priority(StreamId(stream_id), priority.clone()).unwrap();
+        }
+        transfer(&mut client, &mut server).unwrap();
+        assert!(!server.priority_tree().contains(StreamId(3)));
+        assert!(!server.priority_tree().contains(StreamId(5)));
+        assert_eq!(server.stream_state(StreamId(5)), State::Closed(Closed::Skipped));
+        assert_eq!(server.stream_state(StreamId(9)), State::Idle);
+    }
+
+    #[test]
+    fn closed_streams() {
+        let (mut client, mut server) = connected();
+        server.set_max_closed_streams(4);
+        server.set_update_policy(UpdatePolicy::Immediate);
+        for i in 0..6 {
+            let stream_id = StreamId(2 * i + 1);
+            client.send_headers(stream_id, headers(), true).unwrap();
+            transfer(&mut client, &mut server).unwrap();
+            server.send_headers(stream_id, headers(), true).unwrap();
+            transfer(&mut server, &mut client).unwrap();
+        }
+        while server.next_event().is_some() { }
+        // Streams are let go of once they close…
+        assert_eq!(client.streams.len(), 0);
+        assert_eq!(server.streams.len(), 0);
+        // … though the latest are remembered, so that frames on their way can be dealt with…
+        assert_eq!(server.closed_streams.len(), 4);
+        assert_eq!(server.stream_state(StreamId(11)), State::Closed(Closed::SentEndStream));
+        assert_eq!(client.stream_state(StreamId(11)), State::Closed(Closed::ReceivedEndStream));
+        assert_eq!(server.receive_frame(StreamId(11), Payload::WindowUpdate(WindowUpdate {
+            window_size_increment: 1,
+        })), Ok(()));
+        assert_eq!(server.next_frame(), None);
+        // … and frames on the earliest are ignored, for all that can be told of them, though DATA
+        // still counts against the connection’s flow-control window.
+        assert_eq!(server.stream_state(StreamId(1)), State::Closed(Closed::Forgotten));
+        assert_eq!(server.receive_frame(StreamId(1), Payload::Data(Data {
+            pad_length: None,
+            end_stream: false,
+            data: b"late".to_tendril(),
+        })), Ok(()));
+        assert_eq!(server.receive_frame(StreamId(1), Payload::RstStream(RstStream {
+            error_code: ErrorCode::CANCEL,
+        })), Ok(()));
+        assert_eq!(server.next_event(), None);
+        assert_eq!(server.next_frame(), Some((StreamId(0), Payload::WindowUpdate(WindowUpdate {
+            window_size_increment: 4,
+        }))));
+        assert_eq!(server.next_frame(), None);
+        assert_eq!(server.streams.len(), 0);
+        assert_eq!(server.send_data(StreamId(1), b"late".to_tendril(), true),
+                   Err(SendDataError::Stream(Error::Stream(StreamId(1),
+                                                           ErrorCode::STREAM_CLOSED))));
+        assert_eq!(server.streams.len(), 0);
+
+        // Streams that were skipped over are still known for what they are.
+        client.send_headers(StreamId(17), headers(), true).unwrap();
+        transfer(&mut client, &mut server).unwrap();
+        assert_eq!(server.stream_state(StreamId(13)), State::Closed(Closed::Skipped));
+        assert_eq!(server.receive_frame(StreamId(13), Payload::RstStream(RstStream {
+            error_code: ErrorCode::CANCEL,
+        })), Err(Error::Connection(ErrorCode::PROTOCOL_ERROR)));
+    }
+
+    #[test]
+    fn reserved_streams() {
+        let (mut client, mut server) = connected();
+        client.set_protection(Protection { max_reserved_streams: 1, ..Protection::default() });
+        client.send_headers(StreamId(1), headers(), true).unwrap();
+        transfer(&mut client, &mut server).unwrap();
+        let push = server.push(StreamId(1), push_request(b"GET")).unwrap();
+        transfer(&mut server, &mut client).unwrap();
+        // Starting the pushed response leaves room for another push.
+        push.send_headers(&mut server, headers(), false).unwrap();
+        let _ = server.push(StreamId(1), push_request(b"GET")).unwrap();
+        transfer(&mut server, &mut client).unwrap();
+        let _ = server.push(StreamId(1), push_request(b"GET")).unwrap();
+        let result = transfer(&mut server, &mut client);
+        assert_calm(&mut client, result, StreamId(6));
+    }
+
+    #[test]
+    fn padding() {
+        let (mut client, mut server) = connected();
+        server.set_padding(Padding::Multiple(16));
+        client.send_headers(StreamId(1), headers(), true).unwrap();
+        transfer(&mut client, &mut server).unwrap();
+        let _ = server.next_event();
+
+        server.send_headers(StreamId(1), headers(), false).unwrap();
+        let (stream_id, payload) = server.next_frame().unwrap();
+        let mut writer = FrameWriter::new(vec![]);
+        writer.write_payload(stream_id, payload).unwrap();
+        let written = writer.into_inner();
+        // Everything but the nine octets of the frame header.
+        assert_eq!((written.len() - 9) % 16, 0);
+        let (header, payload) = FrameReader::new(&*written).read_frame().unwrap().unwrap();
+        assert_eq!(header.flags & 0x8, 0x8);
+        client.receive_frame(stream_id, payload).unwrap();
+        // The padding counts against flow control, and each frame is padded separately.
+        server.send_data(StreamId(1), data(10), false).unwrap();
+        server.send_data(StreamId(1), data(20000), true).unwrap();
+        assert_eq!(server.send_capacity(StreamId(1)), DEFAULT_WINDOW_SIZE - 16 - 16384 - 3632);
+        let pad_lengths = server.frames.iter().map(|&(_, ref payload)| match *payload {
+            Payload::Data(ref frame) => (frame.data.len32(), frame.pad_length, frame.end_stream),
+            ref other => panic!("unexpected {:?}", other),
+        }).collect::<Vec<_>>();
+        assert_eq!(pad_lengths, vec![(10, Some(5), false), (16384, None, false),
+                                     (3616, Some(15), true)]);
+
+        transfer(&mut server, &mut client).unwrap();
+        assert!(match client.next_event() {
+            Some(Event::Headers { ref headers, .. }) => headers == &self::headers(),
+            _ => false,
+        });
+        let mut received = 0;
+        while let Some(Event::Data { data, .. }) = client.next_event() {
+            received += data.len32();
+        }
+        assert_eq!(received, 20010);
+
+        // Padding only takes what room the windows have left.
+        client.send_headers(StreamId(3), headers(), true).unwrap();
+        transfer(&mut client, &mut server).unwrap();
+        let capacity = server.send_capacity(StreamId(3));
+        server.send_data(StreamId(3), data(capacity as usize - 3), false).unwrap();
+        assert_eq!(server.send_capacity(StreamId(3)), 0);
+        match server.frames.back() {
+            Some(&(_, Payload::Data(Data { pad_length, .. }))) => assert_eq!(pad_length, Some(2)),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn stream_ids() {
+        let (mut client, mut server) = connected();
+        assert_eq!(client.next_stream_id(), Some(StreamId(1)));
+        assert_eq!(server.next_stream_id(), Some(StreamId(2)));
+        client.send_priority(StreamId(3), Priority {
+            exclusive: false,
+            stream_dependency: StreamId(0),
+            weight: 16,
+        }).unwrap();
+        client.send_headers(StreamId(1), headers(), false).unwrap();
+        client.send_headers(StreamId(7), headers(), false).unwrap();
+        assert_eq!(client.next_stream_id(), Some(StreamId(9)));
+        transfer(&mut client, &mut server).unwrap();
+        // Opening stream 7 closed streams 3 and 5, whether known of or not.
+        for &connection in &[&client, &server] {
+            assert_eq!(connection.stream_state(StreamId(1)), State::Open);
+            assert_eq!(connection.stream_state(StreamId(3)), State::Closed(Closed::Skipped));
+            assert_eq!(connection.stream_state(StreamId(5)), State::Closed(Closed::Skipped));
+            assert_eq!(connection.stream_state(StreamId(9)), State::Idle);
+        }
+        assert!(!server.priority_tree().contains(StreamId(3)));
+        assert_eq!(client.send_headers(StreamId(5), headers(), true),
+                   Err(Error::Stream(StreamId(5), ErrorCode::PROTOCOL_ERROR)));
+
+        // A client that goes back.
+        assert_eq!(server.receive_frame(StreamId(5), Payload::Headers(Headers {
+            pad_length: None,
+            end_stream: true,
+            end_headers: true,
+            priority: None,
+            header_block: Fragment::Instructions(vec![]),
+        })), Err(Error::Connection(ErrorCode::PROTOCOL_ERROR)));
+
+        // Running out of identifiers.
+        client.send_headers(StreamId(0x7fffffff), headers(), true).unwrap();
+        assert_eq!(client.next_stream_id(), None);
+    }
+
+    #[test]
+    fn promised_stream_ids() {
+        let (mut client, mut server) = connected();
+        client.send_headers(StreamId(1), headers(), false).unwrap();
+        transfer(&mut client, &mut server).unwrap();
+        let promise = |promised_stream_id| Payload::PushPromise(PushPromise {
+            pad_length: None,
+            end_headers: true,
+            promised_stream_id: StreamId(promised_stream_id),
+            header_block: Fragment::Instructions(vec![]),
+        });
+        client.receive_frame(StreamId(1), promise(4)).unwrap();
+        assert_eq!(client.stream_state(StreamId(2)), State::Closed(Closed::Skipped));
+        // A server that goes back.
+        assert_eq!(client.receive_frame(StreamId(1), promise(2)),
+                   Err(Error::Connection(ErrorCode::PROTOCOL_ERROR)));
+    }
+
+    #[test]
+    fn invalid_frames() {
+        let header = |length, type_, stream_id| Header {
+            length: length,
+            type_: type_,
+            flags: 0,
+            stream_identifier: StreamId(stream_id),
+        };
+        let (_, mut server) = connected();
+        // A PRIORITY frame of the wrong length affects only its stream…
+        assert_eq!(server.receive_invalid_frame(header(4, 0x2, 1), ErrorCode::FRAME_SIZE_ERROR),
+                   Error::Stream(StreamId(1), ErrorCode::FRAME_SIZE_ERROR));
+        assert_eq!(server.next_frame(), Some((StreamId(1), Payload::RstStream(RstStream {
+            error_code: ErrorCode::FRAME_SIZE_ERROR,
+        }))));
+        // … as does a WINDOW_UPDATE with no increment on a stream…
+        assert_eq!(server.receive_invalid_frame(header(4, 0x8, 3), ErrorCode::PROTOCOL_ERROR),
+                   Error::Stream(StreamId(3), ErrorCode::PROTOCOL_ERROR));
+        assert_eq!(server.stream_state(StreamId(3)), State::Closed(Closed::ResetLocally));
+        let _ = server.next_frame();
+        // … but not on the connection.
+        let (_, mut server) = connected();
+        assert_eq!(server.receive_invalid_frame(header(4, 0x8, 0), ErrorCode::PROTOCOL_ERROR),
+                   Error::Connection(ErrorCode::PROTOCOL_ERROR));
+
+        // A frame too large to have been read is always a connection error.
+        let (_, mut server) = connected();
+        assert_eq!(server.receive_invalid_frame(header(16385, 0x2, 1),
+                                                ErrorCode::FRAME_SIZE_ERROR),
+                   Error::Connection(ErrorCode::FRAME_SIZE_ERROR));
+        assert_eq!(server.next_frame(), Some((StreamId(0), Payload::GoAway(GoAway {
+            last_stream_id: StreamId(0),
+            error_code: ErrorCode::FRAME_SIZE_ERROR,
+            additional_debug_data: ByteTendril::new(),
+        }))));
+        // And that is that.
+        assert_eq!(server.receive_invalid_frame(header(4, 0x2, 1), ErrorCode::FRAME_SIZE_ERROR),
+                   Error::Connection(ErrorCode::FRAME_SIZE_ERROR));
+        assert_eq!(server.next_frame(), None);
+    }
+}
@@ -0,0 +1,257 @@
+//! Flow control. See [RFC 7540, section 5.2][spec].
+//!
+//! > Flow control is used for both individual streams and for the connection as a whole.
+//!
+//! Only DATA frames are subject to flow control. Each end keeps a window for each stream and one
+//! for the connection in each direction; sending DATA consumes window on both the stream and the
+//! connection, and WINDOW_UPDATE frames give it back.
+//!
+//! [spec]: http://tools.ietf.org/html/rfc7540#section-5.2
+
+use std::cmp;
+
+use http2::frame::ErrorCode;
+
+/// The largest a flow-control window may be, 2³¹-1 octets.
+///
+/// > A sender MUST NOT allow a flow-control window to exceed 2^31-1 octets.
+pub const MAX_WINDOW_SIZE: u32 = 0x7fffffff;
+
+/// The initial size of every flow-control window, 65,535 octets.
+///
+/// For streams this can be changed with SETTINGS_INITIAL_WINDOW_SIZE; for the connection it can
+/// only be changed with WINDOW_UPDATE frames.
+pub const DEFAULT_WINDOW_SIZE: u32 = 65535;
+
+/// A flow-control window: the number of octets of DATA that may be sent.
+///
+/// > A change to SETTINGS_INITIAL_WINDOW_SIZE can cause the available space in a flow-control
+/// > window to become negative.
+///
+/// That is why this is signed; while it is negative, nothing may be sent.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Window(i32);
+
+impl Window {
+    /// Constructs a window of the given size.
+    ///
+    /// # Panics
+    ///
+    /// If `size` exceeds `MAX_WINDOW_SIZE`.
+    pub fn new(size: u32) -> Window {
+        assert!(size <= MAX_WINDOW_SIZE, "flow-control window too large");
+        Window(size as i32)
+    }
+
+    /// The size of the window, which may be negative.
+    pub fn size(&self) -> i32 {
+        self.0
+    }
+
+    /// The number of octets that may be sent.
+    pub fn available(&self) -> u32 {
+        if self.0 > 0 { self.0 as u32 } else { 0 }
+    }
+
+    /// Consume space in the window, for DATA sent or received.
+    ///
+    /// If there is not enough space, this fails with FLOW_CONTROL_ERROR and the window is left
+    /// unchanged.
+    pub fn consume(&mut self, len: u32) -> Result<(), ErrorCode> {
+        if len > self.available() {
+            return Err(ErrorCode::FLOW_CONTROL_ERROR);
+        }
+        self.0 -= len as i32;
+        Ok(())
+    }
+
+    /// Increase the window, as a WINDOW_UPDATE frame does.
+    ///
+    /// > If a sender receives a WINDOW_UPDATE that causes a flow-control window to exceed this
+    /// > maximum, it MUST terminate either the stream or the connection, as appropriate.
+    ///
+    /// That is signalled by failing with FLOW_CONTROL_ERROR, leaving the window unchanged.
+    pub fn increase(&mut self, increment: u32) -> Result<(), ErrorCode> {
+        self.adjust(increment as i64)
+    }
+
+    /// Adjust the window by the difference between a new and an old initial window size.
+    ///
+    /// This fails with FLOW_CONTROL_ERROR, leaving the window unchanged, if it would take the
+    /// window beyond `MAX_WINDOW_SIZE` in either direction.
+    pub fn adjust(&mut self, delta: i64) -> Result<(), ErrorCode> {
+        let size = self.0 as i64 + delta;
+        if size > MAX_WINDOW_SIZE as i64 || size < -(MAX_WINDOW_SIZE as i64) {
+            return Err(ErrorCode::FLOW_CONTROL_ERROR);
+        }
+        self.0 = size as i32;
+        Ok(())
+    }
+}
+
+/// When to send WINDOW_UPDATE frames for DATA received.
+///
+/// Updating often keeps the peer from stalling, but costs a frame each time; updating rarely is
+/// cheaper, but the peer may run out of window waiting. Whatever the policy, only data that has
+/// been released counts; normally, that happens as soon as it is received.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum UpdatePolicy {
+    /// Send a WINDOW_UPDATE for every DATA frame received.
+    Immediate,
+
+    /// Send a WINDOW_UPDATE once at least half of the window has been used. This is the default.
+    HalfWindow,
+
+    /// Send a WINDOW_UPDATE once at least this many octets have been used.
+    Threshold(u32),
+
+    /// Data is only released when the application says that it is done with it (with
+    /// `Connection::release_capacity`), whereupon WINDOW_UPDATE frames are sent immediately.
+    ///
+    /// This lets the application apply back-pressure: a peer sending faster than the data can be
+    /// dealt with will run out of window. Padding is released automatically.
+    Manual,
+}
+
+impl Default for UpdatePolicy {
+    fn default() -> UpdatePolicy {
+        UpdatePolicy::HalfWindow
+    }
+}
+
+/// The receiving side of a flow-control window.
+///
+/// This tracks how much the peer may still send, and how much of what it has sent we are ready to
+/// give back to it in a WINDOW_UPDATE frame.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct ReceiveWindow {
+    /// How much the peer may still send.
+    window: Window,
+    /// The size of the window that we aim to maintain.
+    size: u32,
+    /// Octets received and released, but not yet given back.
+    released: u32,
+}
+
+impl ReceiveWindow {
+    /// Constructs a receive window of the given size.
+    ///
+    /// # Panics
+    ///
+    /// If `size` exceeds `MAX_WINDOW_SIZE`.
+    pub fn new(size: u32) -> ReceiveWindow {
+        ReceiveWindow {
+            window: Window::new(size),
+            size: size,
+            released: 0,
+        }
+    }
+
+    /// The window as the peer sees it (once it has received every WINDOW_UPDATE sent so far).
+    pub fn window(&self) -> Window {
+        self.window
+    }
+
+    /// Charge DATA received (including any padding) against the window.
+    ///
+    /// > A receiver MAY respond with a stream error (Section 5.4.2) or connection error (Section
+    /// > 5.4.1) of type FLOW_CONTROL_ERROR if it is unable to accept a frame.
+    ///
+    /// A peer that sends more than the window allows gets FLOW_CONTROL_ERROR.
+    pub fn receive(&mut self, len: u32) -> Result<(), ErrorCode> {
+        self.window.consume(len)
+    }
+
+    /// Mark octets received as dealt with, so that they can be given back to the peer.
+    pub fn release(&mut self, len: u32) {
+        self.released = self.released.saturating_add(len);
+    }
+
+    /// Change the size of the window, adjusting it by the difference, as happens to stream
+    /// windows when SETTINGS_INITIAL_WINDOW_SIZE changes.
+    pub fn set_size(&mut self, size: u32) -> Result<(), ErrorCode> {
+        try!(self.window.adjust(size as i64 - self.size as i64));
+        self.size = size;
+        Ok(())
+    }
+
+    /// If the policy calls for a WINDOW_UPDATE now, take its increment, applying it to the window.
+    pub fn take_update(&mut self, policy: UpdatePolicy) -> Option<u32> {
+        let threshold = match policy {
+            UpdatePolicy::Immediate | UpdatePolicy::Manual => 1,
+            UpdatePolicy::HalfWindow => self.size / 2,
+            UpdatePolicy::Threshold(threshold) => threshold,
+        };
+        if self.released == 0 || self.released < threshold {
+            return None;
+        }
+        // Never give back more than would take the window past its maximum size.
+        let increment = match self.window.size() {
+            size if size < 0 => self.released,
+            size => cmp::min(self.released, MAX_WINDOW_SIZE - size as u32),
+        };
+        if increment == 0 {
+            return None;
+        }
+        self.released -= increment;
+        self.window.increase(increment).unwrap();
+        Some(increment)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MAX_WINDOW_SIZE, ReceiveWindow, UpdatePolicy, Window};
+    use http2::frame::ErrorCode;
+
+    #[test]
+    fn window() {
+        let mut window = Window::new(100);
+        assert_eq!(window.consume(60), Ok(()));
+        assert_eq!(window.consume(41), Err(ErrorCode::FLOW_CONTROL_ERROR));
+        assert_eq!(window.available(), 40);
+        assert_eq!(window.adjust(-50), Ok(()));
+        assert_eq!(window.size(), -10);
+        assert_eq!(window.available(), 0);
+        assert_eq!(window.consume(1), Err(ErrorCode::FLOW_CONTROL_ERROR));
+        assert_eq!(window.consume(0), Ok(()));
+        assert_eq!(window.increase(MAX_WINDOW_SIZE), Ok(()));
+        assert_eq!(window.increase(11), Err(ErrorCode::FLOW_CONTROL_ERROR));
+        assert_eq!(window.increase(10), Ok(()));
+        assert_eq!(window.available(), MAX_WINDOW_SIZE);
+    }
+
+    #[test]
+    fn update_policies() {
+        let mut window = ReceiveWindow::new(100);
+        assert_eq!(window.receive(49), Ok(()));
+        window.release(49);
+        assert_eq!(window.take_update(UpdatePolicy::HalfWindow), None);
+        assert_eq!(window.take_update(UpdatePolicy::Threshold(40)), Some(49));
+        assert_eq!(window.window(), Window::new(100));
+
+        assert_eq!(window.receive(1), Ok(()));
+        assert_eq!(window.take_update(UpdatePolicy::Immediate), None);
+        window.release(1);
+        assert_eq!(window.take_update(UpdatePolicy::Immediate), Some(1));
+
+        assert_eq!(window.receive(101), Err(ErrorCode::FLOW_CONTROL_ERROR));
+        assert_eq!(window.receive(100), Ok(()));
+        window.release(100);
+        assert_eq!(window.take_update(UpdatePolicy::HalfWindow), Some(100));
+    }
+
+    #[test]
+    fn set_size() {
+        let mut window = ReceiveWindow::new(100);
+        assert_eq!(window.receive(80), Ok(()));
+        assert_eq!(window.set_size(50), Ok(()));
+        assert_eq!(window.window().size(), -30);
+        assert_eq!(window.receive(1), Err(ErrorCode::FLOW_CONTROL_ERROR));
+        window.release(80);
+        assert_eq!(window.take_update(UpdatePolicy::HalfWindow), Some(80));
+        assert_eq!(window.window(), Window::new(50));
+        assert_eq!(window.set_size(MAX_WINDOW_SIZE), Ok(()));
+        assert_eq!(window.window(), Window::new(MAX_WINDOW_SIZE));
+    }
+}
@@ -0,0 +1,147 @@
+//! The ALTSVC frame definition. See [RFC 7838, section 4][spec].
+//!
+//! [spec]: http://tools.ietf.org/html/rfc7838#section-4
+
+use std::io;
+
+use ByteTendril;
+use super::{Frame, Header, ErrorCode, PayloadSize, NoFlags};
+use super::super::stream::StreamId;
+
+/// The ALTSVC frame definition. See [RFC 7838, section 4][spec].
+///
+/// This advertises alternative services, as the `Alt-Svc` header field does.
+///
+/// > An ALTSVC frame on stream 0 with empty (length 0) "Origin" information is invalid and MUST
+/// > be ignored.  An ALTSVC frame on a stream other than stream 0 containing non-empty "Origin"
+/// > information is invalid and MUST be ignored.
+///
+/// Such frames still decode successfully; ignoring them is up to the receiver.
+///
+/// [spec]: http://tools.ietf.org/html/rfc7838#section-4
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AltSvc {
+    /// The origin that the alternative services apply to, in the ASCII serialisation of RFC
+    /// 6454. On any stream but stream 0 this is empty, the origin being that of the stream.
+    pub origin: ByteTendril,
+
+    /// The alternative services, in the same format as the `Alt-Svc` header field.
+    pub field_value: ByteTendril,
+}
+
+impl AltSvc {
+    /// Whether the frame is valid on the given stream, as opposed to needing to be ignored.
+    pub fn is_valid_on(&self, stream_id: StreamId) -> bool {
+        (stream_id.0 == 0) != self.origin.is_empty()
+    }
+}
+
+impl Frame for AltSvc {
+    type Flags = NoFlags;
+    const TYPE: u8 = 0xa;
+
+    fn decode(_header: Header<NoFlags>, mut payload: ByteTendril) -> Result<AltSvc, ErrorCode> {
+        if payload.len32() < 2 {
+            return Err(ErrorCode::FRAME_SIZE_ERROR);
+        }
+        let origin_len = (payload[0] as u32) << 8 | payload[1] as u32;
+        payload.pop_front(2);
+        if origin_len > payload.len32() {
+            return Err(ErrorCode::FRAME_SIZE_ERROR);
+        }
+        let origin = payload.subtendril(0, origin_len);
+        payload.pop_front(origin_len);
+        Ok(AltSvc {
+            origin: origin,
+            field_value: payload,
+        })
+    }
+
+    fn len(&self) -> PayloadSize {
+        PayloadSize::Exact(2 + self.origin.len32() + self.field_value.len32())
+    }
+
+    fn flags(&self) -> NoFlags {
+        NoFlags
+    }
+
+    fn encode<W: io::Write>(self, w: &mut W) -> io::Result<()> {
+        let origin_len = self.origin.len32();
+        if origin_len > 0xffff {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "ALTSVC origin is too long"));
+        }
+        try!(w.write_all(&[(origin_len >> 8) as u8, origin_len as u8]));
+        try!(w.write_all(&self.origin));
+        w.write_all(&self.field_value)
+    }
+}
+
+frame_tests! {
+    AltSvc;
+
+    frame_size_zero {
+        stream 0,
+        payload [];
+
+        Err(ErrorCode::FRAME_SIZE_ERROR)
+    }
+
+    frame_size_one {
+        stream 0,
+        payload [0];
+
+        Err(ErrorCode::FRAME_SIZE_ERROR)
+    }
+
+    origin_too_long {
+        stream 0,
+        payload [0, 4, b'a', b'b', b'c'];
+
+        Err(ErrorCode::FRAME_SIZE_ERROR)
+    }
+
+    empty {
+        stream 0,
+        payload [0, 0];
+
+        Ok(AltSvc {
+            origin: b"".to_tendril(),
+            field_value: b"".to_tendril(),
+        })
+    }
+
+    connection {
+        stream 0,
+        payload [0, 5, b'h', b't', b't', b'p', b':', b'c', b'l', b'e', b'a', b'r'];
+
+        Ok(AltSvc {
+            origin: b"http:".to_tendril(),
+            field_value: b"clear".to_tendril(),
+        })
+    }
+
+    stream {
+        stream 3,
+        payload [0, 0, b'h', b'3', b'=', b'"', b':', b'4', b'4', b'3', b'"'];
+
+        Ok(AltSvc {
+            origin: b"".to_tendril(),
+            field_value: b"h3=\":443\"".to_tendril(),
+        })
+    }
+}
+
+#[test]
+fn validity() {
+    use TendrilSliceExt;
+
+    let mut frame = AltSvc {
+        origin: b"".to_tendril(),
+        field_value: b"clear".to_tendril(),
+    };
+    assert!(!frame.is_valid_on(StreamId(0)));
+    assert!(frame.is_valid_on(StreamId(1)));
+    frame.origin = b"https://example.com".to_tendril();
+    assert!(frame.is_valid_on(StreamId(0)));
+    assert!(!frame.is_valid_on(StreamId(1)));
+}
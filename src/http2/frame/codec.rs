@@ -0,0 +1,797 @@
+//! Reading and writing whole frames from and to byte streams.
+//!
+//! `FrameReader` takes care of buffering and of splitting the input into frames, leaving you with
+//! a typed header and payload for each; `FrameWriter` does the reverse, splitting DATA frames and
+//! header blocks that are too large for a single frame.
+
+use std::cmp;
+use std::fmt;
+use std::error::Error;
+use std::io;
+
+use ByteTendril;
+use http2::stream::StreamId;
+use super::{Frame, Header, ErrorCode, Payload, CONNECTION_PRELUDE, PADDING};
+use super::{encode_pad_length, encode_stream_id};
+use super::continuation::Continuation;
+use super::data::Data;
+use super::extension::Extensions;
+use super::headers::Headers;
+use super::hpack::Fragment;
+use super::push_promise::PushPromise;
+
+/// The initial value of SETTINGS_MAX_FRAME_SIZE.
+pub const DEFAULT_MAX_FRAME_SIZE: u32 = 16384;
+
+/// The size of the chunks that are read from the underlying reader.
+const READ_SIZE: usize = 16384;
+
+/// The END_HEADERS flag of HEADERS, PUSH_PROMISE and CONTINUATION frames.
+const END_HEADERS: u8 = 0x4;
+
+/// An error from reading a frame.
+#[derive(Debug)]
+pub enum ReadError {
+    /// The underlying reader failed, or ended partway through a frame.
+    Io(io::Error),
+
+    /// The frame with the given header was not acceptable.
+    ///
+    /// This is either a frame larger than SETTINGS_MAX_FRAME_SIZE (FRAME_SIZE_ERROR), in which
+    /// case the payload has not been read and the connection is beyond recovery, or a payload that
+    /// failed to decode. Whether the latter must be treated as a connection error or a stream
+    /// error depends on the frame type; see the relevant section of RFC 7540 for details.
+    Frame(Header<u8>, ErrorCode),
+
+    /// The client connection preface was not as it should be.
+    ///
+    /// > Clients and servers MUST treat an invalid connection preface as a connection error
+    /// > (Section 5.4.1) of type PROTOCOL_ERROR.
+    ///
+    /// If the connection was not upgraded, no GOAWAY need be sent: the client probably isn’t
+    /// speaking HTTP/2 at all.
+    Preface,
+}
+
+impl From<io::Error> for ReadError {
+    fn from(e: io::Error) -> ReadError {
+        ReadError::Io(e)
+    }
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ReadError::Io(ref e) => write!(f, "I/O error reading frame: {}", e),
+            ReadError::Frame(ref header, code) => {
+                write!(f, "bad frame of type {:#x} on stream {}: {:?}",
+                       header.type_, header.stream_identifier.0, code)
+            },
+            ReadError::Preface => f.write_str("invalid connection preface"),
+        }
+    }
+}
+
+impl Error for ReadError {
+    fn description(&self) -> &str {
+        match *self {
+            ReadError::Io(ref e) => e.description(),
+            ReadError::Frame(..) => "bad frame",
+            ReadError::Preface => "invalid connection preface",
+        }
+    }
+}
+
+/// Reads frames from a byte stream.
+///
+/// Input is read into a `ByteTendril` buffer and each payload handed out is a subtendril of it,
+/// so no copying is done beyond what the reads themselves do.
+///
+/// A server must start with `read_preface`, to read the client connection preface.
+pub struct FrameReader<R> {
+    reader: R,
+    buffer: ByteTendril,
+    max_frame_size: u32,
+    extensions: Extensions,
+}
+
+impl<R: io::Read> FrameReader<R> {
+    /// Constructs a new `FrameReader` with the default SETTINGS_MAX_FRAME_SIZE of 16,384.
+    pub fn new(reader: R) -> FrameReader<R> {
+        FrameReader {
+            reader: reader,
+            buffer: ByteTendril::new(),
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            extensions: Extensions::new(),
+        }
+    }
+
+    /// Change the largest frame payload that will be accepted.
+    ///
+    /// This should be the value of SETTINGS_MAX_FRAME_SIZE that we have sent, from the time that
+    /// the peer acknowledges it.
+    pub fn set_max_frame_size(&mut self, max_frame_size: u32) {
+        self.max_frame_size = max_frame_size;
+    }
+
+    /// The largest frame payload that will be accepted.
+    pub fn max_frame_size(&self) -> u32 {
+        self.max_frame_size
+    }
+
+    /// The extension frame types that will be decoded, rather than left as `Payload::Unknown`.
+    pub fn extensions(&self) -> &Extensions {
+        &self.extensions
+    }
+
+    /// Get a mutable reference to the extension frame types, so as to register more.
+    pub fn extensions_mut(&mut self) -> &mut Extensions {
+        &mut self.extensions
+    }
+
+    /// Get a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    /// Get a mutable reference to the underlying reader.
+    ///
+    /// Reading from it directly will probably leave the frame reader hopelessly confused.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+
+    /// Read the client connection preface, as a server must before reading any frames.
+    ///
+    /// This is the same whether the connection uses TLS, HTTP/2 over cleartext TCP with prior
+    /// knowledge, or was upgraded from HTTP/1.1. The input is checked as it arrives, so something
+    /// else (such as an HTTP/1.1 request) is rejected without waiting for all 24 octets. The
+    /// SETTINGS frame which completes the preface is checked by `Connection`.
+    pub fn read_preface(&mut self) -> Result<(), ReadError> {
+        let len = CONNECTION_PRELUDE.len();
+        loop {
+            let available = cmp::min(self.buffer.len(), len);
+            if self.buffer[..available] != CONNECTION_PRELUDE[..available] {
+                return Err(ReadError::Preface);
+            }
+            if available == len {
+                self.buffer.pop_front(len as u32);
+                return Ok(());
+            }
+            let want = self.buffer.len32() + 1;
+            if !try!(self.fill(want)) {
+                return Err(ReadError::Io(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                                        "end of input before the preface")));
+            }
+        }
+    }
+
+    /// Read the next frame.
+    ///
+    /// `Ok(None)` is returned if the reader ends cleanly, between frames.
+    pub fn read_frame(&mut self) -> Result<Option<(Header<u8>, Payload)>, ReadError> {
+        if !try!(self.fill(9)) {
+            return Ok(None);
+        }
+        let header = {
+            let b = &self.buffer[..9];
+            Header::decode([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7], b[8]])
+        };
+        // > An endpoint MUST send an error code of FRAME_SIZE_ERROR if a frame
+        // > exceeds the size defined in SETTINGS_MAX_FRAME_SIZE, exceeds any
+        // > limit defined for the frame type, or is too small to contain
+        // > mandatory frame data.
+        if header.length > self.max_frame_size {
+            return Err(ReadError::Frame(header, ErrorCode::FRAME_SIZE_ERROR));
+        }
+        // The header is in the buffer, so this can’t be Ok(false).
+        let _ = try!(self.fill(9 + header.length));
+        let payload = self.buffer.subtendril(9, header.length);
+        self.buffer.pop_front(9 + header.length);
+        match Payload::decode_with(header, payload, &self.extensions) {
+            Ok(payload) => Ok(Some((header, payload))),
+            Err(code) => Err(ReadError::Frame(header, code)),
+        }
+    }
+
+    /// Make sure that there are at least `len` octets in the buffer.
+    ///
+    /// Returns false if the reader ends while the buffer is empty; ending with anything in the
+    /// buffer is an `UnexpectedEof` error.
+    fn fill(&mut self, len: u32) -> io::Result<bool> {
+        let mut chunk = [0; READ_SIZE];
+        while self.buffer.len32() < len {
+            match self.reader.read(&mut chunk) {
+                Ok(0) => {
+                    if self.buffer.len32() == 0 {
+                        return Ok(false);
+                    }
+                    return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                              "end of input partway through a frame"));
+                },
+                Ok(n) => self.buffer.push_slice(&chunk[..n]),
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Writes frames to a byte stream.
+pub struct FrameWriter<W> {
+    writer: W,
+    max_frame_size: u32,
+}
+
+impl<W: io::Write> FrameWriter<W> {
+    /// Constructs a new `FrameWriter` with the default SETTINGS_MAX_FRAME_SIZE of 16,384.
+    pub fn new(writer: W) -> FrameWriter<W> {
+        FrameWriter {
+            writer: writer,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+        }
+    }
+
+    /// Change the largest frame payload that will be written when splitting frames.
+    ///
+    /// This should be the peer’s SETTINGS_MAX_FRAME_SIZE.
+    pub fn set_max_frame_size(&mut self, max_frame_size: u32) {
+        self.max_frame_size = max_frame_size;
+    }
+
+    /// The largest frame payload that will be written when splitting frames.
+    pub fn max_frame_size(&self) -> u32 {
+        self.max_frame_size
+    }
+
+    /// Get a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    /// Get a mutable reference to the underlying writer.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    /// Consume the frame writer, returning the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    /// Flush the underlying writer.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    /// Write the client connection preface, as a client must before writing any frames.
+    ///
+    /// The first frame after it must be SETTINGS; a new `Connection` starts with that.
+    pub fn write_preface(&mut self) -> io::Result<()> {
+        self.writer.write_all(CONNECTION_PRELUDE)
+    }
+
+    /// Write a frame as it is, with no splitting.
+    pub fn write_frame<F: Frame>(&mut self, stream_id: StreamId, frame: F) -> io::Result<()> {
+        frame.write_frame(Header {
+            length: 0,
+            type_: F::TYPE,
+            flags: F::Flags::from(0),
+            stream_identifier: stream_id,
+        }, &mut self.writer)
+    }
+
+    /// Write a frame, splitting it if it is larger than the maximum frame size.
+    ///
+    /// A DATA frame is split into several DATA frames, with any padding and the END_STREAM flag
+    /// going on the last of them. A HEADERS or PUSH_PROMISE frame has its header block split
+    /// across CONTINUATION frames, with the END_HEADERS flag (if set) on the last of them. Other
+    /// frames are written as they are.
+    ///
+    /// Note that the header block of a HEADERS, PUSH_PROMISE or CONTINUATION frame is written
+    /// exactly as encoded (see `Fragment::encode`), without being decoded and re-encoded, and
+    /// without being gathered into a buffer first.
+    pub fn write_payload(&mut self, stream_id: StreamId, payload: Payload) -> io::Result<()> {
+        match payload {
+            Payload::Data(frame) => self.write_data(stream_id, frame),
+            Payload::Headers(frame) => {
+                let flags = frame.flags().bits();
+                let mut prefix = vec![];
+                try!(encode_pad_length(&mut prefix, frame.pad_length));
+                if let Some(priority) = frame.priority {
+                    try!(priority.encode(&mut prefix));
+                }
+                self.write_header_block(stream_id, <Headers as Frame>::TYPE, flags,
+                                        frame.end_headers, &prefix, frame.header_block,
+                                        frame.pad_length)
+            },
+            Payload::PushPromise(frame) => {
+                let flags = frame.flags().bits();
+                let mut prefix = vec![];
+                try!(encode_pad_length(&mut prefix, frame.pad_length));
+                try!(encode_stream_id(&mut prefix, false, frame.promised_stream_id));
+                self.write_header_block(stream_id, <PushPromise as Frame>::TYPE, flags,
+                                        frame.end_headers, &prefix, frame.header_block,
+                                        frame.pad_length)
+            },
+            Payload::Continuation(frame) => {
+                self.write_header_block(stream_id, <Continuation as Frame>::TYPE, 0,
+                                        frame.end_headers, &[], frame.header_block, None)
+            },
+            payload => payload.write_frame(stream_id, &mut self.writer),
+        }
+    }
+
+    fn write_data(&mut self, stream_id: StreamId, frame: Data) -> io::Result<()> {
+        let Data { pad_length, end_stream, mut data } = frame;
+        let padding = pad_length.map(|x| x as u32 + 1).unwrap_or(0);
+        if padding > self.max_frame_size {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "frame padding is larger than the maximum frame size"));
+        }
+        // The padding goes on the last frame, so the data may run out before that frame, which
+        // then has only padding.
+        while data.len32() + padding > self.max_frame_size {
+            let len = cmp::min(data.len32(), self.max_frame_size);
+            try!(self.write_frame(stream_id, Data {
+                pad_length: None,
+                end_stream: false,
+                data: data.subtendril(0, len),
+            }));
+            data.pop_front(len);
+        }
+        self.write_frame(stream_id, Data {
+            pad_length: pad_length,
+            end_stream: end_stream,
+            data: data,
+        })
+    }
+
+    /// Write a header block, split across as many frames as are needed.
+    ///
+    /// `flags` are the flags for the first frame, apart from END_HEADERS, which will be set on the
+    /// last frame if `end_headers` is true; `prefix` is everything in the first frame’s payload
+    /// before the header block, and `pad_length` determines the padding after it.
+    fn write_header_block(&mut self, stream_id: StreamId, type_: u8, flags: u8,
+                          end_headers: bool, prefix: &[u8], block: Fragment,
+                          pad_length: Option<u8>) -> io::Result<()> {
+        let padding = &PADDING[..pad_length.unwrap_or(0) as usize];
+        let overhead = prefix.len() as u32 + padding.len() as u32;
+        if overhead > self.max_frame_size {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "frame padding is larger than the maximum frame size"));
+        }
+        let len = block.encoded_len() as u32;
+        let first_len = cmp::min(len, self.max_frame_size - overhead);
+        let mut flags = flags & !END_HEADERS;
+        if first_len == len && end_headers {
+            flags = flags | END_HEADERS;
+        }
+        let header: Header<u8> = Header {
+            length: overhead + first_len,
+            type_: type_,
+            flags: flags,
+            stream_identifier: stream_id,
+        };
+        try!(self.writer.write_all(&header.encode()));
+        try!(self.writer.write_all(prefix));
+        let mut writer = HeaderBlockWriter {
+            writer: &mut self.writer,
+            stream_id: stream_id,
+            max_frame_size: self.max_frame_size,
+            end_headers: end_headers,
+            room: first_len,
+            remaining: len,
+            padding: padding,
+        };
+        try!(block.encode(&mut writer));
+        writer.finish()
+    }
+}
+
+/// Writes an encoded header block into the frames that carry it, finishing each frame as it fills
+/// and starting the CONTINUATION frame that follows it.
+///
+/// The frame header of the first frame and whatever precedes the header block in it must already
+/// have been written.
+struct HeaderBlockWriter<'a, W: 'a> {
+    writer: &'a mut W,
+    stream_id: StreamId,
+    max_frame_size: u32,
+    end_headers: bool,
+    /// How much more of the header block the current frame has room for.
+    room: u32,
+    /// How much of the header block is yet to be written.
+    remaining: u32,
+    /// The padding for the end of the current frame, which only the first frame has.
+    padding: &'static [u8],
+}
+
+impl<'a, W: io::Write> HeaderBlockWriter<'a, W> {
+    /// Finish the current frame and start the next, a CONTINUATION.
+    fn next_frame(&mut self) -> io::Result<()> {
+        let len = cmp::min(self.remaining, self.max_frame_size);
+        let header: Header<u8> = Header {
+            length: len,
+            type_: <Continuation as Frame>::TYPE,
+            flags: if len == self.remaining && self.end_headers { END_HEADERS } else { 0 },
+            stream_identifier: self.stream_id,
+        };
+        try!(self.writer.write_all(self.padding));
+        try!(self.writer.write_all(&header.encode()));
+        self.padding = &[];
+        self.room = len;
+        Ok(())
+    }
+
+    /// Finish the last frame, once all of the header block has been written.
+    fn finish(self) -> io::Result<()> {
+        if self.remaining > 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      "header block is shorter than its calculated length"));
+        }
+        self.writer.write_all(self.padding)
+    }
+}
+
+impl<'a, W: io::Write> io::Write for HeaderBlockWriter<'a, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.remaining == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      "header block is longer than its calculated length"));
+        }
+        if self.room == 0 {
+            try!(self.next_frame());
+        }
+        let len = cmp::min(cmp::min(buf.len(), self.room as usize), self.remaining as usize);
+        let written = try!(self.writer.write(&buf[..len]));
+        self.room -= written as u32;
+        self.remaining -= written as u32;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp;
+    use std::io;
+
+    use super::{FrameReader, FrameWriter, ReadError};
+    use http2::frame::{ErrorCode, Header, Payload};
+    use http2::frame::continuation::Continuation;
+    use http2::frame::data::Data;
+    use http2::frame::headers::Headers;
+    use http2::frame::hpack::{Encoder, Entry, Fragment, InstructionDecoder};
+    use http2::frame::ping::Ping;
+    use http2::frame::priority::Priority;
+    use http2::stream::StreamId;
+    use {ByteTendril, TendrilSliceExt};
+
+    /// Reads one octet at a time, to make sure that buffering is working.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl<'a> io::Read for Trickle<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match self.0.split_first() {
+                Some((&b, rest)) => {
+                    buf[0] = b;
+                    self.0 = rest;
+                    Ok(1)
+                },
+                None => Ok(0),
+            }
+        }
+    }
+
+    fn fragment(bytes: &[u8]) -> Fragment {
+        Fragment::Decoder(InstructionDecoder::new(bytes.to_tendril()))
+    }
+
+    #[test]
+    fn read_frames() {
+        let input = b"\x00\x00\x08\x06\x00\x00\x00\x00\x00abcdefgh\
+                      \x00\x00\x03\x00\x01\x00\x00\x00\x01xyz";
+        let mut reader = FrameReader::new(Trickle(input));
+        let (header, payload) = reader.read_frame().unwrap().unwrap();
+        assert_eq!(header, Header {
+            length: 8,
+            type_: 6,
+            flags: 0,
+            stream_identifier: StreamId(0),
+        });
+        assert_eq!(payload, Payload::Ping(Ping { is_response: false, data: *b"abcdefgh" }));
+        let (header, payload) = reader.read_frame().unwrap().unwrap();
+        assert_eq!(header.stream_identifier, StreamId(1));
+        assert_eq!(payload, Payload::Data(Data {
+            pad_length: None,
+            end_stream: true,
+            data: b"xyz".to_tendril(),
+        }));
+        assert!(reader.read_frame().unwrap().is_none());
+    }
+
+    #[test]
+    fn read_truncated() {
+        let mut reader = FrameReader::new(&b"\x00\x00\x08\x06\x00\x00\x00\x00\x00abc"[..]);
+        match reader.read_frame() {
+            Err(ReadError::Io(ref e)) if e.kind() == io::ErrorKind::UnexpectedEof => (),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let mut reader = FrameReader::new(&b"\x00\x00"[..]);
+        match reader.read_frame() {
+            Err(ReadError::Io(ref e)) if e.kind() == io::ErrorKind::UnexpectedEof => (),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn read_too_large() {
+        let mut input = b"\x00\x40\x01\x00\x00\x00\x00\x00\x01".to_vec();
+        input.extend(vec![0; 0x4001]);
+        match FrameReader::new(&*input).read_frame() {
+            Err(ReadError::Frame(header, ErrorCode::FRAME_SIZE_ERROR)) => {
+                assert_eq!(header.length, 0x4001)
+            },
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let mut reader = FrameReader::new(&*input);
+        reader.set_max_frame_size(0x4001);
+        assert!(reader.read_frame().unwrap().is_some());
+    }
+
+    #[test]
+    fn preface() {
+        let mut writer = FrameWriter::new(vec![]);
+        writer.write_preface().unwrap();
+        writer.write_frame(StreamId(0), Ping { is_response: false, data: *b"abcdefgh" }).unwrap();
+        let written = writer.into_inner();
+        assert_eq!(&written[..24], b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n");
+
+        let mut reader = FrameReader::new(Trickle(&written));
+        reader.read_preface().unwrap();
+        let (_, payload) = reader.read_frame().unwrap().unwrap();
+        assert_eq!(payload, Payload::Ping(Ping { is_response: false, data: *b"abcdefgh" }));
+
+        // Anything else is rejected as soon as it differs.
+        let mut reader = FrameReader::new(Trickle(b"GET / HTTP/1.1\r\n"));
+        match reader.read_preface() {
+            Err(ReadError::Preface) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let mut reader = FrameReader::new(Trickle(&written[..23]));
+        match reader.read_preface() {
+            Err(ReadError::Io(ref e)) if e.kind() == io::ErrorKind::UnexpectedEof => (),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn read_bad_payload() {
+        let mut reader = FrameReader::new(&b"\x00\x00\x01\x06\x00\x00\x00\x00\x00a"[..]);
+        match reader.read_frame() {
+            Err(ReadError::Frame(_, ErrorCode::FRAME_SIZE_ERROR)) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    /// Write the payloads and read them back again.
+    fn round_trip(max_frame_size: u32, payloads: Vec<(StreamId, Payload)>)
+            -> Vec<(Header<u8>, Payload)> {
+        let mut writer = FrameWriter::new(vec![]);
+        writer.set_max_frame_size(max_frame_size);
+        for (stream_id, payload) in payloads {
+            writer.write_payload(stream_id, payload).unwrap();
+        }
+        let written = writer.into_inner();
+        let mut reader = FrameReader::new(&*written);
+        let mut frames = vec![];
+        while let Some(frame) = reader.read_frame().unwrap() {
+            assert!(frame.0.length <= max_frame_size);
+            frames.push(frame);
+        }
+        frames
+    }
+
+    #[test]
+    fn write_unsplit() {
+        let frames = round_trip(16384, vec![
+            (StreamId(0), Payload::Ping(Ping { is_response: true, data: *b"abcdefgh" })),
+            (StreamId(1), Payload::Headers(Headers {
+                pad_length: Some(2),
+                end_stream: false,
+                end_headers: true,
+                priority: None,
+                header_block: fragment(b"\x82\x86"),
+            })),
+        ]);
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].1, Payload::Ping(Ping { is_response: true, data: *b"abcdefgh" }));
+        assert_eq!(frames[1].0.flags, 0x4 | 0x8);
+        assert_eq!(frames[1].1, Payload::Headers(Headers {
+            pad_length: Some(2),
+            end_stream: false,
+            end_headers: true,
+            priority: None,
+            header_block: fragment(b"\x82\x86"),
+        }));
+    }
+
+    #[test]
+    fn write_split_data() {
+        let data = (0..100).collect::<Vec<u8>>();
+        let frames = round_trip(40, vec![
+            (StreamId(1), Payload::Data(Data {
+                pad_length: Some(9),
+                end_stream: true,
+                data: data.to_tendril(),
+            })),
+        ]);
+        let lengths = frames.iter().map(|&(ref h, _)| h.length).collect::<Vec<_>>();
+        assert_eq!(lengths, [40, 40, 30]);
+        let mut joined = vec![];
+        for (i, (_, payload)) in frames.into_iter().enumerate() {
+            match payload {
+                Payload::Data(frame) => {
+                    assert_eq!(frame.end_stream, i == 2);
+                    assert_eq!(frame.pad_length, if i == 2 { Some(9) } else { None });
+                    joined.extend(frame.data.iter().cloned());
+                },
+                other => panic!("unexpected {:?}", other),
+            }
+        }
+        assert_eq!(joined, data);
+    }
+
+    #[test]
+    fn write_split_data_padding() {
+        // The data fits in one frame, but not with the padding, which gets a frame to itself.
+        let data = (0..16300).map(|i| i as u8).collect::<Vec<u8>>();
+        let frames = round_trip(16384, vec![
+            (StreamId(1), Payload::Data(Data {
+                pad_length: Some(255),
+                end_stream: true,
+                data: data.to_tendril(),
+            })),
+        ]);
+        let mut frames = frames.into_iter().map(|(h, p)| (h.length, p));
+        assert_eq!(frames.next(), Some((16300, Payload::Data(Data {
+            pad_length: None,
+            end_stream: false,
+            data: data.to_tendril(),
+        }))));
+        assert_eq!(frames.next(), Some((256, Payload::Data(Data {
+            pad_length: Some(255),
+            end_stream: true,
+            data: ByteTendril::new(),
+        }))));
+        assert_eq!(frames.next(), None);
+
+        // Padding that could never fit is an error rather than an endless loop.
+        let mut writer = FrameWriter::new(vec![]);
+        writer.set_max_frame_size(100);
+        let error = writer.write_payload(StreamId(1), Payload::Data(Data {
+            pad_length: Some(100),
+            end_stream: true,
+            data: ByteTendril::new(),
+        })).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn write_split_header_block() {
+        let block = (0..50).map(|_| 0x82).collect::<Vec<u8>>();
+        let frames = round_trip(20, vec![
+            (StreamId(3), Payload::Headers(Headers {
+                pad_length: Some(4),
+                end_stream: true,
+                end_headers: true,
+                priority: Some(Priority {
+                    exclusive: true,
+                    stream_dependency: StreamId(1),
+                    weight: 15,
+                }),
+                header_block: fragment(&block),
+            })),
+        ]);
+        // The first frame has 1 + 5 + 4 octets of overhead, leaving room for 10 of the block.
+        let lengths = frames.iter().map(|&(ref h, _)| h.length).collect::<Vec<_>>();
+        assert_eq!(lengths, [20, 20, 20]);
+        let mut frames = frames.into_iter().map(|(h, p)| (h.stream_identifier, p));
+        assert_eq!(frames.next(), Some((StreamId(3), Payload::Headers(Headers {
+            pad_length: Some(4),
+            end_stream: true,
+            end_headers: false,
+            priority: Some(Priority {
+                exclusive: true,
+                stream_dependency: StreamId(1),
+                weight: 15,
+            }),
+            header_block: fragment(&block[..10]),
+        }))));
+        assert_eq!(frames.next(), Some((StreamId(3), Payload::Continuation(Continuation {
+            end_headers: false,
+            header_block: fragment(&block[10..30]),
+        }))));
+        assert_eq!(frames.next(), Some((StreamId(3), Payload::Continuation(Continuation {
+            end_headers: true,
+            header_block: fragment(&block[30..]),
+        }))));
+        assert_eq!(frames.next(), None);
+    }
+
+    /// Accepts at most three octets at a time, to make sure that partial writes are dealt with.
+    struct Dribble(Vec<u8>);
+
+    impl io::Write for Dribble {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let len = cmp::min(buf.len(), 3);
+            self.0.extend(buf[..len].iter().cloned());
+            Ok(len)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn write_unbuffered() {
+        fn payloads(header_block: Fragment) -> Vec<(StreamId, Payload)> {
+            vec![
+                (StreamId(1), Payload::Headers(Headers {
+                    pad_length: Some(3),
+                    end_stream: false,
+                    end_headers: true,
+                    priority: None,
+                    header_block: header_block,
+                })),
+                (StreamId(1), Payload::Data(Data {
+                    pad_length: Some(2),
+                    end_stream: true,
+                    data: b"abcdefghijklmnopqrstuvwxyz".to_tendril(),
+                })),
+            ]
+        }
+        let entries = vec![
+            Entry { name: b":status".to_tendril(), value: b"200".to_tendril() },
+            Entry { name: b"content-type".to_tendril(), value: b"text/plain".to_tendril() },
+            Entry { name: b"x-custom".to_tendril(), value: b"\x00\x01\x02".to_tendril() },
+        ];
+        let instructions = Encoder::new().encode_fragment(entries.clone());
+        let encoded = Encoder::new().encode_fragment(entries.clone()).into_tendril();
+        assert_eq!(instructions.encoded_len(), encoded.len());
+
+        // Written as it is, the frame’s length is known before the header block is encoded.
+        let mut written = vec![];
+        let (stream_id, payload) = payloads(Encoder::new().encode_fragment(entries)).remove(0);
+        payload.write_frame(stream_id, &mut written).unwrap();
+        assert_eq!(written.len(), 9 + 1 + encoded.len() + 3);
+        assert_eq!(&written[..3], &[0, 0, (1 + encoded.len() + 3) as u8]);
+
+        // Instructions are encoded straight into the frames, splitting and all, and come out
+        // just as an encoded header block does.
+        let mut writer = FrameWriter::new(Dribble(vec![]));
+        writer.set_max_frame_size(16);
+        for (stream_id, payload) in payloads(instructions) {
+            writer.write_payload(stream_id, payload).unwrap();
+        }
+        let mut expected = FrameWriter::new(vec![]);
+        expected.set_max_frame_size(16);
+        let fragment = Fragment::Decoder(InstructionDecoder::new(encoded));
+        for (stream_id, payload) in payloads(fragment) {
+            expected.write_payload(stream_id, payload).unwrap();
+        }
+        assert_eq!(writer.into_inner().0, expected.into_inner());
+    }
+}
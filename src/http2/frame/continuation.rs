@@ -38,7 +38,7 @@ impl Frame for Continuation {
     }
 
     fn len(&self) -> PayloadSize {
-        PayloadSize::Unknown
+        PayloadSize::Exact(self.header_block.encoded_len() as u32)
     }
 
     fn flags(&self) -> Flags {
@@ -0,0 +1,307 @@
+//! Frame types beyond those defined in this crate. See [RFC 7540, section 5.5][spec].
+//!
+//! > Implementations MUST ignore and discard any frame that has a type that is unknown.
+//!
+//! Discarding them is the business of the connection; the decoder keeps a frame of unknown type
+//! as it is, an `Unknown` with its type code, flags and payload (the stream identifier is in the
+//! frame header, as for any other frame). Other crates can go further and teach the decoder about
+//! their own frame types: implement `Frame` for the type, register it with `Extensions`, and
+//! frames of that type will then decode into it, as `Payload::Extension`.
+//!
+//! [spec]: http://tools.ietf.org/html/rfc7540#section-5.5
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+
+use mopa::Any;
+
+use ByteTendril;
+use super::{Frame, Header, ErrorCode, Payload};
+use super::super::stream::StreamId;
+
+/// A frame of a type that the decoder doesn’t know, kept raw.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Unknown {
+    /// The frame type code.
+    pub type_: u8,
+
+    /// The flags, none of which have any meaning that we know of.
+    pub flags: u8,
+
+    /// The whole frame payload.
+    pub payload: ByteTendril,
+}
+
+impl Unknown {
+    /// Keep a frame as it is.
+    pub fn decode(header: Header<u8>, payload: ByteTendril) -> Unknown {
+        Unknown {
+            type_: header.type_,
+            flags: header.flags,
+            payload: payload,
+        }
+    }
+
+    /// Decode the frame as the given type. A frame with some other type code is a PROTOCOL_ERROR.
+    ///
+    /// This is for frame types which weren’t registered with the decoder, and for frames which
+    /// were only understood after the fact, such as those of an extension negotiated with a
+    /// setting that arrived late.
+    pub fn decode_as<F: Frame>(self, stream_id: StreamId) -> Result<F, ErrorCode> {
+        if self.type_ != F::TYPE {
+            return Err(ErrorCode::PROTOCOL_ERROR);
+        }
+        F::decode(Header {
+            length: self.payload.len32(),
+            type_: self.type_,
+            flags: F::Flags::from(self.flags),
+            stream_identifier: stream_id,
+        }, self.payload)
+    }
+
+    /// Write the frame as it was received.
+    pub fn write_frame<W: io::Write>(self, stream_id: StreamId, w: &mut W) -> io::Result<()> {
+        let header = Header {
+            length: self.payload.len32(),
+            type_: self.type_,
+            flags: self.flags,
+            stream_identifier: stream_id,
+        };
+        try!(w.write_all(&header.encode()));
+        w.write_all(&self.payload)
+    }
+}
+
+/// A frame of a type defined outside this crate, which can be carried in `Payload::Extension`.
+///
+/// This is implemented for every suitable `Frame`; there should be no need to implement it
+/// yourself. The original type can be recovered with `downcast_ref` or `downcast`.
+pub trait ExtensionFrame: Any + fmt::Debug {
+    /// The frame type code.
+    fn type_(&self) -> u8;
+
+    /// Compare with another extension frame, which is only equal if it is of the same type.
+    fn eq_extension(&self, other: &ExtensionFrame) -> bool;
+
+    /// Write the frame to the given stream, consuming it.
+    ///
+    /// The `Box<Self>` aspect is to satisfy object safety.
+    fn write_boxed(self: Box<Self>, stream_id: StreamId, w: &mut io::Write) -> io::Result<()>;
+}
+
+mopafy!(ExtensionFrame);
+
+impl<F: Frame + Any + fmt::Debug + PartialEq> ExtensionFrame for F {
+    fn type_(&self) -> u8 {
+        F::TYPE
+    }
+
+    fn eq_extension(&self, other: &ExtensionFrame) -> bool {
+        other.downcast_ref::<F>().map_or(false, |other| self == other)
+    }
+
+    fn write_boxed(self: Box<Self>, stream_id: StreamId, mut w: &mut io::Write)
+            -> io::Result<()> {
+        (*self).write_frame(Header {
+            length: 0,
+            type_: F::TYPE,
+            flags: F::Flags::from(0),
+            stream_identifier: stream_id,
+        }, &mut w)
+    }
+}
+
+impl PartialEq for ExtensionFrame {
+    fn eq(&self, other: &ExtensionFrame) -> bool {
+        self.eq_extension(other)
+    }
+}
+
+impl Eq for ExtensionFrame { }
+
+/// The function which decodes a registered frame type.
+type Decoder = fn(Header<u8>, ByteTendril) -> Result<Box<ExtensionFrame>, ErrorCode>;
+
+fn decode<F>(header: Header<u8>, payload: ByteTendril) -> Result<Box<ExtensionFrame>, ErrorCode>
+where F: Frame + Any + fmt::Debug + PartialEq {
+    Ok(Box::new(try!(F::decode(header.change_flags_type(), payload))))
+}
+
+/// The extension frame types that a decoder knows about.
+///
+/// The frame types defined in this crate always take precedence; registering a type with one of
+/// their type codes has no effect.
+#[derive(Clone, Default)]
+pub struct Extensions {
+    decoders: HashMap<u8, Decoder>,
+}
+
+impl Extensions {
+    /// Constructs a new collection with no frame types registered.
+    pub fn new() -> Extensions {
+        Extensions::default()
+    }
+
+    /// Decode frames with `F`’s type code as `F`, replacing any type registered with that code.
+    pub fn register<F: Frame + Any + fmt::Debug + PartialEq>(&mut self) {
+        let _ = self.decoders.insert(F::TYPE, decode::<F> as Decoder);
+    }
+
+    /// Stop decoding frames with the given type code, leaving them as `Payload::Unknown`.
+    pub fn unregister(&mut self, type_: u8) {
+        let _ = self.decoders.remove(&type_);
+    }
+
+    /// Whether a frame type has been registered with the given type code.
+    pub fn is_registered(&self, type_: u8) -> bool {
+        self.decoders.contains_key(&type_)
+    }
+
+    /// Decode a frame which is not of a type defined in this crate.
+    pub fn decode(&self, header: Header<u8>, payload: ByteTendril) -> Result<Payload, ErrorCode> {
+        match self.decoders.get(&header.type_) {
+            Some(decoder) => decoder(header, payload).map(Payload::Extension),
+            None => Ok(Payload::Unknown(Unknown::decode(header, payload))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::{Extensions, Unknown};
+    use http2::frame::{ErrorCode, Frame, Header, NoFlags, Payload, PayloadSize};
+    use http2::frame::ping::Ping;
+    use http2::stream::StreamId;
+    use {ByteTendril, TendrilSliceExt};
+
+    /// An experimental frame, carrying a single octet on stream 0.
+    #[derive(Debug, Eq, PartialEq)]
+    struct Octet(u8);
+
+    impl Frame for Octet {
+        type Flags = NoFlags;
+        const TYPE: u8 = 0xf0;
+
+        fn decode(header: Header<NoFlags>, payload: ByteTendril) -> Result<Octet, ErrorCode> {
+            if header.stream_identifier.0 != 0 {
+                return Err(ErrorCode::PROTOCOL_ERROR);
+            }
+            if payload.len32() != 1 {
+                return Err(ErrorCode::FRAME_SIZE_ERROR);
+            }
+            Ok(Octet(payload[0]))
+        }
+
+        fn len(&self) -> PayloadSize {
+            PayloadSize::Exact(1)
+        }
+
+        fn flags(&self) -> NoFlags {
+            NoFlags
+        }
+
+        fn encode<W: io::Write>(self, w: &mut W) -> io::Result<()> {
+            w.write_all(&[self.0])
+        }
+    }
+
+    fn header(type_: u8, flags: u8, stream_id: u32, length: u32) -> Header<u8> {
+        Header {
+            length: length,
+            type_: type_,
+            flags: flags,
+            stream_identifier: StreamId(stream_id),
+        }
+    }
+
+    #[test]
+    fn unknown() {
+        let unknown = Unknown {
+            type_: 0xf0,
+            flags: 0x81,
+            payload: b"abc".to_tendril(),
+        };
+        assert_eq!(Payload::decode(header(0xf0, 0x81, 5, 3), b"abc".to_tendril()),
+                   Ok(Payload::Unknown(unknown.clone())));
+
+        let mut encoded = vec![];
+        Payload::Unknown(unknown.clone()).write_frame(StreamId(5), &mut encoded).unwrap();
+        assert_eq!(encoded, b"\x00\x00\x03\xf0\x81\x00\x00\x00\x05abc");
+
+        assert_eq!(unknown.decode_as::<Octet>(StreamId(0)), Err(ErrorCode::FRAME_SIZE_ERROR));
+        let unknown = Unknown { type_: 0xf0, flags: 0, payload: b"a".to_tendril() };
+        assert_eq!(unknown.decode_as::<Octet>(StreamId(0)), Ok(Octet(b'a')));
+        let unknown = Unknown { type_: 0xf1, flags: 0, payload: b"a".to_tendril() };
+        assert_eq!(unknown.decode_as::<Octet>(StreamId(0)), Err(ErrorCode::PROTOCOL_ERROR));
+    }
+
+    #[test]
+    fn registered() {
+        let mut extensions = Extensions::new();
+        extensions.register::<Octet>();
+        assert!(extensions.is_registered(0xf0));
+
+        let payload = Payload::decode_with(header(0xf0, 0, 0, 1), b"a".to_tendril(), &extensions);
+        let frame = match payload {
+            Ok(Payload::Extension(frame)) => frame,
+            other => panic!("unexpected {:?}", other),
+        };
+        assert_eq!(frame.type_(), 0xf0);
+        assert_eq!(frame.downcast_ref::<Octet>(), Some(&Octet(b'a')));
+        assert!(frame.downcast_ref::<Ping>().is_none());
+
+        let payload = Payload::Extension(frame);
+        assert_eq!(payload, Payload::Extension(Box::new(Octet(b'a'))));
+        assert!(payload != Payload::Extension(Box::new(Octet(b'b'))));
+        let mut encoded = vec![];
+        payload.write_frame(StreamId(0), &mut encoded).unwrap();
+        assert_eq!(encoded, b"\x00\x00\x01\xf0\x00\x00\x00\x00\x00a");
+
+        assert_eq!(Payload::decode_with(header(0xf0, 0, 1, 1), b"a".to_tendril(), &extensions),
+                   Err(ErrorCode::PROTOCOL_ERROR));
+
+        extensions.unregister(0xf0);
+        assert_eq!(Payload::decode_with(header(0xf0, 0, 0, 1), b"a".to_tendril(), &extensions),
+                   Ok(Payload::Unknown(Unknown {
+                       type_: 0xf0,
+                       flags: 0,
+                       payload: b"a".to_tendril(),
+                   })));
+    }
+
+    #[test]
+    fn standard_types_take_precedence() {
+        #[derive(Debug, Eq, PartialEq)]
+        struct NotPing;
+
+        impl Frame for NotPing {
+            type Flags = NoFlags;
+            const TYPE: u8 = 0x6;
+
+            fn decode(_: Header<NoFlags>, _: ByteTendril) -> Result<NotPing, ErrorCode> {
+                Ok(NotPing)
+            }
+
+            fn len(&self) -> PayloadSize {
+                PayloadSize::Exact(0)
+            }
+
+            fn flags(&self) -> NoFlags {
+                NoFlags
+            }
+
+            fn encode<W: io::Write>(self, _: &mut W) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut extensions = Extensions::new();
+        extensions.register::<NotPing>();
+        assert_eq!(Payload::decode_with(header(0x6, 0, 0, 8), b"abcdefgh".to_tendril(),
+                                        &extensions),
+                   Ok(Payload::Ping(Ping { is_response: false, data: *b"abcdefgh" })));
+    }
+}
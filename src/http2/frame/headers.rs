@@ -47,7 +47,19 @@ impl Frame for Headers {
     const TYPE: u8 = 0x1;
 
     fn decode(header: Header<Flags>, mut payload: ByteTendril) -> Result<Self, ErrorCode> {
-        let pad_length = try!(decode_padding(header.flags.contains(PADDED), &mut payload));
+        // > An endpoint MUST send an error code of FRAME_SIZE_ERROR if a frame … is too small to
+        // > contain mandatory frame data.
+        let padded = header.flags.contains(PADDED);
+        let priority_len = if header.flags.contains(PRIORITY) { 5 } else { 0 };
+        if payload.len32() < padded as u32 + priority_len {
+            return Err(ErrorCode::FRAME_SIZE_ERROR);
+        }
+        let pad_length = try!(decode_padding(padded, &mut payload));
+        // > Padding that exceeds the size remaining for the header block fragment MUST be treated
+        // > as a PROTOCOL_ERROR.
+        if payload.len32() < priority_len {
+            return Err(ErrorCode::PROTOCOL_ERROR);
+        }
         let priority = if header.flags.contains(PRIORITY) {
             let priority = try!(Priority::decode(header.change_flags_type(),
                                                  payload.subtendril(0, 5)));
@@ -66,7 +78,9 @@ impl Frame for Headers {
     }
 
     fn len(&self) -> PayloadSize {
-        PayloadSize::Unknown
+        let padding = self.pad_length.map_or(0, |pad_length| pad_length as u32 + 1);
+        let priority = if self.priority.is_some() { 5 } else { 0 };
+        PayloadSize::Exact(padding + priority + self.header_block.encoded_len() as u32)
     }
 
     fn flags(&self) -> Flags {
@@ -95,3 +109,57 @@ impl Frame for Headers {
         encode_padding(w, self.pad_length)
     }
 }
+
+frame_tests! {
+    Headers;
+
+    simple {
+        flags END_STREAM | END_HEADERS,
+        stream 1,
+        payload [0x82];
+
+        Ok(Headers {
+            pad_length: None,
+            end_stream: true,
+            end_headers: true,
+            priority: None,
+            header_block: hpack::Fragment::Decoder(hpack::InstructionDecoder::new(
+                [0x82].to_tendril())),
+        })
+    }
+
+    padded_with_priority {
+        flags END_HEADERS | PADDED | PRIORITY,
+        stream 3,
+        payload [2, 0x80, 0, 0, 1, 15, 0x82, 0, 0];
+
+        Ok(Headers {
+            pad_length: Some(2),
+            end_stream: false,
+            end_headers: true,
+            priority: Some(Priority {
+                exclusive: true,
+                stream_dependency: StreamId(1),
+                weight: 15,
+            }),
+            header_block: hpack::Fragment::Decoder(hpack::InstructionDecoder::new(
+                [0x82].to_tendril())),
+        })
+    }
+
+    too_small_for_priority {
+        flags PADDED | PRIORITY,
+        stream 1,
+        payload [0, 0, 0, 0, 1];
+
+        Err(ErrorCode::FRAME_SIZE_ERROR)
+    }
+
+    padding_too_large_for_priority {
+        flags PADDED | PRIORITY,
+        stream 1,
+        payload [1, 0, 0, 0, 1, 15];
+
+        Err(ErrorCode::PROTOCOL_ERROR)
+    }
+}
@@ -0,0 +1,369 @@
+//! The HPACK header block encoder.
+
+use super::{Entry, Fragment, Instruction, LiteralHeaderMode, LiteralHeaderName, Tables};
+
+/// A header block encoder which chooses instructions for header entries.
+///
+/// The encoder keeps its own set of indexing tables, mirroring those which the decoder at the
+/// other end will have. This only works if every header block produced is actually sent, and in
+/// the order in which they were produced; skip one and the two sides will disagree about what the
+/// dynamic table contains, which will not end well.
+///
+/// For each entry, the choice of representation goes thus:
+///
+/// 1. If the name and value are both found in the tables, an indexed header field (6.1);
+/// 2. If the field is sensitive (`authorization`, `proxy-authorization`, or a `cookie` short
+///    enough to be guessed), a literal header field never indexed (6.2.3);
+/// 3. If the entry is too large to fit in the dynamic table at all, a literal header field
+///    without indexing (6.2.2), because adding it would just empty the table;
+/// 4. Otherwise, a literal header field with incremental indexing (6.2.1).
+///
+/// In each of the literal cases, the name is taken from the tables if it can be.
+pub struct Encoder {
+    tables: Tables,
+    /// The smallest protocol maximum size set since the last header block was encoded.
+    ///
+    /// This is `None` if it has not been changed since then.
+    pending_min_size: Option<u32>,
+}
+
+impl Encoder {
+    /// Constructs a new encoder, with the default table size of 4,096 octets.
+    pub fn new() -> Encoder {
+        Encoder {
+            tables: Tables::new(),
+            pending_min_size: None,
+        }
+    }
+
+    /// Get the indexing tables, as the decoder at the other end should also have them.
+    pub fn tables(&self) -> &Tables {
+        &self.tables
+    }
+
+    /// Adjust the maximum index table size that the protocol will permit.
+    ///
+    /// In HTTP/2, this is the SETTINGS_HEADER_TABLE_SIZE value that the *peer* sends, for it is
+    /// its decoder that our encoder must satisfy.
+    ///
+    /// The dynamic table size update instructions which this necessitates are emitted at the start
+    /// of the next header block encoded.
+    pub fn set_protocol_max_size(&mut self, max_size: u32) {
+        self.tables.set_protocol_max_size(max_size);
+        self.pending_min_size = Some(match self.pending_min_size {
+            Some(min) if min < max_size => min,
+            _ => max_size,
+        });
+    }
+
+    /// Encode a header list, producing the instructions for a header block.
+    ///
+    /// The encoder’s tables are updated as the decoder’s will be on executing them.
+    pub fn encode<I>(&mut self, entries: I) -> Vec<Instruction>
+    where I: IntoIterator<Item = Entry> {
+        let mut instructions = vec![];
+        self.encode_size_updates(&mut instructions);
+        for entry in entries {
+            let instruction = self.encode_entry(entry);
+            instructions.push(instruction);
+        }
+        instructions
+    }
+
+    /// Encode a header list, producing a header block fragment.
+    ///
+    /// This is just `encode`, wrapped up for putting into a HEADERS or PUSH_PROMISE frame.
+    #[inline]
+    pub fn encode_fragment<I>(&mut self, entries: I) -> Fragment
+    where I: IntoIterator<Item = Entry> {
+        Fragment::Instructions(self.encode(entries))
+    }
+
+    fn encode_size_updates(&mut self, instructions: &mut Vec<Instruction>) {
+        // > […] if the maximum size is reduced and then increased […] between
+        // > two header blocks, the encoder MUST signal the smallest maximum
+        // > table size […] and then the final maximum size, using at most two
+        // > dynamic table size updates.
+        //
+        // (RFC 7541, section 4.2.) We always use all the space the decoder permits.
+        if let Some(min) = self.pending_min_size.take() {
+            let max = self.tables.protocol_max_size;
+            if min < max && min < self.tables.max_size {
+                instructions.push(Instruction::DynamicTableSizeUpdate { max_size: min });
+                let _ = self.tables.set_max_size(min);
+            }
+            if max != self.tables.max_size {
+                instructions.push(Instruction::DynamicTableSizeUpdate { max_size: max });
+                let _ = self.tables.set_max_size(max);
+            }
+        }
+    }
+
+    fn encode_entry(&mut self, entry: Entry) -> Instruction {
+        let found = self.tables.find(&entry.name, &entry.value);
+        if let Some((index, true)) = found {
+            return Instruction::IndexedHeader { index: index };
+        }
+
+        let mode = if is_sensitive(&entry) {
+            LiteralHeaderMode::NeverIndexed
+        } else if entry.size() > self.tables.max_size {
+            LiteralHeaderMode::WithoutIndexing
+        } else {
+            LiteralHeaderMode::IncrementalIndexing
+        };
+
+        if mode == LiteralHeaderMode::IncrementalIndexing {
+            // This can only fail if the protocol max size is below the table max size, and we
+            // dealt with that in encode_size_updates.
+            let _ = self.tables.insert(entry.clone());
+        }
+
+        Instruction::LiteralHeader {
+            mode: mode,
+            name: match found {
+                Some((index, _)) => LiteralHeaderName::Index(index),
+                None => LiteralHeaderName::Literal(entry.name),
+            },
+            value: entry.value,
+        }
+    }
+}
+
+/// Whether a header field should be protected from compression-based attacks (RFC 7541,
+/// section 7.1.3) by never being indexed.
+fn is_sensitive(entry: &Entry) -> bool {
+    match &*entry.name {
+        b"authorization" | b"proxy-authorization" => true,
+        // > […] an encoder might choose not to index values for the "cookie"
+        // > header field that are considered to be highly valuable or sensitive
+        // > to recovery.
+        //
+        // Short cookies are the ones that can be guessed, so those are the ones we protect.
+        b"cookie" => entry.value.len32() < 20,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Encoder;
+    use super::super::{Decoder, Entry, Fragment, Index, Tables};
+    use super::super::Instruction::*;
+    use super::super::LiteralHeaderMode::*;
+    use super::super::LiteralHeaderName;
+    use {ByteTendril, TendrilSliceExt};
+
+    /// Encode the entries, check that the instructions are as expected and their encoded length
+    /// is as calculated, and then check that a decoder gets back the same entries and ends up with
+    /// the same dynamic table.
+    fn round_trip(encoder: &mut Encoder, tables: &mut Tables, entries: &[Entry],
+                  expected: &[super::super::Instruction]) {
+        let instructions = encoder.encode(entries.iter().cloned());
+        assert_eq!(&*instructions, expected);
+
+        let fragment = Fragment::Instructions(instructions);
+        let len = fragment.encoded_len();
+        let mut encoded = vec![];
+        fragment.encode(&mut encoded).unwrap();
+        assert_eq!(encoded.len(), len);
+        let decoded = Decoder::new(ByteTendril::from(&*encoded), tables)
+            .collect::<Result<Vec<_>, _>>();
+        assert_eq!(decoded.as_ref().map(|x| &**x), Ok(entries));
+
+        assert_eq!(encoder.tables.size, tables.size);
+        assert_eq!(encoder.tables.max_size, tables.max_size);
+        assert_eq!(encoder.tables.dynamic, tables.dynamic);
+    }
+
+    #[test]
+    fn c_3_request_examples() {
+        let mut encoder = Encoder::new();
+        let mut tables = Tables::new();
+        round_trip(&mut encoder, &mut tables, &[
+            entry!(b":method", b"GET"),
+            entry!(b":scheme", b"http"),
+            entry!(b":path", b"/"),
+            entry!(b":authority", b"www.example.com"),
+        ], &[
+            IndexedHeader { index: unsafe { Index::new(2) } },
+            IndexedHeader { index: unsafe { Index::new(6) } },
+            IndexedHeader { index: unsafe { Index::new(4) } },
+            LiteralHeader {
+                mode: IncrementalIndexing,
+                name: LiteralHeaderName::Index(unsafe { Index::new(1) }),
+                value: b"www.example.com".to_tendril(),
+            },
+        ]);
+        round_trip(&mut encoder, &mut tables, &[
+            entry!(b":method", b"GET"),
+            entry!(b":scheme", b"http"),
+            entry!(b":path", b"/"),
+            entry!(b":authority", b"www.example.com"),
+            entry!(b"cache-control", b"no-cache"),
+        ], &[
+            IndexedHeader { index: unsafe { Index::new(2) } },
+            IndexedHeader { index: unsafe { Index::new(6) } },
+            IndexedHeader { index: unsafe { Index::new(4) } },
+            IndexedHeader { index: unsafe { Index::new(62) } },
+            LiteralHeader {
+                mode: IncrementalIndexing,
+                name: LiteralHeaderName::Index(unsafe { Index::new(24) }),
+                value: b"no-cache".to_tendril(),
+            },
+        ]);
+        round_trip(&mut encoder, &mut tables, &[
+            entry!(b":method", b"GET"),
+            entry!(b":scheme", b"https"),
+            entry!(b":path", b"/index.html"),
+            entry!(b":authority", b"www.example.com"),
+            entry!(b"custom-key", b"custom-value"),
+        ], &[
+            IndexedHeader { index: unsafe { Index::new(2) } },
+            IndexedHeader { index: unsafe { Index::new(7) } },
+            IndexedHeader { index: unsafe { Index::new(5) } },
+            IndexedHeader { index: unsafe { Index::new(63) } },
+            LiteralHeader {
+                mode: IncrementalIndexing,
+                name: LiteralHeaderName::Literal(b"custom-key".to_tendril()),
+                value: b"custom-value".to_tendril(),
+            },
+        ]);
+        assert_eq!(encoder.tables.size, 164);
+    }
+
+    #[test]
+    fn c_5_response_examples() {
+        let mut encoder = Encoder::new();
+        let mut tables = Tables::new();
+        encoder.set_protocol_max_size(256);
+        tables.set_protocol_max_size(256);
+        round_trip(&mut encoder, &mut tables, &[
+            entry!(b":status", b"302"),
+            entry!(b"cache-control", b"private"),
+            entry!(b"date", b"Mon, 21 Oct 2013 20:13:21 GMT"),
+            entry!(b"location", b"https://www.example.com"),
+        ], &[
+            DynamicTableSizeUpdate { max_size: 256 },
+            LiteralHeader {
+                mode: IncrementalIndexing,
+                name: LiteralHeaderName::Index(unsafe { Index::new(8) }),
+                value: b"302".to_tendril(),
+            },
+            LiteralHeader {
+                mode: IncrementalIndexing,
+                name: LiteralHeaderName::Index(unsafe { Index::new(24) }),
+                value: b"private".to_tendril(),
+            },
+            LiteralHeader {
+                mode: IncrementalIndexing,
+                name: LiteralHeaderName::Index(unsafe { Index::new(33) }),
+                value: b"Mon, 21 Oct 2013 20:13:21 GMT".to_tendril(),
+            },
+            LiteralHeader {
+                mode: IncrementalIndexing,
+                name: LiteralHeaderName::Index(unsafe { Index::new(46) }),
+                value: b"https://www.example.com".to_tendril(),
+            },
+        ]);
+        round_trip(&mut encoder, &mut tables, &[
+            entry!(b":status", b"307"),
+            entry!(b"cache-control", b"private"),
+            entry!(b"date", b"Mon, 21 Oct 2013 20:13:21 GMT"),
+            entry!(b"location", b"https://www.example.com"),
+        ], &[
+            LiteralHeader {
+                mode: IncrementalIndexing,
+                name: LiteralHeaderName::Index(unsafe { Index::new(8) }),
+                value: b"307".to_tendril(),
+            },
+            IndexedHeader { index: unsafe { Index::new(65) } },
+            IndexedHeader { index: unsafe { Index::new(64) } },
+            IndexedHeader { index: unsafe { Index::new(63) } },
+        ]);
+        assert_eq!(encoder.tables.size, 222);
+    }
+
+    #[test]
+    fn size_update_after_reduction_and_increase() {
+        let mut encoder = Encoder::new();
+        let mut tables = Tables::new();
+        encoder.set_protocol_max_size(0);
+        encoder.set_protocol_max_size(1024);
+        tables.set_protocol_max_size(1024);
+        round_trip(&mut encoder, &mut tables, &[
+            entry!(b"custom-key", b"custom-value"),
+        ], &[
+            DynamicTableSizeUpdate { max_size: 0 },
+            DynamicTableSizeUpdate { max_size: 1024 },
+            LiteralHeader {
+                mode: IncrementalIndexing,
+                name: LiteralHeaderName::Literal(b"custom-key".to_tendril()),
+                value: b"custom-value".to_tendril(),
+            },
+        ]);
+
+        // Nothing has changed since, so there should be no more updates.
+        round_trip(&mut encoder, &mut tables, &[
+            entry!(b"custom-key", b"custom-value"),
+        ], &[
+            IndexedHeader { index: unsafe { Index::new(62) } },
+        ]);
+
+        // Setting the same value again isn’t a change either.
+        encoder.set_protocol_max_size(1024);
+        round_trip(&mut encoder, &mut tables, &[], &[]);
+    }
+
+    #[test]
+    fn oversized_entries_are_not_indexed() {
+        let mut encoder = Encoder::new();
+        let mut tables = Tables::new();
+        encoder.set_protocol_max_size(64);
+        tables.set_protocol_max_size(64);
+        round_trip(&mut encoder, &mut tables, &[
+            entry!(b"a", b"b"),
+            entry!(b"custom-key", b"a value rather too long to fit in sixty-four octets"),
+        ], &[
+            DynamicTableSizeUpdate { max_size: 64 },
+            LiteralHeader {
+                mode: IncrementalIndexing,
+                name: LiteralHeaderName::Literal(b"a".to_tendril()),
+                value: b"b".to_tendril(),
+            },
+            LiteralHeader {
+                mode: WithoutIndexing,
+                name: LiteralHeaderName::Literal(b"custom-key".to_tendril()),
+                value: b"a value rather too long to fit in sixty-four octets".to_tendril(),
+            },
+        ]);
+        assert_eq!(encoder.tables.size, 34);
+    }
+
+    #[test]
+    fn sensitive_entries_are_never_indexed() {
+        let mut encoder = Encoder::new();
+        let mut tables = Tables::new();
+        round_trip(&mut encoder, &mut tables, &[
+            entry!(b"authorization", b"Basic QWxhZGRpbjpvcGVuIHNlc2FtZQ=="),
+            entry!(b"cookie", b"id=42"),
+            entry!(b"cookie", b"session=0123456789abcdef0123456789abcdef"),
+        ], &[
+            LiteralHeader {
+                mode: NeverIndexed,
+                name: LiteralHeaderName::Index(unsafe { Index::new(23) }),
+                value: b"Basic QWxhZGRpbjpvcGVuIHNlc2FtZQ==".to_tendril(),
+            },
+            LiteralHeader {
+                mode: NeverIndexed,
+                name: LiteralHeaderName::Index(unsafe { Index::new(32) }),
+                value: b"id=42".to_tendril(),
+            },
+            LiteralHeader {
+                mode: IncrementalIndexing,
+                name: LiteralHeaderName::Index(unsafe { Index::new(32) }),
+                value: b"session=0123456789abcdef0123456789abcdef".to_tendril(),
+            },
+        ]);
+    }
+}
@@ -381,3 +381,12 @@ for key, table in all_tables.items():
         str((DecodeError if e is None else e).encode(table_indexes))
         for e in table)))
 print('];')
+
+# And the table for encoding, which is just HUFFMAN_TABLE as (code, length in bits) pairs.
+print()
+print('static ENCODE_TABLE: [(u32, u8); {}] = ['.format(len(HUFFMAN_TABLE)))
+for i in range(0, len(HUFFMAN_TABLE), 4):
+    print('    {}'.format(' '.join(
+        '(0x{:08x}, {}),'.format(int(''.join(str(b) for b in bits), 2), len(bits))
+        for bits in HUFFMAN_TABLE[i:i + 4])))
+print('];')
@@ -168,6 +168,21 @@ where W: io::Write {
     }
 }
 
+/// The length in octets of the encoding of `i` with the prefix given by `n_mask`, as written by
+/// the `encodeN` function for that N.
+pub fn encoded_len(n_mask: u8, i: u32) -> usize {
+    if i < n_mask as u32 {
+        return 1;
+    }
+    match i - n_mask as u32 {
+        0...127 => 2,
+        128...16383 => 3,
+        16384...2097151 => 4,
+        2097152...268435455 => 5,
+        _ => 6,
+    }
+}
+
 #[test]
 fn test_encode() {
     macro_rules! t {
@@ -184,3 +199,16 @@ fn test_encode() {
     t!(encode5(0b00000000, 1337) => [0b00011111, 0b10011010, 0b00001010]);
     t!(encode8(0b00000000, 42) => [0b00101010]);
 }
+
+#[test]
+fn test_encoded_len() {
+    for &i in &[0, 1, 3, 4, 14, 15, 16, 30, 31, 32, 126, 127, 128, 142, 143, 1337, 16398,
+                16399, 2097166, 2097167, 268435470, 268435471, 0xffffffff] {
+        let mut output = vec![];
+        encode4(&mut output, 0, i).unwrap();
+        assert_eq!(encoded_len(0b00001111, i), output.len());
+        output.clear();
+        encode7(&mut output, 0, i).unwrap();
+        assert_eq!(encoded_len(0b01111111, i), output.len());
+    }
+}
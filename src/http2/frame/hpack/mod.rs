@@ -1,13 +1,37 @@
 //! An implementation of HPACK: Header Compression for HTTP/2 (RFC 7541).
 
+use std::cmp;
 use std::collections::VecDeque;
 use std::io;
 use std::vec;
 use TendrilSliceExt;
 use ByteTendril;
 
+macro_rules! entry {
+    ($name:expr, $value:expr) => {
+        Entry {
+            name: $name.to_tendril(),
+            value: $value.to_tendril(),
+        }
+    }
+}
+
 mod integer;
 mod string;
+mod encoder;
+
+pub use self::encoder::Encoder;
+
+/// The integer and string representations, which are otherwise private, for the fuzz targets in
+/// `fuzz/` (which are built with `--cfg fuzzing`).
+#[cfg(fuzzing)]
+#[doc(hidden)]
+pub mod primitives {
+    pub use super::integer::{decode4, decode5, decode6, decode7};
+    pub use super::integer::{encode4, encode5, encode6, encode7};
+    pub use super::string::{decode as decode_string, encode as encode_string};
+    pub use super::string::{encode_huffman, encode_plain};
+}
 
 /// An arbitrary decode error. No details are retained on account of how all such errors are
 /// unrecoverable and I’m not interested in lowering my efficiency so you can debug a bad HPACK
@@ -18,17 +42,65 @@ pub struct DecodeError;
 /// `Result<T, DecodeError>`
 pub type DecodeResult<T> = Result<T, DecodeError>;
 
-const STATIC_TABLE_LEN: usize = 61;
+/// Limits on what an `InstructionExecutor` will yield from a single header block.
+///
+/// HPACK is quite happy to let a peer send a tiny header block which decodes to something
+/// enormous: fill the dynamic table with one large entry and then reference it over and over, one
+/// octet per reference. These limits are checked as each entry is produced, so that such a block
+/// can be refused before it has done much damage; string lengths are checked by `Decoder` as each
+/// string is decoded, too, so that a long one is never allocated or Huffman decoded in full.
+///
+/// `None` means no limit, which is what `Limits::default()` gives for every field.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Limits {
+    /// The largest permitted header list size, in octets.
+    ///
+    /// This is calculated the same way as HTTP/2’s SETTINGS_MAX_HEADER_LIST_SIZE: the sum of the
+    /// length of each name and value, plus 32 for each entry.
+    pub max_header_list_size: Option<u32>,
 
-macro_rules! entry {
-    ($name:expr, $value:expr) => {
-        Entry {
-            name: $name.to_tendril(),
-            value: $value.to_tendril(),
-        }
+    /// The longest permitted name or value, in octets (after any Huffman decoding).
+    pub max_string_length: Option<u32>,
+
+    /// The largest permitted number of entries in the header list.
+    pub max_entries: Option<u32>,
+}
+
+/// Which of the `Limits` a header block exceeded.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Limit {
+    /// `Limits.max_header_list_size`
+    HeaderListSize,
+    /// `Limits.max_string_length`
+    StringLength,
+    /// `Limits.max_entries`
+    Entries,
+}
+
+/// An error from executing a header block.
+///
+/// The two cases need quite different treatment in HTTP/2. A decode error leaves the decoding
+/// context in an unknown state, and so must be treated as a connection error of type
+/// COMPRESSION_ERROR. A limit being exceeded, on the other hand, is merely the peer asking for more
+/// than we’re willing to give: the whole block has still been processed, so the tables are fine,
+/// and the request can be refused (e.g. with a 431 response or RST_STREAM with
+/// ENHANCE_YOUR_CALM) without needing to take down the connection.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum ExecuteError {
+    /// The header block was invalid.
+    Decode(DecodeError),
+    /// The header block was valid, but exceeded one of the limits.
+    LimitExceeded(Limit),
+}
+
+impl From<DecodeError> for ExecuteError {
+    fn from(e: DecodeError) -> ExecuteError {
+        ExecuteError::Decode(e)
     }
 }
 
+const STATIC_TABLE_LEN: usize = 61;
+
 lazy_static! {
     /// This table is taken from Appendix A, Static Table Definition.
     static ref STATIC_TABLE: [Entry; STATIC_TABLE_LEN] = [
@@ -167,6 +239,27 @@ impl Tables {
         }
     }
 
+    /// Find an entry in the tables for a header field, for the purposes of encoding.
+    ///
+    /// If any entry has both the name and the value, its index is returned with `true`;
+    /// otherwise, if any entry has the name, its index is returned with `false`. Where there is
+    /// more than one candidate, the lowest index is preferred (thus the static table first).
+    pub fn find(&self, name: &[u8], value: &[u8]) -> Option<(Index, bool)> {
+        let mut name_match = None;
+        for (i, entry) in self.static_.iter().chain(self.dynamic.iter()).enumerate() {
+            if &*entry.name == name {
+                let index = unsafe { Index::new(i as u32 + 1) };
+                if &*entry.value == value {
+                    return Some((index, true));
+                }
+                if name_match.is_none() {
+                    name_match = Some(index);
+                }
+            }
+        }
+        name_match.map(|index| (index, false))
+    }
+
     /// Insert the entry into the dynamic table.
     /// Old entries may be evicted by doing this.
     ///
@@ -324,9 +417,9 @@ impl Instruction {
                         try!(integer::encode4(writer, 0b00010000, index)),
                 }
                 if let LiteralHeaderName::Literal(ref name) = *name {
-                    try!(string::encode_plain(writer, name));
+                    try!(string::encode(writer, name));
                 }
-                string::encode_plain(writer, value)
+                string::encode(writer, value)
             },
 
             Instruction::DynamicTableSizeUpdate { max_size } => {
@@ -334,6 +427,33 @@ impl Instruction {
             },
         }
     }
+
+    /// Calculate the length of the encoded instruction, in octets.
+    pub fn encoded_len(&self) -> usize {
+        match *self {
+            Instruction::IndexedHeader { index } => integer::encoded_len(0b01111111, *index),
+
+            Instruction::LiteralHeader { mode, ref name, ref value } => {
+                let (index, name_len) = match *name {
+                    LiteralHeaderName::Index(index) => (*index, 0),
+                    LiteralHeaderName::Literal(ref name) => (0, string::encoded_len(name)),
+                };
+                let index_len = match mode {
+                    LiteralHeaderMode::IncrementalIndexing => {
+                        integer::encoded_len(0b00111111, index)
+                    },
+                    LiteralHeaderMode::WithoutIndexing | LiteralHeaderMode::NeverIndexed => {
+                        integer::encoded_len(0b00001111, index)
+                    },
+                };
+                index_len + name_len + string::encoded_len(value)
+            },
+
+            Instruction::DynamicTableSizeUpdate { max_size } => {
+                integer::encoded_len(0b00011111, max_size)
+            },
+        }
+    }
 }
 
 /// A header block decoder which just decodes instructions.
@@ -349,6 +469,10 @@ impl Instruction {
 #[derive(Debug, PartialEq, Eq)]
 pub struct InstructionDecoder {
     input: ByteTendril,
+    /// Strings longer than this are cut short; see `limit_strings`.
+    max_string_length: Option<u32>,
+    /// The same, for literals with incremental indexing.
+    max_indexed_string_length: Option<u32>,
 }
 
 impl InstructionDecoder {
@@ -356,8 +480,38 @@ impl InstructionDecoder {
     pub fn new(input: ByteTendril) -> InstructionDecoder {
         InstructionDecoder {
             input: input,
+            max_string_length: None,
+            max_indexed_string_length: None,
         }
     }
+
+    /// Cut string literals longer than `max_string_length` short as they are decoded, keeping
+    /// just enough of them for an `InstructionExecutor` with that limit to see that it has been
+    /// exceeded. The rest of such a string is skipped without being allocated or Huffman decoded.
+    ///
+    /// A literal with incremental indexing must still go into the tables as it is, if it fits, so
+    /// its strings are only cut short if they are longer than the tables could ever hold, too.
+    pub fn limit_strings(&mut self, max_string_length: Option<u32>, tables: &Tables) {
+        self.max_string_length = max_string_length;
+        self.max_indexed_string_length = max_string_length.map(|max| {
+            cmp::max(max, tables.protocol_max_size)
+        });
+    }
+
+    /// Decode a string literal, cutting it short if it is too long.
+    fn decode_string(&mut self, indexed: bool) -> DecodeResult<ByteTendril> {
+        let max_len = if indexed {
+            self.max_indexed_string_length
+        } else {
+            self.max_string_length
+        };
+        string::decode(&mut self.input, max_len)
+    }
+
+    /// Consumes the decoder, returning the input that it has not yet decoded.
+    pub fn into_inner(self) -> ByteTendril {
+        self.input
+    }
 }
 
 macro_rules! try2 {
@@ -392,10 +546,10 @@ impl Iterator for InstructionDecoder {
                 Ok(Instruction::LiteralHeader {
                     mode: LiteralHeaderMode::IncrementalIndexing,
                     name: match try2!(integer::decode6(&mut self.input)) {
-                        0 => LiteralHeaderName::Literal(try2!(string::decode(&mut self.input))),
+                        0 => LiteralHeaderName::Literal(try2!(self.decode_string(true))),
                         index => LiteralHeaderName::Index(unsafe { Index::new(index) }),
                     },
-                    value: try2!(string::decode(&mut self.input)),
+                    value: try2!(self.decode_string(true)),
                 })
             },
             0b00000000...0b00011111 => {
@@ -408,10 +562,10 @@ impl Iterator for InstructionDecoder {
                         LiteralHeaderMode::NeverIndexed
                     },
                     name: match try2!(integer::decode4(&mut self.input)) {
-                        0 => LiteralHeaderName::Literal(try2!(string::decode(&mut self.input))),
+                        0 => LiteralHeaderName::Literal(try2!(self.decode_string(false))),
                         index => LiteralHeaderName::Index(unsafe { Index::new(index) }),
                     },
-                    value: try2!(string::decode(&mut self.input)),
+                    value: try2!(self.decode_string(false)),
                 })
             },
             _ => {
@@ -432,10 +586,21 @@ impl Iterator for InstructionDecoder {
 /// This applier works directly on the tables given to it, applying the decoded instructions; the
 /// values it yields are the header entries (name/value pairs). If you wish to just decode the
 /// instructions without applying them, use `InstructionDecoder` directly.
+///
+/// If any of the executor’s `Limits` is exceeded, it stops yielding entries but carries on
+/// executing the rest of the instructions, because the tables must still end up the same as the
+/// peer’s; once the instructions are exhausted it yields `ExecuteError::LimitExceeded`. So if you
+/// stop iterating before reaching the end, the tables will be left in an inconsistent state.
 pub struct InstructionExecutor<'tables, I>
 where I: Iterator, I::Item: InstructionOrDecodeResultInstruction {
     instructions: I,
     tables: &'tables mut Tables,
+    limits: Limits,
+    header_list_size: u32,
+    entries: u32,
+    exceeded: Option<Limit>,
+    /// Whether any header field representation has been executed yet.
+    started: bool,
 }
 
 #[doc(hidden)]
@@ -466,22 +631,32 @@ where I: Iterator, I::Item: InstructionOrDecodeResultInstruction {
         InstructionExecutor {
             instructions: instructions,
             tables: tables,
+            limits: Limits::default(),
+            header_list_size: 0,
+            entries: 0,
+            exceeded: None,
+            started: false,
         }
     }
-}
 
-impl<'tables, I> Iterator for InstructionExecutor<'tables, I>
-where I: Iterator, I::Item: InstructionOrDecodeResultInstruction {
-    type Item = DecodeResult<Entry>;
+    /// Set the limits on what will be yielded. By default there are none.
+    ///
+    /// This should be done before iteration starts.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
 
-    fn next(&mut self) -> Option<DecodeResult<Entry>> {
+    /// Execute the next instruction that produces an entry, with no regard for the limits.
+    fn next_entry(&mut self) -> Option<DecodeResult<Entry>> {
         loop {
             match self.instructions.next().map(|i| i.into_result_instruction()) {
                 Some(Ok(Instruction::IndexedHeader { index })) => {
+                    self.started = true;
                     // See section 3.2 and 2.3 on static+dynamic tables
                     return Some(self.tables.get(index).map(|entry| entry.clone()));
                 },
                 Some(Ok(Instruction::LiteralHeader { mode, name, value })) => {
+                    self.started = true;
                     let name = match name {
                         LiteralHeaderName::Index(i) => try2!(self.tables.get(i)).name.clone(),
                         LiteralHeaderName::Literal(name) => name,
@@ -496,6 +671,11 @@ where I: Iterator, I::Item: InstructionOrDecodeResultInstruction {
                     return Some(Ok(entry));
                 },
                 Some(Ok(Instruction::DynamicTableSizeUpdate { max_size })) => {
+                    // > This dynamic table size update MUST occur at the beginning of the first
+                    // > header block following the change to the dynamic table size.
+                    if self.started {
+                        return Some(Err(DecodeError));
+                    }
                     // New maximum size MUST be <= HTTP/2’s SETTINGS_HEADER_TABLE_SIZE.
                     // Reducing the maximum size can cause entries to be evicted.
                     try2!(self.tables.set_max_size(max_size));
@@ -506,6 +686,58 @@ where I: Iterator, I::Item: InstructionOrDecodeResultInstruction {
             }
         }
     }
+
+    /// Count the entry against the limits, returning the first limit it takes us over, if any.
+    fn check_limits(&mut self, entry: &Entry) -> Option<Limit> {
+        // `Decoder` has already cut any string longer than this short, but it is still too long
+        // and is caught here; so is one from instructions that were decoded in any other way.
+        if let Some(max) = self.limits.max_string_length {
+            if entry.name.len32() > max || entry.value.len32() > max {
+                return Some(Limit::StringLength);
+            }
+        }
+        self.entries = self.entries.saturating_add(1);
+        if let Some(max) = self.limits.max_entries {
+            if self.entries > max {
+                return Some(Limit::Entries);
+            }
+        }
+        self.header_list_size = self.header_list_size.saturating_add(entry.size());
+        if let Some(max) = self.limits.max_header_list_size {
+            if self.header_list_size > max {
+                return Some(Limit::HeaderListSize);
+            }
+        }
+        None
+    }
+}
+
+impl<'tables, I> Iterator for InstructionExecutor<'tables, I>
+where I: Iterator, I::Item: InstructionOrDecodeResultInstruction {
+    type Item = Result<Entry, ExecuteError>;
+
+    fn next(&mut self) -> Option<Result<Entry, ExecuteError>> {
+        loop {
+            match self.next_entry() {
+                Some(Ok(entry)) => {
+                    if self.exceeded.is_some() {
+                        // Still executing so that the tables stay in sync, but yielding nothing.
+                        continue;
+                    }
+                    self.exceeded = self.check_limits(&entry);
+                    if self.exceeded.is_none() {
+                        return Some(Ok(entry));
+                    }
+                },
+                Some(Err(e)) => {
+                    // The limits are moot now; there’s nothing to keep in sync after this.
+                    self.exceeded = None;
+                    return Some(Err(ExecuteError::Decode(e)));
+                },
+                None => return self.exceeded.take().map(|l| Err(ExecuteError::LimitExceeded(l))),
+            }
+        }
+    }
 }
 
 /// A header block decoder which decodes and executes the instructions against index tables.
@@ -515,10 +747,21 @@ impl<'tables> Decoder<'tables> {
     /// Constructs a new `InstructionExecutor` from the given input and static/dynamic tables.
     pub fn new(input: ByteTendril, tables: &'tables mut Tables)
             -> Decoder<'tables> {
-        InstructionExecutor {
-            instructions: InstructionDecoder::new(input),
-            tables: tables,
-        }
+        InstructionExecutor::from_instructions(InstructionDecoder::new(input), tables)
+    }
+
+    /// Constructs a new `InstructionExecutor` from the given input and static/dynamic tables,
+    /// which will refuse to yield more than the given limits permit.
+    ///
+    /// String literals longer than `limits.max_string_length` are cut short as they are decoded
+    /// (see `InstructionDecoder::limit_strings`).
+    pub fn with_limits(input: ByteTendril, tables: &'tables mut Tables, limits: Limits)
+            -> Decoder<'tables> {
+        let mut instructions = InstructionDecoder::new(input);
+        instructions.limit_strings(limits.max_string_length, tables);
+        let mut decoder = InstructionExecutor::from_instructions(instructions, tables);
+        decoder.set_limits(limits);
+        decoder
     }
 }
 
@@ -543,22 +786,55 @@ pub enum Fragment {
 impl Fragment {
     /// Encode the header block fragment to a writer.
     ///
-    /// If `self` is an `InstructionDecoder` which yields a decode error,
-    /// writing will cease and an I/O error of kind `InvalidData` will be returned.
+    /// For a `Fragment::Decoder`, this writes whatever input it has not yet decoded, as it is,
+    /// just as `into_tendril` would produce; it is not decoded and re-encoded.
     pub fn encode<W: io::Write>(self, writer: &mut W) -> io::Result<()> {
         match self {
-            Fragment::Decoder(decoder) => for instruction in decoder {
-                match instruction {
-                    Ok(i) => try!(i.encode(writer)),
-                    Err(_) => return Err(io::Error::new(io::ErrorKind::InvalidData,
-                                                        "FragmentEncoder hit failed decoding")),
+            Fragment::Decoder(decoder) => writer.write_all(&decoder.input),
+            Fragment::Instructions(vec) => {
+                for instruction in vec {
+                    try!(instruction.encode(writer));
                 }
+                Ok(())
+            },
+        }
+    }
+
+    /// Calculate the length of the encoded fragment, in octets, which is what `encode` will
+    /// write.
+    pub fn encoded_len(&self) -> usize {
+        match *self {
+            Fragment::Decoder(ref decoder) => decoder.input.len(),
+            Fragment::Instructions(ref vec) => {
+                vec.iter().fold(0, |len, instruction| len + instruction.encoded_len())
             },
-            Fragment::Instructions(vec) => for instruction in vec {
-                try!(instruction.encode(writer));
+        }
+    }
+
+    /// Convert the fragment into its encoded form, e.g. for joining fragments together.
+    ///
+    /// For a `Fragment::Decoder`, this is whatever input it has not yet decoded (all of it, unless
+    /// you have been playing with it); `Fragment::Instructions` are encoded.
+    pub fn into_tendril(self) -> ByteTendril {
+        match self {
+            Fragment::Decoder(decoder) => decoder.into_inner(),
+            fragment @ Fragment::Instructions(_) => fragment.to_tendril(),
+        }
+    }
+
+    /// Get the fragment in its encoded form, as `into_tendril` does, but without consuming it.
+    pub fn to_tendril(&self) -> ByteTendril {
+        match *self {
+            Fragment::Decoder(ref decoder) => decoder.input.clone(),
+            Fragment::Instructions(ref vec) => {
+                let mut encoded = Vec::with_capacity(self.encoded_len());
+                for instruction in vec {
+                    // Writing to a Vec<u8> can’t fail.
+                    instruction.encode(&mut encoded).unwrap();
+                }
+                encoded.to_tendril()
             },
         }
-        Ok(())
     }
 }
 
@@ -958,3 +1234,154 @@ t2!(c_5_response_examples_without_huffman_coding,
         entry!(b"set-cookie", b"foo=ASDJKHQKBZXOQWEOPIUAXQWEOIU; max-age=3600; version=1"),
     ];
 });
+
+#[cfg(test)]
+mod limit_tests {
+    use super::{Decoder, Entry, ExecuteError, Index, Instruction, InstructionDecoder, Limit};
+    use super::{Limits, Tables};
+    use {ByteTendril, TendrilSliceExt};
+
+    // The first request of C.3: four entries with a header list size of 42 + 43 + 38 + 57 = 180.
+    const C_3_1: &'static [u8] = b"\x82\x86\x84\x41\x0fwww.example.com";
+
+    fn decode(input: &[u8], tables: &mut Tables, limits: Limits)
+            -> (Vec<Entry>, Option<ExecuteError>) {
+        let mut entries = vec![];
+        let mut error = None;
+        for entry in Decoder::with_limits(ByteTendril::from(input), tables, limits) {
+            match entry {
+                Ok(entry) => entries.push(entry),
+                Err(e) => {
+                    assert_eq!(error, None);
+                    error = Some(e);
+                    if let ExecuteError::Decode(_) = e {
+                        break;
+                    }
+                },
+            }
+        }
+        (entries, error)
+    }
+
+    #[test]
+    fn header_list_size() {
+        let mut tables = Tables::new();
+        let limits = Limits { max_header_list_size: Some(180), ..Limits::default() };
+        let (entries, error) = decode(C_3_1, &mut tables, limits);
+        assert_eq!(entries.len(), 4);
+        assert_eq!(error, None);
+
+        let mut tables = Tables::new();
+        let limits = Limits { max_header_list_size: Some(179), ..Limits::default() };
+        let (entries, error) = decode(C_3_1, &mut tables, limits);
+        assert_eq!(entries, [
+            entry!(b":method", b"GET"),
+            entry!(b":scheme", b"http"),
+            entry!(b":path", b"/"),
+        ]);
+        assert_eq!(error, Some(ExecuteError::LimitExceeded(Limit::HeaderListSize)));
+        // The entry which broke the limit was still inserted into the dynamic table.
+        assert_eq!(tables.size, 57);
+    }
+
+    #[test]
+    fn string_length() {
+        let mut tables = Tables::new();
+        let limits = Limits { max_string_length: Some(14), ..Limits::default() };
+        let (entries, error) = decode(C_3_1, &mut tables, limits);
+        assert_eq!(entries.len(), 3);
+        assert_eq!(error, Some(ExecuteError::LimitExceeded(Limit::StringLength)));
+        assert_eq!(tables.size, 57);
+    }
+
+    #[test]
+    fn entries() {
+        let mut tables = Tables::new();
+        let limits = Limits { max_entries: Some(2), ..Limits::default() };
+        let (entries, error) = decode(C_3_1, &mut tables, limits);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(error, Some(ExecuteError::LimitExceeded(Limit::Entries)));
+        assert_eq!(tables.size, 57);
+    }
+
+    #[test]
+    fn decode_error_trumps_limit() {
+        let mut tables = Tables::new();
+        let limits = Limits { max_entries: Some(0), ..Limits::default() };
+        // Index 62 doesn’t exist yet.
+        let (entries, error) = decode(b"\x82\xbe", &mut tables, limits);
+        assert_eq!(entries.len(), 0);
+        assert_eq!(error, Some(ExecuteError::Decode(super::DecodeError)));
+    }
+
+    #[test]
+    fn repeated_references() {
+        // One entry of 1 + 100 + 32 = 133 octets, then a thousand one-octet references to it.
+        let mut input = b"\x40\x01a\x64".to_vec();
+        input.extend(vec![b'b'; 100]);
+        input.extend(vec![0xbe; 1000]);
+        let mut tables = Tables::new();
+        let limits = Limits { max_header_list_size: Some(10000), ..Limits::default() };
+        let (entries, error) = decode(&input, &mut tables, limits);
+        // 76 × 133 = 10108
+        assert_eq!(entries.len(), 75);
+        assert_eq!(error, Some(ExecuteError::LimitExceeded(Limit::HeaderListSize)));
+        assert_eq!(tables.size, 133);
+    }
+
+    /// A literal header field for `:path` (an indexed name), of the given representation, with a
+    /// plain value of `len` octets.
+    fn long_path(first_octet: u8, len: u32) -> Vec<u8> {
+        let mut block = vec![first_octet];
+        super::integer::encode7(&mut block, 0, len).unwrap();
+        block.extend((0..len).map(|_| b'a'));
+        block
+    }
+
+    #[test]
+    fn long_strings_cut_short() {
+        let limits = Limits { max_string_length: Some(100), ..Limits::default() };
+        // Without indexing, a long value is cut short as it is decoded, the rest skipped…
+        let mut block = long_path(0x04, 5000);
+        block.push(0x82);
+        let mut tables = Tables::new();
+        let mut instructions = InstructionDecoder::new(ByteTendril::from(&*block));
+        instructions.limit_strings(limits.max_string_length, &tables);
+        match instructions.next() {
+            Some(Ok(Instruction::LiteralHeader { value, .. })) => assert_eq!(value.len32(), 101),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(instructions.next(), Some(Ok(Instruction::IndexedHeader {
+            index: unsafe { Index::new(2) },
+        })));
+        // … and the executor finds it too long.
+        let (entries, error) = decode(&block, &mut tables, limits);
+        assert_eq!(entries, []);
+        assert_eq!(error, Some(ExecuteError::LimitExceeded(Limit::StringLength)));
+
+        // With incremental indexing, a value that could go in the tables is decoded in full…
+        let (entries, error) = decode(&long_path(0x44, 200), &mut tables, limits);
+        assert_eq!(entries, []);
+        assert_eq!(error, Some(ExecuteError::LimitExceeded(Limit::StringLength)));
+        assert_eq!(tables.size, 5 + 200 + 32);
+        // … but one that could not is cut short all the same, and empties them as it should.
+        let (_, error) = decode(&long_path(0x44, 5000), &mut tables, limits);
+        assert_eq!(error, Some(ExecuteError::LimitExceeded(Limit::StringLength)));
+        assert_eq!(tables.size, 0);
+    }
+
+    #[test]
+    fn table_size_update_position() {
+        // Two updates at the start of a block are fine; the lower one may evict, the final one
+        // sets the size.
+        let mut tables = Tables::new();
+        let (entries, error) = decode(b"\x20\x3f\xe1\x1f\x82", &mut tables, Limits::default());
+        assert_eq!(entries, [entry!(b":method", b"GET")]);
+        assert_eq!(error, None);
+        // After a header field representation, they are not.
+        let mut tables = Tables::new();
+        let (entries, error) = decode(b"\x82\x20", &mut tables, Limits::default());
+        assert_eq!(entries, [entry!(b":method", b"GET")]);
+        assert_eq!(error, Some(ExecuteError::Decode(super::DecodeError)));
+    }
+}
@@ -3,12 +3,14 @@
 //! Concerning Huffman coded string literals:
 //!
 //! `HuffmanDecoder` operates as an iterator, consuming a `u8` iterator and yielding decoded `u8`s.
+//! `HuffmanEncoder` operates as a writer, wrapping another writer which it writes encoded `u8`s to.
 //!
-//! Huffman encoding is not yet implemented; it is, in fact, rather a low priority, because it is
-//! completely optional. Only decoding is required. I will admit to being baffled as to why
-//! Huffman coding was permitted at all in the specification; its potential space savings are
-//! fairly trivial when compared with the increased complexity.
+//! Huffman encoding is optional, but `encode` uses it whenever it makes the string shorter, which
+//! for typical header values it does. I will admit to being baffled as to why Huffman coding was
+//! permitted at all in the specification; its potential space savings are fairly trivial when
+//! compared with the increased complexity. Still, since we had to decode it anyway…
 
+use std::cmp;
 use std::io;
 use std::mem;
 use std::u32;
@@ -26,28 +28,47 @@ use super::DecodeError;
 /// - Decoding the length fails for any reason;
 /// - The length is greater than the number of remaining bytes;
 /// - Huffman decoding of the value fails for any reason;
-pub fn decode(input: &mut ByteTendril) -> Result<ByteTendril, DecodeError> {
+///
+/// A string longer than `max_len` is cut short, keeping only its first `max_len + 1` octets:
+/// enough to tell that it is too long, without allocating or Huffman decoding any more of it. The
+/// rest is skipped. A Huffman coded string may be up to 8/5 of the length given in its prefix
+/// once decoded, no symbol being shorter than five bits, so it is decoded only as far as that.
+pub fn decode(input: &mut ByteTendril, max_len: Option<u32>)
+        -> Result<ByteTendril, DecodeError> {
     let (huffman, length) = match input.get(0) {
         Some(&b) => (b & 0b10000000 != 0, try!(super::integer::decode7(input))),
         None => return Err(DecodeError),
     };
+    if input.len32() < length {
+        return Err(DecodeError);
+    }
+    let cut = max_len.map_or(u32::MAX, |max_len| max_len.saturating_add(1));
     let output = if huffman {
-        let input_slice = &*input;
-        if input_slice.len() < length as usize {
-            return Err(DecodeError);
-        }
-        let bytes = input_slice[..length as usize].iter().map(|&b| b);
-        try!(HuffmanDecoder::new(bytes).collect())
+        let bytes = input[..length as usize].iter().map(|&b| b);
+        try!(HuffmanDecoder::new(bytes).take(cut as usize).collect())
     } else {
-        match input.try_subtendril(0, length) {
-            Ok(output) => output,
-            Err(_) => return Err(DecodeError),
-        }
+        input.subtendril(0, cmp::min(length, cut))
     };
     input.pop_front(length);
     Ok(output)
 }
 
+/// Encode a string to the specified writer, Huffman encoded if that is shorter.
+pub fn encode<W: io::Write>(w: &mut W, input: &[u8]) -> io::Result<()> {
+    if huffman_encoded_len(input) < input.len() {
+        encode_huffman(w, input)
+    } else {
+        encode_plain(w, input)
+    }
+}
+
+/// Calculate the length of the encoding of a string written by `encode`, in octets (including
+/// the length).
+pub fn encoded_len(input: &[u8]) -> usize {
+    let len = cmp::min(huffman_encoded_len(input), input.len());
+    integer::encoded_len(0b01111111, len as u32) + len
+}
+
 /// Encode a string to the specified writer, not Huffman encoded.
 pub fn encode_plain<W: io::Write>(w: &mut W, input: &[u8]) -> io::Result<()> {
     let len = input.len();
@@ -59,7 +80,108 @@ pub fn encode_plain<W: io::Write>(w: &mut W, input: &[u8]) -> io::Result<()> {
     }
 }
 
-// This Huffman decoder is fast, using large precomputed tables to perform bytewise decoding.
+/// Encode a string to the specified writer, Huffman encoded.
+pub fn encode_huffman<W: io::Write>(w: &mut W, input: &[u8]) -> io::Result<()> {
+    let len = huffman_encoded_len(input);
+    if len <= u32::MAX as usize {
+        try!(integer::encode7(w, 0b10000000, len as u32));
+        let mut encoder = HuffmanEncoder::new(w);
+        try!(io::Write::write_all(&mut encoder, input));
+        encoder.finish().map(|_| ())
+    } else {
+        Err(io::Error::new(io::ErrorKind::InvalidInput, "string is too long to encode"))
+    }
+}
+
+/// Calculate the length of the Huffman encoding of a string, in octets (excluding the length).
+pub fn huffman_encoded_len(input: &[u8]) -> usize {
+    let bits = input.iter().fold(0, |bits, &b| bits + ENCODE_TABLE[b as usize].1 as usize);
+    (bits + 7) / 8
+}
+
+/// The HPACK Huffman encoder.
+///
+/// Write bytes to it and their encoded form will be written to the wrapped writer, a byte at a
+/// time as they are completed. The final partial byte (if any) is padded with the most
+/// significant bits of the EOS symbol (that is, ones) and written when the encoder is dropped;
+/// as `Drop` can’t report errors, call `finish` instead if you care about them.
+pub struct HuffmanEncoder<W: io::Write> {
+    writer: Option<W>,
+    // Only the least significant `bit_count` bits of `bits` are meaningful.
+    // `bit_count` is always less than 8 between writes.
+    bits: u64,
+    bit_count: u8,
+}
+
+impl<W: io::Write> HuffmanEncoder<W> {
+    /// Constructs a new `HuffmanEncoder` writing to the given writer.
+    pub fn new(writer: W) -> HuffmanEncoder<W> {
+        HuffmanEncoder {
+            writer: Some(writer),
+            bits: 0,
+            bit_count: 0,
+        }
+    }
+
+    /// Write the padding and return the wrapped writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        try!(self.write_padding());
+        Ok(self.writer.take().unwrap())
+    }
+
+    fn write_padding(&mut self) -> io::Result<()> {
+        if self.bit_count > 0 {
+            // > As the Huffman-encoded data doesn't always end at an octet
+            // > boundary, some padding is inserted after it, up to the next octet
+            // > boundary.  To prevent this padding from being misinterpreted as
+            // > part of the string literal, the most significant bits of the code
+            // > corresponding to the EOS (end-of-string) symbol are used.
+            let byte = (self.bits << (8 - self.bit_count)) as u8 | 0xff >> self.bit_count;
+            self.bit_count = 0;
+            try!(self.writer.as_mut().unwrap().write_all(&[byte]));
+        }
+        Ok(())
+    }
+}
+
+impl<W: io::Write> io::Write for HuffmanEncoder<W> {
+    fn write(&mut self, input: &[u8]) -> io::Result<usize> {
+        // Each symbol is at most 30 bits, so a 64-byte buffer is plenty for 16 symbols.
+        let mut buf = [0u8; 64];
+        for chunk in input.chunks(16) {
+            let mut len = 0;
+            for &b in chunk {
+                let (code, code_len) = ENCODE_TABLE[b as usize];
+                self.bits = self.bits << code_len | code as u64;
+                self.bit_count += code_len;
+                while self.bit_count >= 8 {
+                    self.bit_count -= 8;
+                    buf[len] = (self.bits >> self.bit_count) as u8;
+                    len += 1;
+                }
+            }
+            try!(self.writer.as_mut().unwrap().write_all(&buf[..len]));
+        }
+        Ok(input.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.as_mut().unwrap().flush()
+    }
+}
+
+impl<W: io::Write> Drop for HuffmanEncoder<W> {
+    fn drop(&mut self) {
+        if self.writer.is_some() {
+            let _ = self.write_padding();
+        }
+    }
+}
+
+// This Huffman decoder is fast, using large precomputed tables to perform bytewise decoding: each
+// step consumes eight bits of input, rather than the one bit at a time of walking the code tree.
+// (huffman-bitwise-iterator-decoder-for-reference-only.rs is an unrolled tree walker, kept for
+// comparison.) The benchmarks below measure it against the `hpack` crate’s decoder.
 //
 // Each table in LOOKUP_TABLES is, as it were, a packed version of [Action; 256] for this Action:
 //
@@ -180,8 +302,10 @@ impl<I: Iterator<Item = u8>> Iterator for HuffmanDecoder<I> {
                     padding = false;
                 },
                 0b11000000_00000000 => {  // padding or table
+                    // Only the rest of a byte we started partway through can be padding; a
+                    // whole byte of ones would be eight bits of padding, and that’s too many.
+                    padding = next_table != 0;
                     next_table = action & 0b00111111_11111111;
-                    padding = true;
                 },
                 _ => {  // decode error (0b10000000_00000000)
                     return Some(Err(DecodeError));
@@ -202,6 +326,7 @@ impl<I: Iterator<Item = u8>> Iterator for HuffmanDecoder<I> {
 
 #[cfg(test)]
 mod huffman {
+    use ByteTendril;
     use super::HuffmanDecoder;
     use super::super::DecodeError;
     extern crate hpack;
@@ -212,6 +337,7 @@ mod huffman {
                 const ENCODED: &'static [u8] = $encoded;
                 const DECODED: &'static [u8] = $decoded;
 
+                use std::io::Write;
                 use super::super::HuffmanDecoder;
                 use test::{self, Bencher};
 
@@ -230,6 +356,24 @@ mod huffman {
                     });
                 }
 
+                #[test]
+                fn encode() {
+                    let mut encoded = vec![];
+                    super::super::HuffmanEncoder::new(&mut encoded).write_all(DECODED).unwrap();
+                    assert_eq!(&*encoded, ENCODED);
+                    assert_eq!(super::super::huffman_encoded_len(DECODED), ENCODED.len());
+                }
+
+                #[bench]
+                fn bench_encode(b: &mut Bencher) {
+                    b.iter(|| {
+                        let mut encoded = Vec::with_capacity(ENCODED.len());
+                        super::super::HuffmanEncoder::new(&mut encoded).write_all(DECODED)
+                                                                      .unwrap();
+                        test::black_box(encoded)
+                    });
+                }
+
                 #[bench]
                 fn hpack_crate(b: &mut Bencher) {
                     let mut decoder = super::hpack::huffman::HuffmanDecoder::new();
@@ -253,9 +397,48 @@ mod huffman {
         }
     }
 
+    #[test]
+    fn encode_picks_the_shorter() {
+        let mut output = vec![];
+        super::encode(&mut output, b"www.example.com").unwrap();
+        assert_eq!(&*output, b"\x8c\xf1\xe3\xc2\xe5\xf2\x3a\x6b\xa0\xab\x90\xf4\xff");
+        assert_eq!(super::encoded_len(b"www.example.com"), output.len());
+
+        // Octets like these take far more than eight bits each to Huffman encode.
+        output.clear();
+        super::encode(&mut output, b"\x00\x01").unwrap();
+        assert_eq!(&*output, b"\x02\x00\x01");
+        assert_eq!(super::encoded_len(b"\x00\x01"), output.len());
+
+        output.clear();
+        super::encode(&mut output, b"").unwrap();
+        assert_eq!(&*output, b"\x00");
+        assert_eq!(super::encoded_len(b""), output.len());
+    }
+
+    #[test]
+    fn decode_cut_short() {
+        // "www.example.com", plain and Huffman coded, then something after it.
+        let plain = b"\x0fwww.example.com\x01x";
+        let huffman = b"\x8c\xf1\xe3\xc2\xe5\xf2\x3a\x6b\xa0\xab\x90\xf4\xff\x01x";
+        for &bytes in &[&plain[..], &huffman[..]] {
+            let mut input = ByteTendril::from_slice(bytes);
+            assert_eq!(&*super::decode(&mut input, Some(15)).unwrap(), b"www.example.com");
+            assert_eq!(&*input, b"\x01x");
+            // Too long, and cut short, but the whole string is skipped all the same.
+            let mut input = ByteTendril::from_slice(bytes);
+            assert_eq!(&*super::decode(&mut input, Some(4)).unwrap(), b"www.e");
+            assert_eq!(&*input, b"\x01x");
+        }
+    }
+
     // A couple of token failing tests. Really we should use something like quickcheck.
     test_fail!(eos, &[0b11111111, 0b11111111, 0b11111111, 0b11111100]);
     test_fail!(bad_padding, &[0b00000111, 0b11111111]);
+    test_fail!(padding_of_zeros, &[0b00000000]);
+    test_fail!(padding_of_a_whole_byte, &[0b11111111]);
+    test_fail!(padding_of_a_whole_byte_after_a_symbol, &[0b11111000, 0b11111111]);
+    test_fail!(eos_in_padding, &[0b00000111, 0b11111111, 0b11111111, 0b11111111, 0b11111111]);
 
     benchmark! {
         moderate,
@@ -657,3 +840,73 @@ static LOOKUP_TABLES: [[u16; 256]; 263] = [
     [757, 757, 757, 757, 757, 757, 757, 757, 757, 757, 757, 757, 757, 757, 757, 757, 757, 757, 757, 757, 757, 757, 757, 757, 757, 757, 757, 757, 757, 757, 757, 757, 757, 757, 757, 757, 757, 757, 757, 757, 757, 757, 757, 757, 757, 757, 757, 757, 757, 757, 757, 757, 757, 757, 757, 757, 757, 757, 757, 757, 757, 757, 757, 757, 758, 758, 758, 758, 758, 758, 758, 758, 758, 758, 758, 758, 758, 758, 758, 758, 758, 758, 758, 758, 758, 758, 758, 758, 758, 758, 758, 758, 758, 758, 758, 758, 758, 758, 758, 758, 758, 758, 758, 758, 758, 758, 758, 758, 758, 758, 758, 758, 758, 758, 758, 758, 758, 758, 758, 758, 758, 758, 758, 758, 758, 758, 758, 758, 759, 759, 759, 759, 759, 759, 759, 759, 759, 759, 759, 759, 759, 759, 759, 759, 759, 759, 759, 759, 759, 759, 759, 759, 759, 759, 759, 759, 759, 759, 759, 759, 759, 759, 759, 759, 759, 759, 759, 759, 759, 759, 759, 759, 759, 759, 759, 759, 759, 759, 759, 759, 759, 759, 759, 759, 759, 759, 759, 759, 759, 759, 759, 759, 760, 760, 760, 760, 760, 760, 760, 760, 760, 760, 760, 760, 760, 760, 760, 760, 760, 760, 760, 760, 760, 760, 760, 760, 760, 760, 760, 760, 760, 760, 760, 760, 760, 760, 760, 760, 760, 760, 760, 760, 760, 760, 760, 760, 760, 760, 760, 760, 760, 760, 760, 760, 760, 760, 760, 760, 760, 760, 760, 760, 760, 760, 760, 760],
     [762, 762, 762, 762, 762, 762, 762, 762, 762, 762, 762, 762, 762, 762, 762, 762, 762, 762, 762, 762, 762, 762, 762, 762, 762, 762, 762, 762, 762, 762, 762, 762, 762, 762, 762, 762, 762, 762, 762, 762, 762, 762, 762, 762, 762, 762, 762, 762, 762, 762, 762, 762, 762, 762, 762, 762, 762, 762, 762, 762, 762, 762, 762, 762, 763, 763, 763, 763, 763, 763, 763, 763, 763, 763, 763, 763, 763, 763, 763, 763, 763, 763, 763, 763, 763, 763, 763, 763, 763, 763, 763, 763, 763, 763, 763, 763, 763, 763, 763, 763, 763, 763, 763, 763, 763, 763, 763, 763, 763, 763, 763, 763, 763, 763, 763, 763, 763, 763, 763, 763, 763, 763, 763, 763, 763, 763, 763, 763, 764, 764, 764, 764, 764, 764, 764, 764, 764, 764, 764, 764, 764, 764, 764, 764, 764, 764, 764, 764, 764, 764, 764, 764, 764, 764, 764, 764, 764, 764, 764, 764, 764, 764, 764, 764, 764, 764, 764, 764, 764, 764, 764, 764, 764, 764, 764, 764, 764, 764, 764, 764, 764, 764, 764, 764, 764, 764, 764, 764, 764, 764, 764, 764, 765, 765, 765, 765, 765, 765, 765, 765, 765, 765, 765, 765, 765, 765, 765, 765, 765, 765, 765, 765, 765, 765, 765, 765, 765, 765, 765, 765, 765, 765, 765, 765, 765, 765, 765, 765, 765, 765, 765, 765, 765, 765, 765, 765, 765, 765, 765, 765, 765, 765, 765, 765, 765, 765, 765, 765, 765, 765, 765, 765, 765, 765, 765, 765],
 ];
+
+// This static is also generated by generate_huffman_lookup_tables.py.
+// It is the code table from RFC 7541, Appendix B, as (code, length in bits); the last is EOS.
+static ENCODE_TABLE: [(u32, u8); 257] = [
+    (0x00001ff8, 13), (0x007fffd8, 23), (0x0fffffe2, 28), (0x0fffffe3, 28),
+    (0x0fffffe4, 28), (0x0fffffe5, 28), (0x0fffffe6, 28), (0x0fffffe7, 28),
+    (0x0fffffe8, 28), (0x00ffffea, 24), (0x3ffffffc, 30), (0x0fffffe9, 28),
+    (0x0fffffea, 28), (0x3ffffffd, 30), (0x0fffffeb, 28), (0x0fffffec, 28),
+    (0x0fffffed, 28), (0x0fffffee, 28), (0x0fffffef, 28), (0x0ffffff0, 28),
+    (0x0ffffff1, 28), (0x0ffffff2, 28), (0x3ffffffe, 30), (0x0ffffff3, 28),
+    (0x0ffffff4, 28), (0x0ffffff5, 28), (0x0ffffff6, 28), (0x0ffffff7, 28),
+    (0x0ffffff8, 28), (0x0ffffff9, 28), (0x0ffffffa, 28), (0x0ffffffb, 28),
+    (0x00000014, 6), (0x000003f8, 10), (0x000003f9, 10), (0x00000ffa, 12),
+    (0x00001ff9, 13), (0x00000015, 6), (0x000000f8, 8), (0x000007fa, 11),
+    (0x000003fa, 10), (0x000003fb, 10), (0x000000f9, 8), (0x000007fb, 11),
+    (0x000000fa, 8), (0x00000016, 6), (0x00000017, 6), (0x00000018, 6),
+    (0x00000000, 5), (0x00000001, 5), (0x00000002, 5), (0x00000019, 6),
+    (0x0000001a, 6), (0x0000001b, 6), (0x0000001c, 6), (0x0000001d, 6),
+    (0x0000001e, 6), (0x0000001f, 6), (0x0000005c, 7), (0x000000fb, 8),
+    (0x00007ffc, 15), (0x00000020, 6), (0x00000ffb, 12), (0x000003fc, 10),
+    (0x00001ffa, 13), (0x00000021, 6), (0x0000005d, 7), (0x0000005e, 7),
+    (0x0000005f, 7), (0x00000060, 7), (0x00000061, 7), (0x00000062, 7),
+    (0x00000063, 7), (0x00000064, 7), (0x00000065, 7), (0x00000066, 7),
+    (0x00000067, 7), (0x00000068, 7), (0x00000069, 7), (0x0000006a, 7),
+    (0x0000006b, 7), (0x0000006c, 7), (0x0000006d, 7), (0x0000006e, 7),
+    (0x0000006f, 7), (0x00000070, 7), (0x00000071, 7), (0x00000072, 7),
+    (0x000000fc, 8), (0x00000073, 7), (0x000000fd, 8), (0x00001ffb, 13),
+    (0x0007fff0, 19), (0x00001ffc, 13), (0x00003ffc, 14), (0x00000022, 6),
+    (0x00007ffd, 15), (0x00000003, 5), (0x00000023, 6), (0x00000004, 5),
+    (0x00000024, 6), (0x00000005, 5), (0x00000025, 6), (0x00000026, 6),
+    (0x00000027, 6), (0x00000006, 5), (0x00000074, 7), (0x00000075, 7),
+    (0x00000028, 6), (0x00000029, 6), (0x0000002a, 6), (0x00000007, 5),
+    (0x0000002b, 6), (0x00000076, 7), (0x0000002c, 6), (0x00000008, 5),
+    (0x00000009, 5), (0x0000002d, 6), (0x00000077, 7), (0x00000078, 7),
+    (0x00000079, 7), (0x0000007a, 7), (0x0000007b, 7), (0x00007ffe, 15),
+    (0x000007fc, 11), (0x00003ffd, 14), (0x00001ffd, 13), (0x0ffffffc, 28),
+    (0x000fffe6, 20), (0x003fffd2, 22), (0x000fffe7, 20), (0x000fffe8, 20),
+    (0x003fffd3, 22), (0x003fffd4, 22), (0x003fffd5, 22), (0x007fffd9, 23),
+    (0x003fffd6, 22), (0x007fffda, 23), (0x007fffdb, 23), (0x007fffdc, 23),
+    (0x007fffdd, 23), (0x007fffde, 23), (0x00ffffeb, 24), (0x007fffdf, 23),
+    (0x00ffffec, 24), (0x00ffffed, 24), (0x003fffd7, 22), (0x007fffe0, 23),
+    (0x00ffffee, 24), (0x007fffe1, 23), (0x007fffe2, 23), (0x007fffe3, 23),
+    (0x007fffe4, 23), (0x001fffdc, 21), (0x003fffd8, 22), (0x007fffe5, 23),
+    (0x003fffd9, 22), (0x007fffe6, 23), (0x007fffe7, 23), (0x00ffffef, 24),
+    (0x003fffda, 22), (0x001fffdd, 21), (0x000fffe9, 20), (0x003fffdb, 22),
+    (0x003fffdc, 22), (0x007fffe8, 23), (0x007fffe9, 23), (0x001fffde, 21),
+    (0x007fffea, 23), (0x003fffdd, 22), (0x003fffde, 22), (0x00fffff0, 24),
+    (0x001fffdf, 21), (0x003fffdf, 22), (0x007fffeb, 23), (0x007fffec, 23),
+    (0x001fffe0, 21), (0x001fffe1, 21), (0x003fffe0, 22), (0x001fffe2, 21),
+    (0x007fffed, 23), (0x003fffe1, 22), (0x007fffee, 23), (0x007fffef, 23),
+    (0x000fffea, 20), (0x003fffe2, 22), (0x003fffe3, 22), (0x003fffe4, 22),
+    (0x007ffff0, 23), (0x003fffe5, 22), (0x003fffe6, 22), (0x007ffff1, 23),
+    (0x03ffffe0, 26), (0x03ffffe1, 26), (0x000fffeb, 20), (0x0007fff1, 19),
+    (0x003fffe7, 22), (0x007ffff2, 23), (0x003fffe8, 22), (0x01ffffec, 25),
+    (0x03ffffe2, 26), (0x03ffffe3, 26), (0x03ffffe4, 26), (0x07ffffde, 27),
+    (0x07ffffdf, 27), (0x03ffffe5, 26), (0x00fffff1, 24), (0x01ffffed, 25),
+    (0x0007fff2, 19), (0x001fffe3, 21), (0x03ffffe6, 26), (0x07ffffe0, 27),
+    (0x07ffffe1, 27), (0x03ffffe7, 26), (0x07ffffe2, 27), (0x00fffff2, 24),
+    (0x001fffe4, 21), (0x001fffe5, 21), (0x03ffffe8, 26), (0x03ffffe9, 26),
+    (0x0ffffffd, 28), (0x07ffffe3, 27), (0x07ffffe4, 27), (0x07ffffe5, 27),
+    (0x000fffec, 20), (0x00fffff3, 24), (0x000fffed, 20), (0x001fffe6, 21),
+    (0x003fffe9, 22), (0x001fffe7, 21), (0x001fffe8, 21), (0x007ffff3, 23),
+    (0x003fffea, 22), (0x003fffeb, 22), (0x01ffffee, 25), (0x01ffffef, 25),
+    (0x00fffff4, 24), (0x00fffff5, 24), (0x03ffffea, 26), (0x007ffff4, 23),
+    (0x03ffffeb, 26), (0x07ffffe6, 27), (0x03ffffec, 26), (0x03ffffed, 26),
+    (0x07ffffe7, 27), (0x07ffffe8, 27), (0x07ffffe9, 27), (0x07ffffea, 27),
+    (0x07ffffeb, 27), (0x0ffffffe, 28), (0x07ffffec, 27), (0x07ffffed, 27),
+    (0x07ffffee, 27), (0x07ffffef, 27), (0x07fffff0, 27), (0x03ffffee, 26),
+    (0x3fffffff, 30),
+];
@@ -83,10 +83,6 @@ macro_rules! flags {
     }
 }
 
-//pub struct Connection;
-
-//pub struct Message;
-
 /// The length of a payload.
 pub enum PayloadSize {
     /// The payload length is known ahead of time and is this value.
@@ -95,6 +91,9 @@ pub enum PayloadSize {
     /// The payload length will only be determined after writing the value.
     /// Since the header comes before the payload, this means that the payload will
     /// need to be written to intermediate storage in order to calculate the length.
+    ///
+    /// None of the frame types defined in this crate need this; it is for those which can’t
+    /// reasonably work out their length before writing themselves.
     Unknown,
 }
 
@@ -118,9 +117,6 @@ pub trait Frame: Sized {
     /// Write the payload to the writer, consuming it.
     fn encode<W: io::Write>(self, w: &mut W) -> io::Result<()>;
 
-    // TODO: is this what we want from the design?
-    //fn apply(connection: &mut Connection) -> Result<Message, ErrorCode> { unimplemented!(); }
-
     /// Write the frame (with a partially complete header).
     fn write_frame<W>(self, mut header: Header<Self::Flags>, w: &mut W) -> io::Result<()>
     where W: io::Write {
@@ -133,11 +129,10 @@ pub trait Frame: Sized {
             },
             PayloadSize::Unknown => {
                 // As the payload size is not known, we must buffer it and calculate the length.
-                // TODO: optimise this in some way (e.g. slab allocation of intermediate vectors).
                 let mut buffer = vec![];
                 try!(self.encode(&mut buffer));
                 header.length = buffer.len() as u32;
-                try!(w.write_all(&buffer));
+                try!(w.write_all(&header.encode()));
                 w.write_all(&buffer)
             },
         }
@@ -338,6 +333,19 @@ pub mod goaway;
 pub mod window_update;
 pub mod continuation;
 
+// RFC 7838, section 4, The ALTSVC HTTP/2 Frame
+pub mod altsvc;
+
+// RFC 8336, section 2, The ORIGIN HTTP/2 Frame
+pub mod origin;
+
+// RFC 9218, section 7, The PRIORITY_UPDATE Frame
+pub mod priority_update;
+
+// RFC 7540, section 5.5, Extending HTTP/2
+pub mod extension;
+use self::extension::{ExtensionFrame, Extensions, Unknown};
+
 macro_rules! define_frame_types {
     ($($path:ident :: $ty:ident),*$(,)*) => {
         /// The payload of a frame.
@@ -345,22 +353,61 @@ macro_rules! define_frame_types {
         #[derive(Debug, Eq, PartialEq)]
         pub enum Payload {
             $(#[allow(missing_docs)] $ty($path::$ty),)*
+            /// A frame of a type registered with `Extensions`.
+            Extension(Box<ExtensionFrame>),
             /// A frame of unknown type, which must be ignored.
-            UnknownType,
+            Unknown(Unknown),
         }
         impl Payload {
-            /// Decode the payload of a frame.
+            /// Decode the payload of a frame, leaving any frame of a type not defined in this
+            /// crate as `Payload::Unknown`.
             pub fn decode(header: Header<u8>, payload: ByteTendril) -> Result<Self, ErrorCode> {
+                Payload::decode_with(header, payload, &Extensions::new())
+            }
+
+            /// Decode the payload of a frame, decoding frames of the types registered in
+            /// `extensions` as `Payload::Extension`.
+            ///
+            /// A malformed ALTSVC or ORIGIN frame is left as `Payload::Unknown` rather than being
+            /// an error: these extensions are not critical, and a receiver that doesn’t want them
+            /// ignores them anyway, just as it ignores frames of unknown types.
+            pub fn decode_with(header: Header<u8>, payload: ByteTendril, extensions: &Extensions)
+                    -> Result<Self, ErrorCode> {
+                match header.type_ {
+                    <altsvc::AltSvc as Frame>::TYPE | <origin::Origin as Frame>::TYPE => {
+                        let raw = payload.clone();
+                        Ok(Payload::decode_known(header, payload, extensions)
+                               .unwrap_or_else(|_| Payload::Unknown(Unknown::decode(header, raw))))
+                    },
+                    _ => Payload::decode_known(header, payload, extensions),
+                }
+            }
+
+            fn decode_known(header: Header<u8>, payload: ByteTendril, extensions: &Extensions)
+                    -> Result<Self, ErrorCode> {
                 match header.type_ {
                     $(<$path::$ty as Frame>::TYPE => {
                         Ok(Payload::$ty(try!(<$path::$ty as Frame>::decode(
                             header.change_flags_type(), payload))))
                     },)*
-                    _ => {
-                        Ok(Payload::UnknownType)
-                        // > […] Implementations MUST ignore
-                        // > and discard any frame that has a type that is unknown.
-                    },
+                    _ => extensions.decode(header, payload),
+                }
+            }
+
+            /// Write the frame to the given stream.
+            ///
+            /// This does no splitting: the frame is written as it is, however large it may be.
+            pub fn write_frame<W>(self, stream_id: StreamId, w: &mut W) -> io::Result<()>
+            where W: io::Write {
+                match self {
+                    $(Payload::$ty(frame) => frame.write_frame(Header {
+                        length: 0,
+                        type_: <$path::$ty as Frame>::TYPE,
+                        flags: From::from(0),
+                        stream_identifier: stream_id,
+                    }, w),)*
+                    Payload::Extension(frame) => frame.write_boxed(stream_id, w),
+                    Payload::Unknown(frame) => frame.write_frame(stream_id, w),
                 }
             }
         }
@@ -378,9 +425,21 @@ define_frame_types! {
     goaway::GoAway,
     window_update::WindowUpdate,
     continuation::Continuation,
+    altsvc::AltSvc,
+    origin::Origin,
+    priority_update::PriorityUpdate,
 }
 
-//const CONNECTION_PRELUDE: &'static [u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+/// The client connection preface, which the client sends before its first SETTINGS frame.
+///
+/// > In HTTP/2, each endpoint is required to send a connection preface as a final confirmation of
+/// > the protocol in use and to establish the initial settings for the HTTP/2 connection.  The
+/// > client and server each send a different connection preface.
+///
+/// The server connection preface is just a SETTINGS frame. See [RFC 7540, section 3.5][spec].
+///
+/// [spec]: http://tools.ietf.org/html/rfc7540#section-3.5
+pub const CONNECTION_PRELUDE: &'static [u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
 
 static PADDING: [u8; 256] = [0; 256];
 
@@ -434,6 +493,8 @@ fn encode_stream_id<W: io::Write>(w: &mut W, leading_bit: bool, stream_id: Strea
 }
 
 pub mod hpack;
+pub mod codec;
+pub mod trace;
 
 #[test]
 fn header_encoding_and_decoding() {
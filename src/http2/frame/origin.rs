@@ -0,0 +1,125 @@
+//! The ORIGIN frame definition. See [RFC 8336, section 2][spec].
+//!
+//! [spec]: http://tools.ietf.org/html/rfc8336#section-2
+
+use std::io;
+
+use ByteTendril;
+use super::{Frame, Header, ErrorCode, PayloadSize, NoFlags};
+
+/// The ORIGIN frame definition. See [RFC 8336, section 2][spec].
+///
+/// This is how a server tells a client which origins it is willing to answer for on the
+/// connection, the origin set. It defines no flags.
+///
+/// > The ORIGIN frame MUST be sent on stream 0; an ORIGIN frame on any other stream is invalid
+/// > and MUST be ignored.
+///
+/// Such frames still decode successfully; ignoring them is up to the receiver.
+///
+/// [spec]: http://tools.ietf.org/html/rfc8336#section-2
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Origin {
+    /// The origins, each in the ASCII serialisation of RFC 6454 (e.g. `https://example.com`).
+    pub origins: Vec<ByteTendril>,
+}
+
+impl Frame for Origin {
+    type Flags = NoFlags;
+    const TYPE: u8 = 0xc;
+
+    fn decode(_header: Header<NoFlags>, mut payload: ByteTendril) -> Result<Origin, ErrorCode> {
+        let mut origins = vec![];
+        while payload.len32() > 0 {
+            if payload.len32() < 2 {
+                return Err(ErrorCode::FRAME_SIZE_ERROR);
+            }
+            let origin_len = (payload[0] as u32) << 8 | payload[1] as u32;
+            payload.pop_front(2);
+            if origin_len > payload.len32() {
+                return Err(ErrorCode::FRAME_SIZE_ERROR);
+            }
+            origins.push(payload.subtendril(0, origin_len));
+            payload.pop_front(origin_len);
+        }
+        Ok(Origin {
+            origins: origins,
+        })
+    }
+
+    fn len(&self) -> PayloadSize {
+        PayloadSize::Exact(self.origins.iter().fold(0, |len, origin| len + 2 + origin.len32()))
+    }
+
+    fn flags(&self) -> NoFlags {
+        NoFlags
+    }
+
+    fn encode<W: io::Write>(self, w: &mut W) -> io::Result<()> {
+        for origin in &self.origins {
+            let origin_len = origin.len32();
+            if origin_len > 0xffff {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "origin is too long"));
+            }
+            try!(w.write_all(&[(origin_len >> 8) as u8, origin_len as u8]));
+            try!(w.write_all(origin));
+        }
+        Ok(())
+    }
+}
+
+frame_tests! {
+    Origin;
+
+    empty {
+        stream 0,
+        payload [];
+
+        Ok(Origin {
+            origins: vec![],
+        })
+    }
+
+    truncated_length {
+        stream 0,
+        payload [0];
+
+        Err(ErrorCode::FRAME_SIZE_ERROR)
+    }
+
+    truncated_origin {
+        stream 0,
+        payload [0, 3, b'a', b'b', b'c', 0, 2, b'd'];
+
+        Err(ErrorCode::FRAME_SIZE_ERROR)
+    }
+
+    one {
+        stream 0,
+        payload [0, 8, b'h', b't', b't', b'p', b':', b'/', b'/', b'a'];
+
+        Ok(Origin {
+            origins: vec![b"http://a".to_tendril()],
+        })
+    }
+
+    several {
+        stream 0,
+        payload [0, 8, b'h', b't', b't', b'p', b':', b'/', b'/', b'a',
+                 0, 0,
+                 0, 9, b'h', b't', b't', b'p', b's', b':', b'/', b'/', b'b'];
+
+        Ok(Origin {
+            origins: vec![b"http://a".to_tendril(), b"".to_tendril(), b"https://b".to_tendril()],
+        })
+    }
+
+    other_stream {
+        stream 1,
+        payload [0, 8, b'h', b't', b't', b'p', b':', b'/', b'/', b'a'];
+
+        Ok(Origin {
+            origins: vec![b"http://a".to_tendril()],
+        })
+    }
+}
@@ -38,13 +38,9 @@ impl Frame for Priority {
         if payload.len32() != 5 {
             return Err(ErrorCode::FRAME_SIZE_ERROR);
         }
+        // A dependency on stream 0 is fine; section 5.3.1 says that is what a stream that does not
+        // depend on any other stream has.
         let stream_dependency = stream_id_from_be_slice!(&*payload, 0);
-        if stream_dependency.0 == 0 {
-            // XXX: the spec *DOES NOT SAY* (in section 6.3, anyway) what should be done here.
-            // Seriously. But it’s obviously illegal to depend on the connection as a whole.
-            // I’m figuring on treating it the same as if header.stream_identifier is 0.
-            return Err(ErrorCode::PROTOCOL_ERROR);
-        }
         Ok(Priority {
             exclusive: payload[0] & 0b10000000 == 0b10000000,
             stream_dependency: stream_dependency,
@@ -102,11 +98,11 @@ frame_tests! {
         Err(ErrorCode::FRAME_SIZE_ERROR)
     }
 
-    needs_nonzero_stream_dependency {
+    depends_on_root {
         stream 1,
         payload [0, 0, 0, 0, 0];
 
-        Err(ErrorCode::PROTOCOL_ERROR)
+        Ok(Priority { exclusive: false, stream_dependency: StreamId(0), weight: 0 })
     }
 
     basic {
@@ -0,0 +1,124 @@
+//! The PRIORITY_UPDATE frame definition. See [RFC 9218, section 7.1][spec].
+//!
+//! [spec]: http://tools.ietf.org/html/rfc9218#section-7.1
+
+use std::io;
+
+use ByteTendril;
+use super::{Frame, Header, ErrorCode, PayloadSize, NoFlags};
+use super::super::stream::StreamId;
+
+/// The PRIORITY_UPDATE frame definition. See [RFC 9218, section 7.1][spec].
+///
+/// This is how a client changes the priority of a request, replacing the PRIORITY frame of RFC
+/// 7540. It is always sent on stream 0.
+///
+/// [spec]: http://tools.ietf.org/html/rfc9218#section-7.1
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PriorityUpdate {
+    /// The stream whose priority is being updated.
+    pub prioritized_stream_id: StreamId,
+
+    /// The new priority, in the same format as the `Priority` header field; see
+    /// `headers::priority::Priority` for parsing it.
+    pub priority_field_value: ByteTendril,
+}
+
+impl Frame for PriorityUpdate {
+    type Flags = NoFlags;
+    const TYPE: u8 = 0x10;
+
+    fn decode(header: Header<NoFlags>, mut payload: ByteTendril)
+            -> Result<PriorityUpdate, ErrorCode> {
+        // > The PRIORITY_UPDATE frame is always sent on stream 0 […]. If a PRIORITY_UPDATE frame
+        // > is received with a stream ID other than 0x0, the recipient MUST respond with a
+        // > connection error of type PROTOCOL_ERROR.
+        if header.stream_identifier.0 != 0 {
+            return Err(ErrorCode::PROTOCOL_ERROR);
+        }
+        if payload.len32() < 4 {
+            return Err(ErrorCode::FRAME_SIZE_ERROR);
+        }
+        // > If a PRIORITY_UPDATE frame is received with a Prioritized Stream ID of 0x0, the
+        // > recipient MUST respond with a connection error of type PROTOCOL_ERROR.
+        let prioritized_stream_id = stream_id_from_be_slice!(&*payload, 0);
+        if prioritized_stream_id.0 == 0 {
+            return Err(ErrorCode::PROTOCOL_ERROR);
+        }
+        payload.pop_front(4);
+        Ok(PriorityUpdate {
+            prioritized_stream_id: prioritized_stream_id,
+            priority_field_value: payload,
+        })
+    }
+
+    fn len(&self) -> PayloadSize {
+        PayloadSize::Exact(4 + self.priority_field_value.len32())
+    }
+
+    fn flags(&self) -> NoFlags {
+        NoFlags
+    }
+
+    fn encode<W: io::Write>(self, w: &mut W) -> io::Result<()> {
+        try!(w.write_all(&[
+            (self.prioritized_stream_id.0 >> 24) as u8,
+            (self.prioritized_stream_id.0 >> 16) as u8,
+            (self.prioritized_stream_id.0 >> 8) as u8,
+            self.prioritized_stream_id.0 as u8,
+        ]));
+        w.write_all(&self.priority_field_value)
+    }
+}
+
+frame_tests! {
+    PriorityUpdate;
+
+    applies_to_connection {
+        stream 1,
+        payload [0, 0, 0, 1];
+
+        Err(ErrorCode::PROTOCOL_ERROR)
+    }
+
+    frame_size_zero {
+        stream 0,
+        payload [];
+
+        Err(ErrorCode::FRAME_SIZE_ERROR)
+    }
+
+    frame_size_three {
+        stream 0,
+        payload [0, 0, 1];
+
+        Err(ErrorCode::FRAME_SIZE_ERROR)
+    }
+
+    needs_nonzero_prioritized_stream_id {
+        stream 0,
+        payload [0, 0, 0, 0, b'u', b'=', b'0'];
+
+        Err(ErrorCode::PROTOCOL_ERROR)
+    }
+
+    empty_field_value {
+        stream 0,
+        payload [0, 0, 0, 1];
+
+        Ok(PriorityUpdate {
+            prioritized_stream_id: StreamId(1),
+            priority_field_value: b"".to_tendril(),
+        })
+    }
+
+    basic {
+        stream 0,
+        payload [0x01, 0x23, 0x45, 0x67, b'u', b'=', b'5', b',', b' ', b'i'];
+
+        Ok(PriorityUpdate {
+            prioritized_stream_id: StreamId(0x01234567),
+            priority_field_value: b"u=5, i".to_tendril(),
+        })
+    }
+}
@@ -46,7 +46,20 @@ impl Frame for PushPromise {
     const TYPE: u8 = 0x5;
 
     fn decode(header: Header<Flags>, mut payload: ByteTendril) -> Result<Self, ErrorCode> {
-        let pad_length = try!(decode_padding(header.flags.contains(PADDED), &mut payload));
+        // > An endpoint MUST send an error code of FRAME_SIZE_ERROR if a frame … is too small to
+        // > contain mandatory frame data.
+        //
+        // The promised stream identifier takes four octets.
+        let padded = header.flags.contains(PADDED);
+        if payload.len32() < padded as u32 + 4 {
+            return Err(ErrorCode::FRAME_SIZE_ERROR);
+        }
+        let pad_length = try!(decode_padding(padded, &mut payload));
+        // > Padding that exceeds the size remaining for the header block fragment MUST be treated
+        // > as a PROTOCOL_ERROR.
+        if payload.len32() < 4 {
+            return Err(ErrorCode::PROTOCOL_ERROR);
+        }
         let promised_stream_id = stream_id_from_be_slice!(&*payload, 0);
         payload.pop_front(4);
         Ok(PushPromise {
@@ -58,7 +71,9 @@ impl Frame for PushPromise {
     }
 
     fn len(&self) -> PayloadSize {
-        PayloadSize::Unknown
+        let padding = self.pad_length.map_or(0, |pad_length| pad_length as u32 + 1);
+        // The promised stream identifier takes four octets.
+        PayloadSize::Exact(padding + 4 + self.header_block.encoded_len() as u32)
     }
 
     fn flags(&self) -> Flags {
@@ -79,3 +94,37 @@ impl Frame for PushPromise {
         encode_padding(w, self.pad_length)
     }
 }
+
+frame_tests! {
+    PushPromise;
+
+    simple {
+        flags END_HEADERS,
+        stream 1,
+        payload [0, 0, 0, 2, 0x82];
+
+        Ok(PushPromise {
+            pad_length: None,
+            end_headers: true,
+            promised_stream_id: StreamId(2),
+            header_block: hpack::Fragment::Decoder(hpack::InstructionDecoder::new(
+                [0x82].to_tendril())),
+        })
+    }
+
+    too_small_for_promised_stream_id {
+        flags PADDED,
+        stream 1,
+        payload [0, 0, 0];
+
+        Err(ErrorCode::FRAME_SIZE_ERROR)
+    }
+
+    padding_too_large_for_promised_stream_id {
+        flags PADDED,
+        stream 1,
+        payload [1, 0, 0, 0, 2];
+
+        Err(ErrorCode::PROTOCOL_ERROR)
+    }
+}
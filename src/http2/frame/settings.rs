@@ -19,6 +19,8 @@ const SETTINGS_MAX_CONCURRENT_STREAMS: u16 = 0x3;
 const SETTINGS_INITIAL_WINDOW_SIZE: u16 = 0x4;
 const SETTINGS_MAX_FRAME_SIZE: u16 = 0x5;
 const SETTINGS_MAX_HEADER_LIST_SIZE: u16 = 0x6;
+const SETTINGS_ENABLE_CONNECT_PROTOCOL: u16 = 0x8;
+const SETTINGS_NO_RFC7540_PRIORITIES: u16 = 0x9;
 
 /// The SETTINGS frame definition. See [RFC 7540, section 6.5][spec].
 ///
@@ -81,6 +83,113 @@ pub enum Settings {
         /// [6.5.2]: http://tools.ietf.org/html/rfc7540#section-6.5.2
         /// [chunk extensions]: http://tools.ietf.org/html/rfc7230#section-4.1.1
         max_header_list_size: Option<u32>,
+
+        /// The ENABLE_CONNECT_PROTOCOL setting, from [RFC 8441, section 3][spec], says that the
+        /// sender (which will be a server) supports the extended CONNECT method, with its
+        /// `:protocol` pseudo-header field, as used to bootstrap WebSockets. The default is
+        /// *false*, and once it has been set to true it may not be set to false again.
+        ///
+        /// [spec]: http://tools.ietf.org/html/rfc8441#section-3
+        enable_connect_protocol: Option<bool>,
+
+        /// The NO_RFC7540_PRIORITIES setting, from [RFC 9218, section 2.1][spec], says that the
+        /// sender is not using the RFC 7540 priority scheme (PRIORITY frames and the priority
+        /// fields of HEADERS frames), but rather the `Priority` header field and PRIORITY_UPDATE
+        /// frames. The default is *false*, and it may not be changed after the first SETTINGS
+        /// frame.
+        ///
+        /// [spec]: http://tools.ietf.org/html/rfc9218#section-2.1
+        no_rfc7540_priorities: Option<bool>,
+
+        /// Any other settings, as identifier/value pairs, in the order they appear in the frame.
+        ///
+        /// > An endpoint that receives a SETTINGS frame with any unknown or unsupported
+        /// > identifier MUST ignore that setting.
+        ///
+        /// Ignoring them is up to the recipient; they are kept so that they can be observed, and so
+        /// that extensions this doesn’t know about can be sent. Settings which have fields of
+        /// their own should not be put here.
+        unknown: Vec<(u16, u32)>,
+    }
+}
+
+/// The current value of each setting, as opposed to the changes that a SETTINGS frame makes.
+///
+/// Each endpoint has its own set of values; `Values::default()` is what they all start out as.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Values {
+    /// SETTINGS_HEADER_TABLE_SIZE; initially 4,096.
+    pub header_table_size: u32,
+    /// SETTINGS_ENABLE_PUSH; initially true.
+    pub enable_push: bool,
+    /// SETTINGS_MAX_CONCURRENT_STREAMS; initially unlimited (`None`).
+    pub max_concurrent_streams: Option<u32>,
+    /// SETTINGS_INITIAL_WINDOW_SIZE; initially 65,535.
+    pub initial_window_size: u32,
+    /// SETTINGS_MAX_FRAME_SIZE; initially 16,384.
+    pub max_frame_size: u32,
+    /// SETTINGS_MAX_HEADER_LIST_SIZE; initially unlimited (`None`).
+    pub max_header_list_size: Option<u32>,
+    /// SETTINGS_ENABLE_CONNECT_PROTOCOL; initially false.
+    pub enable_connect_protocol: bool,
+    /// SETTINGS_NO_RFC7540_PRIORITIES; initially false.
+    pub no_rfc7540_priorities: bool,
+}
+
+impl Default for Values {
+    fn default() -> Values {
+        Values {
+            header_table_size: 4096,
+            enable_push: true,
+            max_concurrent_streams: None,
+            initial_window_size: 65535,
+            max_frame_size: 16384,
+            max_header_list_size: None,
+            enable_connect_protocol: false,
+            no_rfc7540_priorities: false,
+        }
+    }
+}
+
+impl Values {
+    /// Apply the changes of a SETTINGS frame. An acknowledgment changes nothing.
+    pub fn apply(&mut self, settings: &Settings) {
+        if let Settings::Parameters {
+            header_table_size,
+            enable_push,
+            max_concurrent_streams,
+            initial_window_size,
+            max_frame_size,
+            max_header_list_size,
+            enable_connect_protocol,
+            no_rfc7540_priorities,
+            ..
+        } = *settings {
+            if let Some(value) = header_table_size {
+                self.header_table_size = value;
+            }
+            if let Some(value) = enable_push {
+                self.enable_push = value;
+            }
+            if let Some(value) = max_concurrent_streams {
+                self.max_concurrent_streams = Some(value);
+            }
+            if let Some(value) = initial_window_size {
+                self.initial_window_size = value;
+            }
+            if let Some(value) = max_frame_size {
+                self.max_frame_size = value;
+            }
+            if let Some(value) = max_header_list_size {
+                self.max_header_list_size = Some(value);
+            }
+            if let Some(value) = enable_connect_protocol {
+                self.enable_connect_protocol = value;
+            }
+            if let Some(value) = no_rfc7540_priorities {
+                self.no_rfc7540_priorities = value;
+            }
+        }
     }
 }
 
@@ -117,6 +226,9 @@ impl Frame for Settings {
             let mut initial_window_size = None;
             let mut max_frame_size = None;
             let mut max_header_list_size = None;
+            let mut enable_connect_protocol = None;
+            let mut no_rfc7540_priorities = None;
+            let mut unknown = vec![];
 
             let payload = &*payload;
             let mut i = 0;
@@ -164,9 +276,34 @@ impl Frame for Settings {
 
                     SETTINGS_MAX_HEADER_LIST_SIZE => max_header_list_size = Some(value),
 
+                    // > The value of the parameter MUST be 0 or 1.
+                    //
+                    // RFC 8441 doesn’t say what to do with other values, but the other boolean
+                    // settings make them PROTOCOL_ERROR, and so shall this.
+                    SETTINGS_ENABLE_CONNECT_PROTOCOL => {
+                        match value {
+                            0 => enable_connect_protocol = Some(false),
+                            1 => enable_connect_protocol = Some(true),
+                            _ => return Err(ErrorCode::PROTOCOL_ERROR),
+                        }
+                    },
+
+                    // > The value of the parameter MUST be 0 or 1.  Any value other than 0 or 1
+                    // > MUST be treated as a connection error (Section 5.4.1 of [HTTP/2]) of type
+                    // > PROTOCOL_ERROR.
+                    SETTINGS_NO_RFC7540_PRIORITIES => {
+                        match value {
+                            0 => no_rfc7540_priorities = Some(false),
+                            1 => no_rfc7540_priorities = Some(true),
+                            _ => return Err(ErrorCode::PROTOCOL_ERROR),
+                        }
+                    },
+
                     // > An endpoint that receives a SETTINGS frame with any unknown or
                     // > unsupported identifier MUST ignore that setting.
-                    _ => (),
+                    //
+                    // It is kept, but nothing will pay any attention to it.
+                    _ => unknown.push((identifier, value)),
                 }
                 i += 6;
             }
@@ -178,6 +315,9 @@ impl Frame for Settings {
                 initial_window_size: initial_window_size,
                 max_frame_size: max_frame_size,
                 max_header_list_size: max_header_list_size,
+                enable_connect_protocol: enable_connect_protocol,
+                no_rfc7540_priorities: no_rfc7540_priorities,
+                unknown: unknown,
             })
         }
     }
@@ -192,6 +332,9 @@ impl Frame for Settings {
                 initial_window_size,
                 max_frame_size,
                 max_header_list_size,
+                enable_connect_protocol,
+                no_rfc7540_priorities,
+                ref unknown,
             } => {
                 let mut len = 0;
                 if header_table_size.is_some() {
@@ -212,7 +355,13 @@ impl Frame for Settings {
                 if max_header_list_size.is_some() {
                     len += 6;
                 }
-                len
+                if enable_connect_protocol.is_some() {
+                    len += 6;
+                }
+                if no_rfc7540_priorities.is_some() {
+                    len += 6;
+                }
+                len + 6 * unknown.len() as u32
             }
         })
     }
@@ -234,21 +383,24 @@ impl Frame for Settings {
             initial_window_size,
             max_frame_size,
             max_header_list_size,
+            enable_connect_protocol,
+            no_rfc7540_priorities,
+            unknown,
         } = self {
-            // Six bytes per setting, six possible settings, maximum write size of 36 bytes.
-            let mut buf = [0; 36];
-            let mut i = 0;
+            // Six bytes per setting; with unknown settings, there could be any number of them.
+            let mut buf = Vec::with_capacity(6 * (8 + unknown.len()));
             macro_rules! w {
-                ($value:expr, $identifier:ident) => {
+                ($value:expr, $identifier:expr) => {
                     if let Some(value) = $value {
                         let value = value as u32;
-                        buf[i] = ($identifier >> 8) as u8;
-                        buf[i + 1] = $identifier as u8;
-                        buf[i + 2] = (value >> 24) as u8;
-                        buf[i + 3] = (value >> 16) as u8;
-                        buf[i + 4] = (value >> 8) as u8;
-                        buf[i + 5] = value as u8;
-                        i += 6;
+                        buf.extend_from_slice(&[
+                            ($identifier >> 8) as u8,
+                            $identifier as u8,
+                            (value >> 24) as u8,
+                            (value >> 16) as u8,
+                            (value >> 8) as u8,
+                            value as u8,
+                        ]);
                     }
                 }
             }
@@ -258,7 +410,12 @@ impl Frame for Settings {
             w!(initial_window_size, SETTINGS_INITIAL_WINDOW_SIZE);
             w!(max_frame_size, SETTINGS_MAX_FRAME_SIZE);
             w!(max_header_list_size, SETTINGS_MAX_HEADER_LIST_SIZE);
-            w.write_all(&buf[..i])
+            w!(enable_connect_protocol, SETTINGS_ENABLE_CONNECT_PROTOCOL);
+            w!(no_rfc7540_priorities, SETTINGS_NO_RFC7540_PRIORITIES);
+            for (identifier, value) in unknown {
+                w!(Some(value), identifier);
+            }
+            w.write_all(&buf)
         } else {
             Ok(())
         }
@@ -344,6 +501,9 @@ frame_tests! {
             initial_window_size: None,
             max_frame_size: None,
             max_header_list_size: None,
+            enable_connect_protocol: None,
+            no_rfc7540_priorities: None,
+            unknown: vec![],
         })
     }
 
@@ -359,12 +519,13 @@ frame_tests! {
             initial_window_size: None,
             max_frame_size: None,
             max_header_list_size: None,
+            enable_connect_protocol: None,
+            no_rfc7540_priorities: None,
+            unknown: vec![],
         })
     }
 
     unknown_setting {
-        decode only,
-
         flags Flags::empty(),
         stream 0,
         payload [0; 6];
@@ -376,6 +537,9 @@ frame_tests! {
             initial_window_size: None,
             max_frame_size: None,
             max_header_list_size: None,
+            enable_connect_protocol: None,
+            no_rfc7540_priorities: None,
+            unknown: vec![(0, 0)],
         })
     }
 
@@ -394,6 +558,9 @@ frame_tests! {
             initial_window_size: None,
             max_frame_size: None,
             max_header_list_size: None,
+            enable_connect_protocol: None,
+            no_rfc7540_priorities: None,
+            unknown: vec![],
         })
     }
 
@@ -418,6 +585,9 @@ frame_tests! {
             initial_window_size: Some(0x3456789a),
             max_frame_size: Some(0x6789ab),
             max_header_list_size: Some(0x56789abc),
+            enable_connect_protocol: None,
+            no_rfc7540_priorities: None,
+            unknown: vec![(0x0908, 0), (0x0102, 0)],
         })
     }
 
@@ -438,9 +608,43 @@ frame_tests! {
             initial_window_size: Some(0x3456789a),
             max_frame_size: Some(0x6789ab),
             max_header_list_size: Some(0x56789abc),
+            enable_connect_protocol: None,
+            no_rfc7540_priorities: None,
+            unknown: vec![],
+        })
+    }
+
+    extension_settings {
+        flags Flags::empty(),
+        stream 0,
+        payload [0, 6, 0x00, 0x00, 0x10, 0x00,
+                 0, 8, 0x00, 0x00, 0x00, 0x01,
+                 0, 9, 0x00, 0x00, 0x00, 0x00,
+                 0xf0, 0x0d, 0x12, 0x34, 0x56, 0x78,
+                 0x00, 0x07, 0x00, 0x00, 0x00, 0x2a,
+                 0xf0, 0x0d, 0x9a, 0xbc, 0xde, 0xf0];
+
+        Ok(Settings::Parameters {
+            header_table_size: None,
+            enable_push: None,
+            max_concurrent_streams: None,
+            initial_window_size: None,
+            max_frame_size: None,
+            max_header_list_size: Some(4096),
+            enable_connect_protocol: Some(true),
+            no_rfc7540_priorities: Some(false),
+            unknown: vec![(0xf00d, 0x12345678), (0x0007, 42), (0xf00d, 0x9abcdef0)],
         })
     }
 
+    bad_enable_connect_protocol {
+        flags Flags::empty(),
+        stream 0,
+        payload [0, 8, 0x00, 0x00, 0x00, 0x02];
+
+        Err(ErrorCode::PROTOCOL_ERROR)
+    }
+
     enable_push_false {
         flags Flags::empty(),
         stream 0,
@@ -453,6 +657,9 @@ frame_tests! {
             initial_window_size: None,
             max_frame_size: None,
             max_header_list_size: None,
+            enable_connect_protocol: None,
+            no_rfc7540_priorities: None,
+            unknown: vec![],
         })
     }
 
@@ -464,6 +671,32 @@ frame_tests! {
         Err(ErrorCode::PROTOCOL_ERROR)
     }
 
+    no_rfc7540_priorities {
+        flags Flags::empty(),
+        stream 0,
+        payload [0, 9, 0x00, 0x00, 0x00, 0x01];
+
+        Ok(Settings::Parameters {
+            header_table_size: None,
+            enable_push: None,
+            max_concurrent_streams: None,
+            initial_window_size: None,
+            max_frame_size: None,
+            max_header_list_size: None,
+            enable_connect_protocol: None,
+            no_rfc7540_priorities: Some(true),
+            unknown: vec![],
+        })
+    }
+
+    bad_no_rfc7540_priorities {
+        flags Flags::empty(),
+        stream 0,
+        payload [0, 9, 0x00, 0x00, 0x00, 0x02];
+
+        Err(ErrorCode::PROTOCOL_ERROR)
+    }
+
     maximal_initial_window_size {
         flags Flags::empty(),
         stream 0,
@@ -476,6 +709,9 @@ frame_tests! {
             initial_window_size: Some(0x7fffffff),
             max_frame_size: None,
             max_header_list_size: None,
+            enable_connect_protocol: None,
+            no_rfc7540_priorities: None,
+            unknown: vec![],
         })
     }
 
@@ -499,6 +735,9 @@ frame_tests! {
             initial_window_size: None,
             max_frame_size: Some(0x00ffffff),
             max_header_list_size: None,
+            enable_connect_protocol: None,
+            no_rfc7540_priorities: None,
+            unknown: vec![],
         })
     }
 
@@ -522,6 +761,9 @@ frame_tests! {
             initial_window_size: None,
             max_frame_size: Some(0x00004000),
             max_header_list_size: None,
+            enable_connect_protocol: None,
+            no_rfc7540_priorities: None,
+            unknown: vec![],
         })
     }
 
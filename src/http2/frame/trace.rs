@@ -0,0 +1,439 @@
+//! A human-readable trace of frames, for seeing what crossed the wire.
+//!
+//! Each `Payload` displays as a single line naming the frame type, its flags and its fields, in
+//! the terms of the RFC that defines it; the stream identifier is in the frame header, not the
+//! payload, so it is not included. Header blocks can only be decoded in order, with the decoding
+//! context of the connection they came from, so that is the business of a `Tracer`, which follows
+//! the frames sent in one direction on a connection and adds the instructions and the header list
+//! of each header block as it is completed.
+//!
+//! ```text
+//! stream 0: SETTINGS max_concurrent_streams=100 initial_window_size=65535
+//! stream 1: HEADERS end_stream end_headers header_block=18
+//!     instructions:
+//!         indexed 2
+//!         literal with incremental indexing name=[1] value="www.example.com"
+//!     header list:
+//!         :method: GET
+//!         :authority: www.example.com
+//! ```
+
+use std::cmp;
+use std::fmt;
+use std::u32;
+
+use ByteTendril;
+use http2::stream::StreamId;
+use super::Payload;
+use super::hpack::{self, Fragment, Instruction, InstructionDecoder, LiteralHeaderMode};
+use super::hpack::LiteralHeaderName;
+use super::settings::Settings;
+
+/// How many octets of DATA are shown; the rest is elided.
+const DATA_PREVIEW_LEN: usize = 32;
+
+/// Octets shown as text, with anything but printable ASCII escaped as in a Rust byte string.
+///
+/// The quotes are not included.
+pub struct Octets<'a>(pub &'a [u8]);
+
+impl<'a> fmt::Display for Octets<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for &b in self.0 {
+            try!(match b {
+                b'"' => f.write_str("\\\""),
+                b'\\' => f.write_str("\\\\"),
+                b'\t' => f.write_str("\\t"),
+                b'\r' => f.write_str("\\r"),
+                b'\n' => f.write_str("\\n"),
+                b' '...b'~' => fmt::Write::write_char(f, b as char),
+                _ => write!(f, "\\x{:02x}", b),
+            });
+        }
+        Ok(())
+    }
+}
+
+fn flag(f: &mut fmt::Formatter, name: &str, set: bool) -> fmt::Result {
+    if set {
+        write!(f, " {}", name)
+    } else {
+        Ok(())
+    }
+}
+
+fn pad_length(f: &mut fmt::Formatter, pad_length: Option<u8>) -> fmt::Result {
+    match pad_length {
+        Some(pad_length) => write!(f, " pad_length={}", pad_length),
+        None => Ok(()),
+    }
+}
+
+fn setting(f: &mut fmt::Formatter, name: &str, value: Option<u32>) -> fmt::Result {
+    match value {
+        Some(value) => write!(f, " {}={}", name, value),
+        None => Ok(()),
+    }
+}
+
+impl fmt::Display for Payload {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Payload::Data(ref frame) => {
+                try!(f.write_str("DATA"));
+                try!(flag(f, "end_stream", frame.end_stream));
+                try!(pad_length(f, frame.pad_length));
+                let preview_len = cmp::min(frame.data.len(), DATA_PREVIEW_LEN);
+                try!(write!(f, " length={} data=\"{}\"", frame.data.len32(),
+                            Octets(&frame.data[..preview_len])));
+                if preview_len < frame.data.len() {
+                    try!(f.write_str("..."));
+                }
+                Ok(())
+            },
+            Payload::Headers(ref frame) => {
+                try!(f.write_str("HEADERS"));
+                try!(flag(f, "end_stream", frame.end_stream));
+                try!(flag(f, "end_headers", frame.end_headers));
+                try!(pad_length(f, frame.pad_length));
+                if let Some(ref priority) = frame.priority {
+                    try!(write!(f, " priority=(dependency={} weight={}",
+                                priority.stream_dependency.0, priority.weight as u32 + 1));
+                    try!(flag(f, "exclusive", priority.exclusive));
+                    try!(f.write_str(")"));
+                }
+                write!(f, " header_block={}", frame.header_block.encoded_len())
+            },
+            Payload::Priority(ref frame) => {
+                try!(write!(f, "PRIORITY dependency={} weight={}", frame.stream_dependency.0,
+                            frame.weight as u32 + 1));
+                flag(f, "exclusive", frame.exclusive)
+            },
+            Payload::RstStream(ref frame) => {
+                write!(f, "RST_STREAM error_code={:?}", frame.error_code)
+            },
+            Payload::Settings(Settings::Acknowledgment) => f.write_str("SETTINGS ack"),
+            Payload::Settings(Settings::Parameters {
+                header_table_size,
+                enable_push,
+                max_concurrent_streams,
+                initial_window_size,
+                max_frame_size,
+                max_header_list_size,
+                enable_connect_protocol,
+                no_rfc7540_priorities,
+                ref unknown,
+            }) => {
+                try!(f.write_str("SETTINGS"));
+                try!(setting(f, "header_table_size", header_table_size));
+                try!(setting(f, "enable_push", enable_push.map(|value| value as u32)));
+                try!(setting(f, "max_concurrent_streams", max_concurrent_streams));
+                try!(setting(f, "initial_window_size", initial_window_size));
+                try!(setting(f, "max_frame_size", max_frame_size));
+                try!(setting(f, "max_header_list_size", max_header_list_size));
+                try!(setting(f, "enable_connect_protocol",
+                             enable_connect_protocol.map(|value| value as u32)));
+                try!(setting(f, "no_rfc7540_priorities",
+                             no_rfc7540_priorities.map(|value| value as u32)));
+                for &(identifier, value) in unknown {
+                    try!(write!(f, " 0x{:04x}={}", identifier, value));
+                }
+                Ok(())
+            },
+            Payload::PushPromise(ref frame) => {
+                try!(write!(f, "PUSH_PROMISE promised_stream_id={}", frame.promised_stream_id.0));
+                try!(flag(f, "end_headers", frame.end_headers));
+                try!(pad_length(f, frame.pad_length));
+                write!(f, " header_block={}", frame.header_block.encoded_len())
+            },
+            Payload::Ping(ref frame) => {
+                try!(f.write_str("PING"));
+                try!(flag(f, "ack", frame.is_response));
+                write!(f, " data=\"{}\"", Octets(&frame.data))
+            },
+            Payload::GoAway(ref frame) => {
+                try!(write!(f, "GOAWAY last_stream_id={} error_code={:?}", frame.last_stream_id.0,
+                            frame.error_code));
+                if frame.additional_debug_data.len32() > 0 {
+                    try!(write!(f, " additional_debug_data=\"{}\"",
+                                Octets(&frame.additional_debug_data)));
+                }
+                Ok(())
+            },
+            Payload::WindowUpdate(ref frame) => {
+                write!(f, "WINDOW_UPDATE window_size_increment={}", frame.window_size_increment)
+            },
+            Payload::Continuation(ref frame) => {
+                try!(f.write_str("CONTINUATION"));
+                try!(flag(f, "end_headers", frame.end_headers));
+                write!(f, " header_block={}", frame.header_block.encoded_len())
+            },
+            Payload::AltSvc(ref frame) => {
+                write!(f, "ALTSVC origin=\"{}\" field_value=\"{}\"", Octets(&frame.origin),
+                       Octets(&frame.field_value))
+            },
+            Payload::Origin(ref frame) => {
+                try!(f.write_str("ORIGIN"));
+                for origin in &frame.origins {
+                    try!(write!(f, " \"{}\"", Octets(origin)));
+                }
+                Ok(())
+            },
+            Payload::PriorityUpdate(ref frame) => {
+                write!(f, "PRIORITY_UPDATE prioritized_stream_id={} priority_field_value=\"{}\"",
+                       frame.prioritized_stream_id.0, Octets(&frame.priority_field_value))
+            },
+            // All that is known of an extension frame is what its `Debug` shows.
+            Payload::Extension(ref frame) => {
+                write!(f, "type=0x{:02x} {:?}", frame.type_(), frame)
+            },
+            Payload::Unknown(ref frame) => {
+                write!(f, "type=0x{:02x} flags=0x{:02x} length={}", frame.type_, frame.flags,
+                       frame.payload.len32())
+            },
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Instruction::IndexedHeader { index } => write!(f, "indexed {}", *index),
+            Instruction::LiteralHeader { mode, ref name, ref value } => {
+                try!(f.write_str(match mode {
+                    LiteralHeaderMode::IncrementalIndexing => "literal with incremental indexing",
+                    LiteralHeaderMode::WithoutIndexing => "literal without indexing",
+                    LiteralHeaderMode::NeverIndexed => "literal never indexed",
+                }));
+                try!(match *name {
+                    LiteralHeaderName::Index(index) => write!(f, " name=[{}]", *index),
+                    LiteralHeaderName::Literal(ref name) => write!(f, " name=\"{}\"", Octets(name)),
+                });
+                write!(f, " value=\"{}\"", Octets(value))
+            },
+            Instruction::DynamicTableSizeUpdate { max_size } => {
+                write!(f, "dynamic table size update {}", max_size)
+            },
+        }
+    }
+}
+
+/// Traces the frames sent in one direction on a connection, decoding header blocks as they are
+/// completed.
+pub struct Tracer {
+    tables: hpack::Tables,
+    /// The header block under way, so far.
+    header_block: Option<ByteTendril>,
+}
+
+impl Tracer {
+    /// Constructs a tracer for the first frame sent in one direction on a connection.
+    ///
+    /// Not knowing what SETTINGS_HEADER_TABLE_SIZE the receiver allows, this allows the sender to
+    /// make the dynamic table as large as it likes.
+    pub fn new() -> Tracer {
+        let mut tables = hpack::Tables::new();
+        tables.set_protocol_max_size(u32::MAX);
+        Tracer {
+            tables: tables,
+            header_block: None,
+        }
+    }
+
+    /// Write the trace of a frame, ending with a newline.
+    ///
+    /// This is the payload as it displays, and, if it completes a header block, the instructions
+    /// that the block is made up of and the header list they decode to.
+    pub fn trace<W: fmt::Write>(&mut self, w: &mut W, stream_id: StreamId, payload: &Payload)
+            -> fmt::Result {
+        try!(writeln!(w, "stream {}: {}", stream_id.0, payload));
+        let (fragment, end_headers) = match *payload {
+            Payload::Headers(ref frame) => {
+                self.header_block = None;
+                (&frame.header_block, frame.end_headers)
+            },
+            Payload::PushPromise(ref frame) => {
+                self.header_block = None;
+                (&frame.header_block, frame.end_headers)
+            },
+            Payload::Continuation(ref frame) => (&frame.header_block, frame.end_headers),
+            _ => return Ok(()),
+        };
+        self.add_fragment(fragment);
+        if end_headers {
+            let header_block = self.header_block.take().unwrap();
+            try!(self.trace_header_block(w, header_block));
+        }
+        Ok(())
+    }
+
+    fn add_fragment(&mut self, fragment: &Fragment) {
+        let fragment = fragment.to_tendril();
+        match self.header_block {
+            Some(ref mut header_block) => header_block.push_tendril(&fragment),
+            None => self.header_block = Some(fragment),
+        }
+    }
+
+    fn trace_header_block<W: fmt::Write>(&mut self, w: &mut W, header_block: ByteTendril)
+            -> fmt::Result {
+        try!(w.write_str("    instructions:\n"));
+        for instruction in InstructionDecoder::new(header_block.clone()) {
+            match instruction {
+                Ok(instruction) => try!(writeln!(w, "        {}", instruction)),
+                Err(_) => {
+                    try!(w.write_str("        (decoding error)\n"));
+                    break;
+                },
+            }
+        }
+        try!(w.write_str("    header list:\n"));
+        for entry in hpack::Decoder::new(header_block, &mut self.tables) {
+            match entry {
+                Ok(entry) => {
+                    try!(writeln!(w, "        {}: {}", Octets(&entry.name), Octets(&entry.value)));
+                },
+                Err(_) => {
+                    // The dynamic table is probably not what the sender thinks it is any more.
+                    try!(w.write_str("        (decoding error; later header blocks may not \
+                                      decode correctly)\n"));
+                    break;
+                },
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Tracer;
+    use http2::frame::Payload;
+    use http2::frame::continuation::Continuation;
+    use http2::frame::data::Data;
+    use http2::frame::extension::Unknown;
+    use http2::frame::goaway::GoAway;
+    use http2::frame::headers::Headers;
+    use http2::frame::hpack::{Fragment, InstructionDecoder};
+    use http2::frame::ping::Ping;
+    use http2::frame::priority::Priority;
+    use http2::frame::settings::Settings;
+    use http2::frame::ErrorCode;
+    use http2::stream::StreamId;
+    use TendrilSliceExt;
+
+    #[test]
+    fn display() {
+        for &(ref payload, expected) in &[
+            (Payload::Data(Data {
+                pad_length: Some(4),
+                end_stream: true,
+                data: b"hello\r\n\x00".to_tendril(),
+            }), r#"DATA end_stream pad_length=4 length=8 data="hello\r\n\x00""#),
+            (Payload::Data(Data {
+                pad_length: None,
+                end_stream: false,
+                data: [b'a'; 40].to_tendril(),
+            }), r#"DATA length=40 data="aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"..."#),
+            (Payload::Priority(Priority {
+                exclusive: true,
+                stream_dependency: StreamId(3),
+                weight: 15,
+            }), "PRIORITY dependency=3 weight=16 exclusive"),
+            (Payload::Settings(Settings::Acknowledgment), "SETTINGS ack"),
+            (Payload::Settings(Settings::Parameters {
+                header_table_size: None,
+                enable_push: Some(false),
+                max_concurrent_streams: Some(100),
+                initial_window_size: None,
+                max_frame_size: None,
+                max_header_list_size: None,
+                enable_connect_protocol: None,
+                no_rfc7540_priorities: None,
+                unknown: vec![(0xabcd, 1)],
+            }), "SETTINGS enable_push=0 max_concurrent_streams=100 0xabcd=1"),
+            (Payload::Ping(Ping {
+                is_response: true,
+                data: *b"ping\"\\\x7f!",
+            }), r#"PING ack data="ping\"\\\x7f!""#),
+            (Payload::GoAway(GoAway {
+                last_stream_id: StreamId(5),
+                error_code: ErrorCode::ENHANCE_YOUR_CALM,
+                additional_debug_data: b"calm down".to_tendril(),
+            }), concat!("GOAWAY last_stream_id=5 error_code=ENHANCE_YOUR_CALM ",
+                        r#"additional_debug_data="calm down""#)),
+            (Payload::Unknown(Unknown {
+                type_: 0xfe,
+                flags: 0x81,
+                payload: b"?".to_tendril(),
+            }), "type=0xfe flags=0x81 length=1"),
+        ] {
+            assert_eq!(payload.to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn header_block() {
+        // RFC 7541, section C.3.1, split in two.
+        let fragment = |octets: &[u8]| {
+            Fragment::Decoder(InstructionDecoder::new(octets.to_tendril()))
+        };
+        let mut tracer = Tracer::new();
+        let mut trace = String::new();
+        tracer.trace(&mut trace, StreamId(1), &Payload::Headers(Headers {
+            pad_length: None,
+            end_stream: true,
+            end_headers: false,
+            priority: None,
+            header_block: fragment(&[0x82, 0x86, 0x84, 0x41, 0x0f, 0x77, 0x77]),
+        })).unwrap();
+        tracer.trace(&mut trace, StreamId(1), &Payload::Continuation(Continuation {
+            end_headers: true,
+            header_block: fragment(b"w.example.com"),
+        })).unwrap();
+        assert_eq!(trace, "\
+stream 1: HEADERS end_stream header_block=7
+stream 1: CONTINUATION end_headers header_block=13
+    instructions:
+        indexed 2
+        indexed 6
+        indexed 4
+        literal with incremental indexing name=[1] value=\"www.example.com\"
+    header list:
+        :method: GET
+        :scheme: http
+        :path: /
+        :authority: www.example.com
+");
+
+        // The dynamic table carries on from there (RFC 7541, section C.3.2).
+        let mut trace = String::new();
+        tracer.trace(&mut trace, StreamId(3), &Payload::Headers(Headers {
+            pad_length: None,
+            end_stream: true,
+            end_headers: true,
+            priority: Some(Priority {
+                exclusive: false,
+                stream_dependency: StreamId(1),
+                weight: 255,
+            }),
+            header_block: fragment(&[0x82, 0x86, 0x84, 0xbe, 0x58, 0x08, 0x6e, 0x6f, 0x2d, 0x63,
+                                     0x61, 0x63, 0x68, 0x65]),
+        })).unwrap();
+        assert_eq!(trace, "\
+stream 3: HEADERS end_stream end_headers priority=(dependency=1 weight=256) header_block=14
+    instructions:
+        indexed 2
+        indexed 6
+        indexed 4
+        indexed 62
+        literal with incremental indexing name=[24] value=\"no-cache\"
+    header list:
+        :method: GET
+        :scheme: http
+        :path: /
+        :authority: www.example.com
+        cache-control: no-cache
+");
+    }
+}
@@ -0,0 +1,337 @@
+//! Header block reassembly. See [RFC 7540, section 4.3][spec].
+//!
+//! > ```text
+//! >    A complete header block consists of either:
+//! >
+//! >    o  a single HEADERS or PUSH_PROMISE frame, with the END_HEADERS flag
+//! >       set, or
+//! >
+//! >    o  a HEADERS or PUSH_PROMISE frame with the END_HEADERS flag cleared
+//! >       and one or more CONTINUATION frames, where the last CONTINUATION
+//! >       frame has the END_HEADERS flag set.
+//! > ```
+//!
+//! [spec]: http://tools.ietf.org/html/rfc7540#section-4.3
+
+use std::mem;
+
+use ByteTendril;
+use http2::frame::{ErrorCode, Payload};
+use http2::frame::headers::Headers;
+use http2::frame::push_promise::PushPromise;
+use http2::frame::hpack::{Fragment, InstructionDecoder};
+use http2::stream::StreamId;
+
+/// A complete header block, with the frame that started it.
+///
+/// The frame’s `header_block` is the whole block, ready for `hpack::Decoder` (by way of
+/// `Fragment::into_tendril`), and its `end_headers` is set.
+#[derive(Debug, Eq, PartialEq)]
+pub enum HeaderBlock {
+    /// The block was started by a HEADERS frame on the given stream.
+    Headers(StreamId, Headers),
+    /// The block was started by a PUSH_PROMISE frame on the given stream.
+    PushPromise(StreamId, PushPromise),
+}
+
+impl HeaderBlock {
+    /// The stream that the header block was sent on.
+    pub fn stream_id(&self) -> StreamId {
+        match *self {
+            HeaderBlock::Headers(stream_id, _) => stream_id,
+            HeaderBlock::PushPromise(stream_id, _) => stream_id,
+        }
+    }
+
+    /// Convert the header block back into a frame payload.
+    pub fn into_payload(self) -> Payload {
+        match self {
+            HeaderBlock::Headers(_, frame) => Payload::Headers(frame),
+            HeaderBlock::PushPromise(_, frame) => Payload::PushPromise(frame),
+        }
+    }
+
+    fn take_fragment(&mut self) -> ByteTendril {
+        let fragment = match *self {
+            HeaderBlock::Headers(_, ref mut frame) => &mut frame.header_block,
+            HeaderBlock::PushPromise(_, ref mut frame) => &mut frame.header_block,
+        };
+        mem::replace(fragment, Fragment::Instructions(vec![])).into_tendril()
+    }
+
+    fn complete(mut self, block: ByteTendril) -> HeaderBlock {
+        let header_block = Fragment::Decoder(InstructionDecoder::new(block));
+        match self {
+            HeaderBlock::Headers(_, ref mut frame) => {
+                frame.end_headers = true;
+                frame.header_block = header_block;
+            },
+            HeaderBlock::PushPromise(_, ref mut frame) => {
+                frame.end_headers = true;
+                frame.header_block = header_block;
+            },
+        }
+        self
+    }
+}
+
+/// The outcome of giving a frame to an `Assembler`.
+#[derive(Debug, Eq, PartialEq)]
+pub enum Pushed {
+    /// The frame has nothing to do with header blocks and is returned untouched.
+    Other(Payload),
+    /// The frame was part of a header block which is not yet complete.
+    Incomplete,
+    /// The frame completed a header block.
+    Complete(HeaderBlock),
+}
+
+/// Reassembles header blocks from HEADERS, PUSH_PROMISE and CONTINUATION frames.
+///
+/// All frames received on a connection should be pushed through the assembler, in order, so that
+/// it can enforce this rule from [RFC 7540, section 6.10][spec]:
+///
+/// > Any number of CONTINUATION frames can be sent, as long as the preceding frame is on the same
+/// > stream and is a HEADERS, PUSH_PROMISE, or CONTINUATION frame without the END_HEADERS flag
+/// > set.
+///
+/// Breaking that rule is a connection error of type PROTOCOL_ERROR. A header block larger than
+/// the assembler’s maximum size is a connection error of type ENHANCE_YOUR_CALM; it can’t be
+/// treated as a stream error, because the block would still need to be decoded to keep the HPACK
+/// decoding context in sync with the peer, and that’s precisely what we’re declining to do.
+///
+/// [spec]: http://tools.ietf.org/html/rfc7540#section-6.10
+#[derive(Debug)]
+pub struct Assembler {
+    max_size: u32,
+    pending: Option<(HeaderBlock, ByteTendril)>,
+}
+
+impl Assembler {
+    /// Constructs a new `Assembler` which will accept header blocks of up to `max_size` octets.
+    ///
+    /// The size is that of the encoded block, after any padding has been removed.
+    pub fn new(max_size: u32) -> Assembler {
+        Assembler {
+            max_size: max_size,
+            pending: None,
+        }
+    }
+
+    /// Change the maximum size of header block that will be accepted.
+    pub fn set_max_size(&mut self, max_size: u32) {
+        self.max_size = max_size;
+    }
+
+    /// Whether a header block is partway through being received.
+    ///
+    /// While this is true, only CONTINUATION frames on `expecting()` are acceptable.
+    pub fn is_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// The stream on which a CONTINUATION frame is expected next, if any.
+    pub fn expecting(&self) -> Option<StreamId> {
+        self.pending.as_ref().map(|&(ref block, _)| block.stream_id())
+    }
+
+    /// Give the next frame received to the assembler.
+    pub fn push(&mut self, stream_id: StreamId, payload: Payload) -> Result<Pushed, ErrorCode> {
+        match self.pending.take() {
+            Some((block, mut fragment)) => {
+                // > If the END_HEADERS bit is not set, this frame MUST be followed by another
+                // > CONTINUATION frame.  A receiver MUST treat the receipt of any other type of
+                // > frame or a frame on a different stream as a connection error (Section 5.4.1)
+                // > of type PROTOCOL_ERROR.
+                let continuation = match payload {
+                    Payload::Continuation(continuation) => continuation,
+                    _ => return Err(ErrorCode::PROTOCOL_ERROR),
+                };
+                if stream_id != block.stream_id() {
+                    return Err(ErrorCode::PROTOCOL_ERROR);
+                }
+                let end_headers = continuation.end_headers;
+                let more = continuation.header_block.into_tendril();
+                if fragment.len32() as u64 + more.len32() as u64 > self.max_size as u64 {
+                    return Err(ErrorCode::ENHANCE_YOUR_CALM);
+                }
+                fragment.push_tendril(&more);
+                self.finish(block, fragment, end_headers)
+            },
+            None => {
+                let (mut block, end_headers) = match payload {
+                    Payload::Headers(frame) => {
+                        let end_headers = frame.end_headers;
+                        (HeaderBlock::Headers(stream_id, frame), end_headers)
+                    },
+                    Payload::PushPromise(frame) => {
+                        let end_headers = frame.end_headers;
+                        (HeaderBlock::PushPromise(stream_id, frame), end_headers)
+                    },
+                    // > A CONTINUATION frame MUST be preceded by a HEADERS, PUSH_PROMISE or
+                    // > CONTINUATION frame without the END_HEADERS flag set.  A recipient that
+                    // > observes violation of this rule MUST respond with a connection error
+                    // > (Section 5.4.1) of type PROTOCOL_ERROR.
+                    Payload::Continuation(_) => return Err(ErrorCode::PROTOCOL_ERROR),
+                    payload => return Ok(Pushed::Other(payload)),
+                };
+                let fragment = block.take_fragment();
+                if fragment.len32() > self.max_size {
+                    return Err(ErrorCode::ENHANCE_YOUR_CALM);
+                }
+                self.finish(block, fragment, end_headers)
+            },
+        }
+    }
+
+    fn finish(&mut self, block: HeaderBlock, fragment: ByteTendril, end_headers: bool)
+            -> Result<Pushed, ErrorCode> {
+        if end_headers {
+            Ok(Pushed::Complete(block.complete(fragment)))
+        } else {
+            self.pending = Some((block, fragment));
+            Ok(Pushed::Incomplete)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Assembler, HeaderBlock, Pushed};
+    use http2::frame::{ErrorCode, Payload};
+    use http2::frame::continuation::Continuation;
+    use http2::frame::extension::Unknown;
+    use http2::frame::headers::Headers;
+    use http2::frame::ping::Ping;
+    use http2::frame::push_promise::PushPromise;
+    use http2::frame::hpack::{Fragment, InstructionDecoder};
+    use http2::stream::StreamId;
+    use {ByteTendril, TendrilSliceExt};
+
+    fn fragment(bytes: &[u8]) -> Fragment {
+        Fragment::Decoder(InstructionDecoder::new(bytes.to_tendril()))
+    }
+
+    fn headers(end_headers: bool, bytes: &[u8]) -> Payload {
+        Payload::Headers(Headers {
+            pad_length: None,
+            end_stream: true,
+            end_headers: end_headers,
+            priority: None,
+            header_block: fragment(bytes),
+        })
+    }
+
+    fn continuation(end_headers: bool, bytes: &[u8]) -> Payload {
+        Payload::Continuation(Continuation {
+            end_headers: end_headers,
+            header_block: fragment(bytes),
+        })
+    }
+
+    #[test]
+    fn single_frame() {
+        let mut assembler = Assembler::new(16384);
+        assert_eq!(assembler.push(StreamId(1), headers(true, b"\x82\x86")),
+                   Ok(Pushed::Complete(HeaderBlock::Headers(StreamId(1), Headers {
+                       pad_length: None,
+                       end_stream: true,
+                       end_headers: true,
+                       priority: None,
+                       header_block: fragment(b"\x82\x86"),
+                   }))));
+        assert!(!assembler.is_pending());
+    }
+
+    #[test]
+    fn continuations() {
+        let mut assembler = Assembler::new(16384);
+        assert_eq!(assembler.push(StreamId(1), headers(false, b"\x82")), Ok(Pushed::Incomplete));
+        assert_eq!(assembler.expecting(), Some(StreamId(1)));
+        assert_eq!(assembler.push(StreamId(1), continuation(false, b"\x86")),
+                   Ok(Pushed::Incomplete));
+        match assembler.push(StreamId(1), continuation(true, b"\x84")) {
+            Ok(Pushed::Complete(HeaderBlock::Headers(StreamId(1), frame))) => {
+                assert!(frame.end_headers);
+                assert!(frame.end_stream);
+                assert_eq!(&*frame.header_block.into_tendril(), b"\x82\x86\x84");
+            },
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(assembler.expecting(), None);
+    }
+
+    #[test]
+    fn push_promise() {
+        let mut assembler = Assembler::new(16384);
+        let push_promise = Payload::PushPromise(PushPromise {
+            pad_length: None,
+            end_headers: false,
+            promised_stream_id: StreamId(2),
+            header_block: fragment(b"\x82"),
+        });
+        assert_eq!(assembler.push(StreamId(1), push_promise), Ok(Pushed::Incomplete));
+        match assembler.push(StreamId(1), continuation(true, b"\x87")) {
+            Ok(Pushed::Complete(HeaderBlock::PushPromise(StreamId(1), frame))) => {
+                assert_eq!(frame.promised_stream_id, StreamId(2));
+                assert_eq!(&*frame.header_block.into_tendril(), b"\x82\x87");
+            },
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn other_frames_pass_through() {
+        let mut assembler = Assembler::new(16384);
+        let ping = Ping { is_response: false, data: *b"12345678" };
+        assert_eq!(assembler.push(StreamId(0), Payload::Ping(ping.clone())),
+                   Ok(Pushed::Other(Payload::Ping(ping))));
+    }
+
+    #[test]
+    fn interleaving() {
+        let mut assembler = Assembler::new(16384);
+        assert_eq!(assembler.push(StreamId(1), continuation(true, b"\x82")),
+                   Err(ErrorCode::PROTOCOL_ERROR));
+
+        let mut assembler = Assembler::new(16384);
+        assert_eq!(assembler.push(StreamId(1), headers(false, b"\x82")), Ok(Pushed::Incomplete));
+        assert_eq!(assembler.push(StreamId(3), continuation(true, b"\x86")),
+                   Err(ErrorCode::PROTOCOL_ERROR));
+
+        let mut assembler = Assembler::new(16384);
+        assert_eq!(assembler.push(StreamId(1), headers(false, b"\x82")), Ok(Pushed::Incomplete));
+        assert_eq!(assembler.push(StreamId(1), headers(true, b"\x86")),
+                   Err(ErrorCode::PROTOCOL_ERROR));
+
+        let mut assembler = Assembler::new(16384);
+        assert_eq!(assembler.push(StreamId(1), headers(false, b"\x82")), Ok(Pushed::Incomplete));
+        let ping = Payload::Ping(Ping { is_response: false, data: [0; 8] });
+        assert_eq!(assembler.push(StreamId(0), ping), Err(ErrorCode::PROTOCOL_ERROR));
+
+        let mut assembler = Assembler::new(16384);
+        assert_eq!(assembler.push(StreamId(1), headers(false, b"\x82")), Ok(Pushed::Incomplete));
+        let unknown = Payload::Unknown(Unknown {
+            type_: 0xff,
+            flags: 0,
+            payload: ByteTendril::new(),
+        });
+        assert_eq!(assembler.push(StreamId(1), unknown),
+                   Err(ErrorCode::PROTOCOL_ERROR));
+    }
+
+    #[test]
+    fn max_size() {
+        let mut assembler = Assembler::new(4);
+        assert_eq!(assembler.push(StreamId(1), headers(true, b"\x82\x82\x82\x82\x82")),
+                   Err(ErrorCode::ENHANCE_YOUR_CALM));
+
+        let mut assembler = Assembler::new(4);
+        assert_eq!(assembler.push(StreamId(1), headers(false, b"\x82\x82")),
+                   Ok(Pushed::Incomplete));
+        assert_eq!(assembler.push(StreamId(1), continuation(false, b"\x82\x82")),
+                   Ok(Pushed::Incomplete));
+        assert_eq!(assembler.push(StreamId(1), continuation(true, b"\x82")),
+                   Err(ErrorCode::ENHANCE_YOUR_CALM));
+    }
+}
@@ -0,0 +1,288 @@
+//! Keeping a connection alive, and noticing when it has died, with PING frames. See [RFC 7540,
+//! section 6.7][spec].
+//!
+//! > The PING frame (type=0x6) is a mechanism for measuring a minimal round-trip time from the
+//! > sender, as well as determining whether an idle connection is still functional.
+//!
+//! `Connection` has no clock, so this is kept apart from it. A `Keepalive` is told when frames
+//! arrive (`received`) and when PINGs are acknowledged (`ping_acknowledged`), and is polled at
+//! its `deadline` (`poll`). Once nothing has been received for the idle interval, it sends a PING;
+//! if that is not acknowledged within the timeout, the connection is dead and should be dropped.
+//! Each acknowledgment gives a measurement of the round-trip time.
+//!
+//! The time comes from a `Clock`, which is the system’s monotonic clock unless you say otherwise;
+//! `ManualClock` is one that only moves when told to, for tests.
+//!
+//! [spec]: http://tools.ietf.org/html/rfc7540#section-6.7
+
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+use http2::connection::Connection;
+
+/// The first four octets of the PINGs a `Keepalive` sends; the other four are a counter.
+const PING_PREFIX: [u8; 4] = *b"keep";
+
+/// A source of the current time.
+pub trait Clock {
+    /// The current time.
+    fn now(&self) -> Instant;
+}
+
+impl<'a, C: Clock> Clock for &'a C {
+    fn now(&self) -> Instant {
+        (**self).now()
+    }
+}
+
+/// The system’s monotonic clock.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A clock that stands still until it is advanced.
+#[derive(Clone, Debug)]
+pub struct ManualClock {
+    now: Cell<Instant>,
+}
+
+impl ManualClock {
+    /// Constructs a clock that starts at the present moment.
+    pub fn new() -> ManualClock {
+        ManualClock {
+            now: Cell::new(Instant::now()),
+        }
+    }
+
+    /// Move the clock forwards.
+    pub fn advance(&self, duration: Duration) {
+        self.now.set(self.now.get() + duration);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.now.get()
+    }
+}
+
+/// Whether the peer still seems to be there.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Liveness {
+    /// All is well, as far as we know.
+    Alive,
+    /// A PING went unacknowledged for longer than the timeout.
+    Dead,
+}
+
+/// Sends PINGs on an idle connection and times their acknowledgments.
+#[derive(Debug)]
+pub struct Keepalive<C: Clock = SystemClock> {
+    clock: C,
+    /// How long the connection may be idle before a PING is sent.
+    interval: Duration,
+    /// How long a PING may go unacknowledged.
+    timeout: Duration,
+    /// When the last frame was received, or when we started.
+    last_received: Instant,
+    /// The PING awaiting acknowledgment, with when it was sent.
+    outstanding: Option<([u8; 8], Instant)>,
+    /// The number of PINGs sent, for making each one’s payload unique.
+    sent: u32,
+    /// The latest round-trip time measured.
+    rtt: Option<Duration>,
+}
+
+impl Keepalive<SystemClock> {
+    /// Constructs a keepalive that sends a PING after `interval` without receiving anything, and
+    /// gives up on the connection if that PING is not acknowledged within `timeout`.
+    pub fn new(interval: Duration, timeout: Duration) -> Keepalive<SystemClock> {
+        Keepalive::with_clock(SystemClock, interval, timeout)
+    }
+}
+
+impl<C: Clock> Keepalive<C> {
+    /// Constructs a keepalive as with `new`, taking the time from `clock`.
+    pub fn with_clock(clock: C, interval: Duration, timeout: Duration) -> Keepalive<C> {
+        let now = clock.now();
+        Keepalive {
+            clock: clock,
+            interval: interval,
+            timeout: timeout,
+            last_received: now,
+            outstanding: None,
+            sent: 0,
+            rtt: None,
+        }
+    }
+
+    /// Note that a frame has been received, which shows that the connection is not idle.
+    pub fn received(&mut self) {
+        self.last_received = self.clock.now();
+    }
+
+    /// Handle the acknowledgment of a PING (`Event::PingAcknowledged`), returning whether it was
+    /// one that this sent. If so, the round-trip time is measured.
+    pub fn ping_acknowledged(&mut self, data: [u8; 8]) -> bool {
+        match self.outstanding {
+            Some((outstanding, sent)) if outstanding == data => {
+                let now = self.clock.now();
+                self.rtt = Some(now.duration_since(sent));
+                self.last_received = now;
+                self.outstanding = None;
+                true
+            },
+            _ => false,
+        }
+    }
+
+    /// Send a PING if the connection has been idle long enough, and check whether the
+    /// outstanding one, if any, has gone unacknowledged for too long.
+    ///
+    /// This should be called again by the `deadline`. Once the connection is dead, it stays so.
+    pub fn poll(&mut self, connection: &mut Connection) -> Liveness {
+        let now = self.clock.now();
+        match self.outstanding {
+            Some((_, sent)) => if now.duration_since(sent) >= self.timeout {
+                return Liveness::Dead;
+            },
+            None => if now.duration_since(self.last_received) >= self.interval {
+                let data = self.next_ping_data();
+                connection.send_ping(data);
+                self.outstanding = Some((data, now));
+            },
+        }
+        Liveness::Alive
+    }
+
+    /// When `poll` next needs calling: when the idle interval runs out, or when the outstanding
+    /// PING times out.
+    pub fn deadline(&self) -> Instant {
+        match self.outstanding {
+            Some((_, sent)) => sent + self.timeout,
+            None => self.last_received + self.interval,
+        }
+    }
+
+    /// The latest round-trip time measured, if any PING has been acknowledged yet.
+    pub fn rtt(&self) -> Option<Duration> {
+        self.rtt
+    }
+
+    /// Whether a PING is awaiting acknowledgment.
+    pub fn is_waiting(&self) -> bool {
+        self.outstanding.is_some()
+    }
+
+    fn next_ping_data(&mut self) -> [u8; 8] {
+        let n = self.sent;
+        self.sent = self.sent.wrapping_add(1);
+        [PING_PREFIX[0], PING_PREFIX[1], PING_PREFIX[2], PING_PREFIX[3],
+         (n >> 24) as u8, (n >> 16) as u8, (n >> 8) as u8, n as u8]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{Clock, Keepalive, Liveness, ManualClock};
+    use http2::connection::{Connection, Role};
+    use http2::frame::Payload;
+    use http2::frame::ping::Ping;
+    use http2::frame::settings::Settings;
+    use http2::stream::StreamId;
+
+    fn connection() -> Connection {
+        let mut connection = Connection::new(Role::Client, Settings::Parameters {
+            header_table_size: None,
+            enable_push: None,
+            max_concurrent_streams: None,
+            initial_window_size: None,
+            max_frame_size: None,
+            max_header_list_size: None,
+            enable_connect_protocol: None,
+            no_rfc7540_priorities: None,
+            unknown: vec![],
+        });
+        // The SETTINGS frame.
+        let _ = connection.next_frame();
+        connection
+    }
+
+    /// The data of the PING that the connection has been told to send, if any.
+    fn ping_sent(connection: &mut Connection) -> Option<[u8; 8]> {
+        match connection.next_frame() {
+            Some((StreamId(0), Payload::Ping(Ping { is_response: false, data }))) => Some(data),
+            None => None,
+            other => panic!("unexpected frame {:?}", other),
+        }
+    }
+
+    #[test]
+    fn idle() {
+        let clock = ManualClock::new();
+        let mut connection = connection();
+        let mut keepalive = Keepalive::with_clock(&clock, Duration::from_secs(10),
+                                                  Duration::from_secs(5));
+        assert_eq!(keepalive.poll(&mut connection), Liveness::Alive);
+        assert_eq!(ping_sent(&mut connection), None);
+
+        // Receiving frames puts off the PING.
+        clock.advance(Duration::from_secs(8));
+        keepalive.received();
+        clock.advance(Duration::from_secs(8));
+        assert_eq!(keepalive.poll(&mut connection), Liveness::Alive);
+        assert_eq!(ping_sent(&mut connection), None);
+        assert_eq!(keepalive.deadline(), clock.now() + Duration::from_secs(2));
+
+        clock.advance(Duration::from_secs(2));
+        assert_eq!(keepalive.poll(&mut connection), Liveness::Alive);
+        let data = ping_sent(&mut connection).unwrap();
+        assert!(keepalive.is_waiting());
+        // Only the one.
+        assert_eq!(keepalive.poll(&mut connection), Liveness::Alive);
+        assert_eq!(ping_sent(&mut connection), None);
+
+        clock.advance(Duration::from_millis(150));
+        assert!(!keepalive.ping_acknowledged(*b"shutdown"));
+        assert!(keepalive.ping_acknowledged(data));
+        assert!(!keepalive.ping_acknowledged(data));
+        assert_eq!(keepalive.rtt(), Some(Duration::from_millis(150)));
+
+        // The next one is different.
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(keepalive.poll(&mut connection), Liveness::Alive);
+        let next = ping_sent(&mut connection).unwrap();
+        assert!(next != data);
+        clock.advance(Duration::from_millis(50));
+        assert!(keepalive.ping_acknowledged(next));
+        assert_eq!(keepalive.rtt(), Some(Duration::from_millis(50)));
+    }
+
+    #[test]
+    fn dead() {
+        let clock = ManualClock::new();
+        let mut connection = connection();
+        let mut keepalive = Keepalive::with_clock(&clock, Duration::from_secs(10),
+                                                  Duration::from_secs(5));
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(keepalive.poll(&mut connection), Liveness::Alive);
+        assert!(ping_sent(&mut connection).is_some());
+        assert_eq!(keepalive.deadline(), clock.now() + Duration::from_secs(5));
+
+        // Other frames are no substitute for the acknowledgment.
+        clock.advance(Duration::from_secs(4));
+        keepalive.received();
+        assert_eq!(keepalive.poll(&mut connection), Liveness::Alive);
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(keepalive.poll(&mut connection), Liveness::Dead);
+        assert_eq!(keepalive.poll(&mut connection), Liveness::Dead);
+        assert_eq!(keepalive.rtt(), None);
+    }
+}
@@ -3,3 +3,32 @@
 #[macro_use]
 pub mod stream;
 pub mod frame;
+pub mod header_block;
+pub mod flow_control;
+pub mod priority;
+pub mod protection;
+pub mod padding;
+pub mod connection;
+pub mod keepalive;
+pub mod upgrade;
+
+pub use self::connection::{Connection, Event, Role};
+
+use self::frame::ErrorCode;
+use self::stream::StreamId;
+
+/// An error detected on a connection. See [RFC 7540, section 5.4][spec].
+///
+/// [spec]: http://tools.ietf.org/html/rfc7540#section-5.4
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Error {
+    /// > An endpoint that detects a stream error sends a RST_STREAM frame
+    /// > (Section 6.4) that contains the stream identifier of the stream where
+    /// > the error occurred.
+    Stream(StreamId, ErrorCode),
+
+    /// > An endpoint that encounters a connection error SHOULD first send a
+    /// > GOAWAY frame (Section 6.8) with the stream identifier of the last
+    /// > stream that it successfully received from its peer.
+    Connection(ErrorCode),
+}
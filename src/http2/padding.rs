@@ -0,0 +1,164 @@
+//! Choosing how much padding to put in frames. See [RFC 7540, section 10.7][spec].
+//!
+//! > Padding can be used to obscure the exact size of frame content and is provided to mitigate
+//! > specific attacks within HTTP, for example, attacks where compressed content includes both
+//! > attacker-controlled plaintext and secret data (e.g., [BREACH]).
+//!
+//! DATA, HEADERS and PUSH_PROMISE frames can be padded with up to 255 octets, after the one octet
+//! of the Pad Length field. The padding of DATA frames counts against flow control, so a
+//! `Connection` pads what it sends according to its `Padding` policy (see
+//! `Connection::set_padding`), accounting for it as it goes.
+//!
+//! > Use of padding can result in less protection than might seem immediately obvious.  At best,
+//! > padding only makes it more difficult for an attacker to infer length information by
+//! > increasing the number of frames an attacker has to observe.
+//!
+//! [spec]: http://tools.ietf.org/html/rfc7540#section-10.7
+
+use std::cmp;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
+/// How much padding to put in each frame that can be padded.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Padding {
+    /// No padding at all. This is the default.
+    None,
+
+    /// Pad each frame payload up to a multiple of this many octets, as far as 255 octets of
+    /// padding allow.
+    Multiple(u32),
+
+    /// Pad each frame with a number of octets chosen at random from this range (inclusive), not
+    /// counting the Pad Length field.
+    Random {
+        /// The fewest octets of padding.
+        min: u8,
+        /// The most octets of padding.
+        max: u8,
+    },
+}
+
+impl Default for Padding {
+    fn default() -> Padding {
+        Padding::None
+    }
+}
+
+/// Applies a `Padding` policy to frames one by one.
+#[derive(Clone, Debug)]
+pub struct Padder {
+    padding: Padding,
+    /// The state of the xorshift* generator used for `Padding::Random`.
+    state: u64,
+}
+
+impl Padder {
+    /// Constructs a padder for the given policy, with a generator seeded at random.
+    pub fn new(padding: Padding) -> Padder {
+        // The hasher of a `RandomState` is keyed at random, which is all that is needed here.
+        Padder::with_seed(padding, RandomState::new().build_hasher().finish())
+    }
+
+    /// Constructs a padder for the given policy whose random choices are determined by `seed`.
+    pub fn with_seed(padding: Padding, seed: u64) -> Padder {
+        Padder {
+            padding: padding,
+            // Zero is the one state that xorshift never leaves.
+            state: if seed == 0 { 0x9e3779b97f4a7c15 } else { seed },
+        }
+    }
+
+    /// The policy being applied.
+    pub fn padding(&self) -> Padding {
+        self.padding
+    }
+
+    /// Choose the Pad Length for a frame whose payload is `len` octets without padding, when the
+    /// padded payload, Pad Length field included, may be no more than `max_len` octets.
+    ///
+    /// `None` means that the frame should not be padded, either because the policy says so or
+    /// because there is no room even for the Pad Length field.
+    pub fn pad_length(&mut self, len: u32, max_len: u32) -> Option<u8> {
+        let pad_length = match self.padding {
+            Padding::None => return None,
+            Padding::Multiple(block) if block <= 1 => return None,
+            Padding::Multiple(block) => {
+                let padded_len = len as u64 + 1;
+                let target = (padded_len + block as u64 - 1) / block as u64 * block as u64;
+                cmp::min(target - padded_len, 255) as u32
+            },
+            Padding::Random { min, max } => {
+                let (min, max) = (cmp::min(min, max) as u64, cmp::max(min, max) as u64);
+                (min + self.next_random() % (max - min + 1)) as u32
+            },
+        };
+        if len >= max_len {
+            return None;
+        }
+        Some(cmp::min(pad_length, max_len - len - 1) as u8)
+    }
+
+    /// The next number from the xorshift* generator.
+    fn next_random(&mut self) -> u64 {
+        let mut x = self.state;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.state = x;
+        x.wrapping_mul(0x2545f4914f6cdd1d)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Padder, Padding};
+
+    #[test]
+    fn none() {
+        let mut padder = Padder::new(Padding::None);
+        assert_eq!(padder.pad_length(10, 16384), None);
+        let mut padder = Padder::new(Padding::Multiple(1));
+        assert_eq!(padder.pad_length(10, 16384), None);
+    }
+
+    #[test]
+    fn multiple() {
+        let mut padder = Padder::new(Padding::Multiple(16));
+        assert_eq!(padder.pad_length(0, 16384), Some(15));
+        assert_eq!(padder.pad_length(10, 16384), Some(5));
+        assert_eq!(padder.pad_length(15, 16384), Some(0));
+        assert_eq!(padder.pad_length(16, 16384), Some(15));
+        // Limited by the room there is.
+        assert_eq!(padder.pad_length(10, 12), Some(1));
+        assert_eq!(padder.pad_length(10, 11), Some(0));
+        assert_eq!(padder.pad_length(10, 10), None);
+        // Limited by what the Pad Length field can express.
+        let mut padder = Padder::new(Padding::Multiple(1024));
+        assert_eq!(padder.pad_length(100, 16384), Some(255));
+        assert_eq!(padder.pad_length(1000, 16384), Some(23));
+    }
+
+    #[test]
+    fn random() {
+        let mut padder = Padder::with_seed(Padding::Random { min: 4, max: 8 }, 1);
+        let mut seen = [false; 256];
+        for _ in 0..1000 {
+            let pad_length = padder.pad_length(100, 16384).unwrap();
+            assert!(pad_length >= 4 && pad_length <= 8);
+            seen[pad_length as usize] = true;
+        }
+        assert!(seen[4..9].iter().all(|&seen| seen));
+
+        // The same seed makes the same choices.
+        let mut a = Padder::with_seed(Padding::Random { min: 0, max: 255 }, 42);
+        let mut b = Padder::with_seed(Padding::Random { min: 0, max: 255 }, 42);
+        for _ in 0..100 {
+            assert_eq!(a.pad_length(0, 16384), b.pad_length(0, 16384));
+        }
+
+        let mut padder = Padder::new(Padding::Random { min: 10, max: 10 });
+        assert_eq!(padder.pad_length(100, 16384), Some(10));
+        assert_eq!(padder.pad_length(100, 105), Some(4));
+    }
+}
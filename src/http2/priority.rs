@@ -0,0 +1,476 @@
+//! Stream prioritisation. See [RFC 7540, section 5.3][spec].
+//!
+//! > A client can assign a priority for a new stream by including prioritization information in
+//! > the HEADERS frame (Section 6.2) that opens the stream.  At any other time, the PRIORITY frame
+//! > (Section 6.3) can be used to change the priority of a stream.
+//!
+//! `Tree` keeps the dependency tree that those frames describe, and schedules the sending of data
+//! according to it: a stream is only sent data when nothing it depends on can be, and siblings
+//! share in proportion to their weights.
+//!
+//! [spec]: http://tools.ietf.org/html/rfc7540#section-5.3
+
+use std::collections::HashMap;
+
+use http2::frame::ErrorCode;
+use http2::frame::priority::Priority;
+use http2::stream::StreamId;
+
+/// The weight streams get by default, as it appears in frames (that is, with origin 0; it means
+/// a weight of 16).
+///
+/// > All streams are initially assigned a non-exclusive dependency on stream 0x0.  Pushed streams
+/// > (Section 8.2) initially depend on their associated stream.  In both cases, streams are
+/// > assigned a default weight of 16.
+pub const DEFAULT_WEIGHT: u8 = 15;
+
+/// The root of the tree, which every stream ultimately depends on.
+const ROOT: StreamId = StreamId(0);
+
+struct Node {
+    parent: StreamId,
+    /// The weight, from 1 to 256.
+    weight: u32,
+    children: Vec<StreamId>,
+    /// Whether the stream has data waiting to be sent.
+    ready: bool,
+    /// The number of ready streams in this subtree, this one included.
+    active: usize,
+    /// The virtual time at which this stream is next due to send, among its siblings; it advances
+    /// in inverse proportion to the weight, so the smallest is always the one most owed.
+    pass: u64,
+    /// The pass of the child most recently sent on, which children that become ready start from.
+    time: u64,
+}
+
+impl Node {
+    fn new(parent: StreamId, weight: u8) -> Node {
+        Node {
+            parent: parent,
+            weight: weight as u32 + 1,
+            children: vec![],
+            ready: false,
+            active: 0,
+            pass: 0,
+            time: 0,
+        }
+    }
+}
+
+/// A stream dependency tree and weighted fair scheduler.
+///
+/// Streams are added to the tree as they are mentioned, with the default priority unless
+/// otherwise specified, and should be removed when they close.
+pub struct Tree {
+    nodes: HashMap<StreamId, Node>,
+}
+
+impl Tree {
+    /// Constructs a tree with nothing but the root.
+    pub fn new() -> Tree {
+        let mut nodes = HashMap::new();
+        let _ = nodes.insert(ROOT, Node::new(ROOT, DEFAULT_WEIGHT));
+        Tree {
+            nodes: nodes,
+        }
+    }
+
+    /// Whether a stream is in the tree.
+    pub fn contains(&self, stream_id: StreamId) -> bool {
+        stream_id != ROOT && self.nodes.contains_key(&stream_id)
+    }
+
+    /// The stream that a stream depends on (stream 0 if none), if it is in the tree.
+    pub fn parent(&self, stream_id: StreamId) -> Option<StreamId> {
+        self.node(stream_id).map(|node| node.parent)
+    }
+
+    /// The weight of a stream, from 1 to 256, if it is in the tree.
+    pub fn weight(&self, stream_id: StreamId) -> Option<u32> {
+        self.node(stream_id).map(|node| node.weight)
+    }
+
+    /// The streams that depend directly on a stream (stream 0 for the root).
+    pub fn children(&self, stream_id: StreamId) -> &[StreamId] {
+        self.nodes.get(&stream_id).map_or(&[], |node| &node.children[..])
+    }
+
+    fn node(&self, stream_id: StreamId) -> Option<&Node> {
+        if stream_id == ROOT {
+            None
+        } else {
+            self.nodes.get(&stream_id)
+        }
+    }
+
+    /// Add a stream to the tree with the default priority, if it is not there already.
+    pub fn insert(&mut self, stream_id: StreamId) {
+        if stream_id == ROOT || self.nodes.contains_key(&stream_id) {
+            return;
+        }
+        let _ = self.nodes.insert(stream_id, Node::new(ROOT, DEFAULT_WEIGHT));
+        self.nodes.get_mut(&ROOT).unwrap().children.push(stream_id);
+    }
+
+    /// Apply priority information from a HEADERS or PRIORITY frame, adding the stream if need be.
+    ///
+    /// > A stream cannot depend on itself.  An endpoint MUST treat this as a stream error
+    /// > (Section 5.4.2) of type PROTOCOL_ERROR.
+    ///
+    /// Other than that, it cannot fail.
+    pub fn reprioritise(&mut self, stream_id: StreamId, priority: &Priority)
+            -> Result<(), ErrorCode> {
+        if priority.stream_dependency == stream_id || stream_id == ROOT {
+            return Err(ErrorCode::PROTOCOL_ERROR);
+        }
+        self.insert(stream_id);
+        let mut parent = priority.stream_dependency;
+        let mut weight = priority.weight;
+        let mut exclusive = priority.exclusive;
+        if parent != ROOT && !self.nodes.contains_key(&parent) {
+            // > A dependency on a stream that is not currently in the tree -- such as a stream
+            // > in the "idle" state -- results in that stream being given a default priority
+            // > (Section 5.3.5).
+            parent = ROOT;
+            weight = DEFAULT_WEIGHT;
+            exclusive = false;
+        }
+        if self.is_ancestor(stream_id, parent) {
+            // > If a stream is made dependent on one of its own dependencies, the formerly
+            // > dependent stream is first moved to be dependent on the reprioritized stream's
+            // > previous parent.  The moved dependency retains its weight.
+            let previous_parent = self.nodes[&stream_id].parent;
+            self.detach(parent);
+            self.attach(parent, previous_parent);
+        }
+        self.detach(stream_id);
+        if exclusive {
+            // > An exclusive flag allows for the insertion of a new level of dependencies.  The
+            // > exclusive flag causes the stream to become the sole dependency of its parent
+            // > stream, causing other dependencies to become dependent on the exclusive stream.
+            let siblings = self.nodes[&parent].children.clone();
+            for sibling in siblings {
+                self.detach(sibling);
+                self.attach(sibling, stream_id);
+            }
+        }
+        self.nodes.get_mut(&stream_id).unwrap().weight = weight as u32 + 1;
+        self.attach(stream_id, parent);
+        Ok(())
+    }
+
+    /// Remove a stream from the tree, as when it closes.
+    ///
+    /// The streams which depended on it are given the default priority.
+    pub fn remove(&mut self, stream_id: StreamId) {
+        if !self.contains(stream_id) {
+            return;
+        }
+        let children = self.nodes[&stream_id].children.clone();
+        for child in children {
+            self.detach(child);
+            self.nodes.get_mut(&child).unwrap().weight = DEFAULT_WEIGHT as u32 + 1;
+            self.attach(child, ROOT);
+        }
+        self.detach(stream_id);
+        let _ = self.nodes.remove(&stream_id);
+    }
+
+    /// Whether `ancestor` is one of the streams that `stream_id` depends on, directly or not.
+    fn is_ancestor(&self, ancestor: StreamId, mut stream_id: StreamId) -> bool {
+        while stream_id != ROOT {
+            stream_id = self.nodes[&stream_id].parent;
+            if stream_id == ancestor {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Take a stream (and its subtree) out of its parent’s children.
+    fn detach(&mut self, stream_id: StreamId) {
+        let (parent, active) = {
+            let node = &self.nodes[&stream_id];
+            (node.parent, node.active)
+        };
+        self.nodes.get_mut(&parent).unwrap().children.retain(|&child| child != stream_id);
+        let mut ancestor = parent;
+        while active > 0 {
+            let node = self.nodes.get_mut(&ancestor).unwrap();
+            node.active -= active;
+            if ancestor == ROOT {
+                break;
+            }
+            ancestor = node.parent;
+        }
+    }
+
+    /// Make a detached stream (and its subtree) one of `parent`’s children.
+    fn attach(&mut self, stream_id: StreamId, parent: StreamId) {
+        let active = {
+            let time = self.nodes[&parent].time;
+            let node = self.nodes.get_mut(&stream_id).unwrap();
+            node.parent = parent;
+            node.pass = time;
+            node.active
+        };
+        self.nodes.get_mut(&parent).unwrap().children.push(stream_id);
+        self.add_active(parent, active as isize);
+    }
+
+    /// Change the count of ready streams in the subtree of `stream_id` and its ancestors.
+    fn add_active(&mut self, mut stream_id: StreamId, delta: isize) {
+        if delta == 0 {
+            return;
+        }
+        loop {
+            let (parent, became_active) = {
+                let node = self.nodes.get_mut(&stream_id).unwrap();
+                let was_active = node.active > 0;
+                node.active = (node.active as isize + delta) as usize;
+                (node.parent, !was_active && node.active > 0)
+            };
+            if stream_id == ROOT {
+                break;
+            }
+            if became_active {
+                // Being idle doesn’t earn a stream credit; it starts level with its siblings.
+                let time = self.nodes[&parent].time;
+                self.nodes.get_mut(&stream_id).unwrap().pass = time;
+            }
+            stream_id = parent;
+        }
+    }
+
+    /// Set whether a stream has data waiting to be sent, adding it to the tree if need be.
+    pub fn set_ready(&mut self, stream_id: StreamId, ready: bool) {
+        if stream_id == ROOT {
+            return;
+        }
+        self.insert(stream_id);
+        let was_ready = {
+            let node = self.nodes.get_mut(&stream_id).unwrap();
+            let was_ready = node.ready;
+            node.ready = ready;
+            was_ready
+        };
+        match (was_ready, ready) {
+            (false, true) => self.add_active(stream_id, 1),
+            (true, false) => self.add_active(stream_id, -1),
+            _ => (),
+        }
+    }
+
+    /// Whether a stream has data waiting to be sent.
+    pub fn is_ready(&self, stream_id: StreamId) -> bool {
+        self.node(stream_id).map_or(false, |node| node.ready)
+    }
+
+    /// Choose the ready stream that should be sent data next, of those for which `can_send`
+    /// returns true (those with some flow-control window left, say).
+    ///
+    /// > Streams can be prioritized by marking them as dependent on the completion of other
+    /// > streams (Section 5.3.1).  Each dependency is assigned a relative weight, a number that is
+    /// > used to determine the relative proportion of available resources that are assigned to
+    /// > streams dependent on the same stream.
+    ///
+    /// A stream is chosen only if none of the streams it depends on can be; among siblings, the
+    /// one which has received least in proportion to its weight wins, ties going to the lowest
+    /// stream identifier. Call `sent` afterwards to record how much was sent.
+    pub fn next<F: FnMut(StreamId) -> bool>(&self, mut can_send: F) -> Option<StreamId> {
+        self.next_in(ROOT, &mut can_send)
+    }
+
+    fn next_in<F: FnMut(StreamId) -> bool>(&self, stream_id: StreamId, can_send: &mut F)
+            -> Option<StreamId> {
+        let node = &self.nodes[&stream_id];
+        if node.ready && stream_id != ROOT && can_send(stream_id) {
+            return Some(stream_id);
+        }
+        let mut children = node.children.iter()
+                                        .map(|id| (self.nodes[id].pass, *id))
+                                        .filter(|&(_, id)| self.nodes[&id].active > 0)
+                                        .collect::<Vec<_>>();
+        children.sort();
+        for (_, child) in children {
+            if let Some(stream_id) = self.next_in(child, can_send) {
+                return Some(stream_id);
+            }
+        }
+        None
+    }
+
+    /// Record that `len` octets of data were sent on a stream, charging it and the streams it
+    /// depends on for them.
+    pub fn sent(&mut self, mut stream_id: StreamId, len: u32) {
+        // Even an empty DATA frame costs something.
+        let len = if len == 0 { 1 } else { len as u64 };
+        while stream_id != ROOT {
+            let (parent, pass) = match self.nodes.get_mut(&stream_id) {
+                Some(node) => {
+                    let pass = node.pass;
+                    node.pass += len * 256 / node.weight as u64;
+                    (node.parent, pass)
+                },
+                None => return,
+            };
+            self.nodes.get_mut(&parent).unwrap().time = pass;
+            stream_id = parent;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Tree;
+    use http2::frame::ErrorCode;
+    use http2::frame::priority::Priority;
+    use http2::stream::StreamId;
+
+    fn priority(stream_dependency: u32, weight: u8, exclusive: bool) -> Priority {
+        Priority {
+            exclusive: exclusive,
+            stream_dependency: StreamId(stream_dependency),
+            weight: weight,
+        }
+    }
+
+    fn children(tree: &Tree, stream_id: u32) -> Vec<u32> {
+        let mut children = tree.children(StreamId(stream_id)).iter()
+                                                             .map(|id| id.0)
+                                                             .collect::<Vec<_>>();
+        children.sort();
+        children
+    }
+
+    #[test]
+    fn dependencies() {
+        let mut tree = Tree::new();
+        tree.insert(StreamId(1));
+        tree.insert(StreamId(3));
+        assert_eq!(children(&tree, 0), [1, 3]);
+        assert_eq!(tree.weight(StreamId(1)), Some(16));
+
+        // Non-exclusive: 5 joins 1 and 3 under 0.
+        tree.reprioritise(StreamId(5), &priority(0, 255, false)).unwrap();
+        assert_eq!(children(&tree, 0), [1, 3, 5]);
+        assert_eq!(tree.weight(StreamId(5)), Some(256));
+
+        // Exclusive: 7 takes over 0’s children.
+        tree.reprioritise(StreamId(7), &priority(0, 0, true)).unwrap();
+        assert_eq!(children(&tree, 0), [7]);
+        assert_eq!(children(&tree, 7), [1, 3, 5]);
+        assert_eq!(tree.parent(StreamId(5)), Some(StreamId(7)));
+
+        // Depending on a stream not in the tree gets the default priority.
+        tree.reprioritise(StreamId(9), &priority(11, 99, true)).unwrap();
+        assert_eq!(tree.parent(StreamId(9)), Some(StreamId(0)));
+        assert_eq!(tree.weight(StreamId(9)), Some(16));
+
+        assert_eq!(tree.reprioritise(StreamId(9), &priority(9, 0, false)),
+                   Err(ErrorCode::PROTOCOL_ERROR));
+    }
+
+    #[test]
+    fn dependent_on_own_dependency() {
+        // This is the example from section 5.3.3: A is made dependent on D.
+        //
+        //     x                x                x
+        //     |               / \               |
+        //     A              D   A              D
+        //    / \            /   / \             |
+        //   B   C     ==>  F   B   C    ==>     A
+        //      / \                 |           /|\
+        //     D   E                E          B C F
+        //     |                                 |
+        //     F                                 E
+        let (a, b, c, d, e, f) = (1, 3, 5, 7, 9, 11);
+        let build = || {
+            let mut tree = Tree::new();
+            for &(stream, parent) in &[(a, 0), (b, a), (c, a), (d, c), (e, c), (f, d)] {
+                tree.reprioritise(StreamId(stream), &priority(parent, 20, false)).unwrap();
+            }
+            tree
+        };
+
+        let mut tree = build();
+        tree.reprioritise(StreamId(a), &priority(d, 0, false)).unwrap();
+        assert_eq!(children(&tree, 0), [d]);
+        assert_eq!(children(&tree, d), [a, f]);
+        assert_eq!(children(&tree, a), [b, c]);
+        assert_eq!(children(&tree, c), [e]);
+        assert_eq!(tree.weight(StreamId(d)), Some(21));
+        assert_eq!(tree.weight(StreamId(a)), Some(1));
+
+        let mut exclusive_tree = build();
+        exclusive_tree.reprioritise(StreamId(a), &priority(d, 0, true)).unwrap();
+        assert_eq!(children(&exclusive_tree, 0), [d]);
+        assert_eq!(children(&exclusive_tree, d), [a]);
+        assert_eq!(children(&exclusive_tree, a), [b, c, f]);
+        assert_eq!(children(&exclusive_tree, c), [e]);
+    }
+
+    #[test]
+    fn remove() {
+        let mut tree = Tree::new();
+        tree.reprioritise(StreamId(1), &priority(0, 0, false)).unwrap();
+        tree.reprioritise(StreamId(3), &priority(1, 200, false)).unwrap();
+        tree.reprioritise(StreamId(5), &priority(3, 100, false)).unwrap();
+        tree.remove(StreamId(3));
+        assert!(!tree.contains(StreamId(3)));
+        assert_eq!(children(&tree, 1), []);
+        assert_eq!(children(&tree, 0), [1, 5]);
+        assert_eq!(tree.weight(StreamId(5)), Some(16));
+    }
+
+    #[test]
+    fn scheduling() {
+        let mut tree = Tree::new();
+        tree.reprioritise(StreamId(1), &priority(0, 0, false)).unwrap();
+        tree.reprioritise(StreamId(3), &priority(1, 0, false)).unwrap();
+        tree.reprioritise(StreamId(5), &priority(0, 2, false)).unwrap();
+        assert_eq!(tree.next(|_| true), None);
+
+        // A stream goes before the streams that depend on it.
+        tree.set_ready(StreamId(1), true);
+        tree.set_ready(StreamId(3), true);
+        assert_eq!(tree.next(|_| true), Some(StreamId(1)));
+        assert_eq!(tree.next(|id| id != StreamId(1)), Some(StreamId(3)));
+        tree.set_ready(StreamId(1), false);
+        assert_eq!(tree.next(|_| true), Some(StreamId(3)));
+
+        // Siblings share in proportion to their weights: 1 (and so 3) has 1, 5 has 3.
+        tree.set_ready(StreamId(5), true);
+        let mut counts = [0; 6];
+        for _ in 0..400 {
+            let stream_id = tree.next(|_| true).unwrap();
+            counts[stream_id.0 as usize] += 1;
+            tree.sent(stream_id, 1000);
+        }
+        assert_eq!(counts, [0, 0, 0, 100, 0, 300]);
+
+        tree.set_ready(StreamId(5), false);
+        assert_eq!(tree.next(|_| true), Some(StreamId(3)));
+        tree.remove(StreamId(3));
+        assert_eq!(tree.next(|_| true), None);
+    }
+
+    #[test]
+    fn no_credit_for_idling() {
+        let mut tree = Tree::new();
+        tree.set_ready(StreamId(1), true);
+        for _ in 0..100 {
+            assert_eq!(tree.next(|_| true), Some(StreamId(1)));
+            tree.sent(StreamId(1), 1000);
+        }
+        // Stream 3 doesn’t get to monopolise the connection just because it came late.
+        tree.set_ready(StreamId(3), true);
+        let mut counts = [0; 4];
+        for _ in 0..10 {
+            let stream_id = tree.next(|_| true).unwrap();
+            counts[stream_id.0 as usize] += 1;
+            tree.sent(stream_id, 1000);
+        }
+        assert_eq!(counts, [0, 5, 0, 5]);
+    }
+}
@@ -0,0 +1,69 @@
+//! Protection against peers that abuse the protocol to tie up resources.
+//!
+//! Each of these is perfectly legal in moderation, which is why they make for cheap attacks: the
+//! peer does little work and makes us do a lot. A `Connection` counts them, and once a peer
+//! exceeds one of the limits set in `Protection` it is told to go away with a connection error
+//! of type ENHANCE_YOUR_CALM.
+//!
+//! > An endpoint that doesn't monitor use of these features exposes itself to a risk of
+//! > denial-of-service attack.  Implementations SHOULD track the use of these features and set
+//! > limits on their use.  An endpoint MAY treat activity that is suspicious as a connection
+//! > error (Section 5.4.1) of type ENHANCE_YOUR_CALM.
+//!
+//! (That is [RFC 9113, section 10.5][spec].) The connection has no clock, so nothing here is a
+//! rate; instead, the counts of things that are only bad in bulk are worked off by the peer
+//! doing something useful, as detailed for each limit.
+//!
+//! [spec]: http://tools.ietf.org/html/rfc9113#section-10.5
+
+/// The limits on abuse that a connection tolerates, for `Connection::set_protection`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Protection {
+    /// How many more streams the peer may reset before we have finished responding on them than
+    /// it lets us finish; that is, each such reset counts one and each stream the peer opened
+    /// that closes normally takes one off again.
+    ///
+    /// This is the rapid reset attack (CVE-2023-44487): opening streams and resetting them at
+    /// once costs the peer nothing, and does not count against SETTINGS_MAX_CONCURRENT_STREAMS,
+    /// but each one puts the application to work.
+    pub max_rapid_resets: u32,
+
+    /// How many CONTINUATION frames a header block may have.
+    ///
+    /// The limit on the size of a header block does not help against a flood of CONTINUATION
+    /// frames carrying little or nothing.
+    pub max_continuation_frames: u32,
+
+    /// How many frames that the peer has made us send without any say from the application
+    /// (PING and SETTINGS acknowledgments and RST_STREAM) may be waiting to be taken with
+    /// `next_frame`.
+    ///
+    /// This covers SETTINGS and PING floods, and resets provoked by bad frames, from a peer that
+    /// makes us respond faster than it reads the responses.
+    pub max_pending_responses: u32,
+
+    /// How many DATA frames with no data and without END_STREAM may arrive in a row; any DATA
+    /// frame with data starts the count again.
+    pub max_empty_data_frames: u32,
+
+    /// How many streams the server may have reserved (with PUSH_PROMISE) and not yet started.
+    pub max_reserved_streams: u32,
+
+    /// How many idle streams the peer may have put into the priority tree with PRIORITY frames.
+    ///
+    /// These are legitimately used to group streams, but each takes up memory until it is used.
+    pub max_idle_streams: u32,
+}
+
+impl Default for Protection {
+    fn default() -> Protection {
+        Protection {
+            max_rapid_resets: 100,
+            max_continuation_frames: 32,
+            max_pending_responses: 1000,
+            max_empty_data_frames: 100,
+            max_reserved_streams: 100,
+            max_idle_streams: 100,
+        }
+    }
+}
@@ -1,12 +1,124 @@
-//! Stream identifier matters.
+//! Stream identifier and state matters.
+
+use http2::Error;
+use http2::frame::ErrorCode;
 
 /// A stream identifier.
 ///
-/// This is only a 31-bit quantity, but that the most significant bit is zero is not enforced at
-/// the type level; you must maintain that yourself.
+/// This is only a 31-bit quantity. The field is public, so that the most significant bit is zero
+/// is not enforced at the type level; `StreamId::new` checks it, and `StreamIdAllocator` only ever
+/// hands out valid identifiers.
+///
+/// > Streams initiated by a client MUST use odd-numbered stream identifiers; those initiated by
+/// > the server MUST use even-numbered stream identifiers.  A stream identifier of zero (0x0) is
+/// > used for connection control messages; the stream identifier of zero cannot be used to
+/// > establish a new stream.
 #[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub struct StreamId(pub u32);
 
+/// The largest stream identifier, 2³¹ - 1.
+pub const MAX_STREAM_ID: StreamId = StreamId(0x7fffffff);
+
+impl StreamId {
+    /// Constructs a stream identifier, or returns `None` if the reserved most significant bit is
+    /// set.
+    pub fn new(id: u32) -> Option<StreamId> {
+        if id <= MAX_STREAM_ID.0 {
+            Some(StreamId(id))
+        } else {
+            None
+        }
+    }
+
+    /// Whether this is the identifier of a stream that a client initiates (an odd number).
+    pub fn is_client_initiated(&self) -> bool {
+        self.0 % 2 == 1
+    }
+
+    /// Whether this is the identifier of a stream that a server initiates (a nonzero even
+    /// number).
+    pub fn is_server_initiated(&self) -> bool {
+        self.0 != 0 && self.0 % 2 == 0
+    }
+}
+
+/// Hands out the identifiers of the streams that one end of a connection initiates. See [RFC
+/// 7540, section 5.1.1][spec].
+///
+/// > The identifier of a newly established stream MUST be numerically greater than all streams
+/// > that the initiating endpoint has opened or reserved.
+///
+/// Identifiers are handed out in increasing order, odd ones for a client and even ones for a
+/// server, until there are none left:
+///
+/// > Stream identifiers cannot be reused.  Long-lived connections can result in an endpoint
+/// > exhausting the available range of stream identifiers.  A client that is unable to establish
+/// > a new stream identifier can establish a new connection for new streams.  A server that is
+/// > unable to establish a new stream identifier can send a GOAWAY frame so that the client is
+/// > forced to open a new connection for new streams.
+///
+/// An identifier can also be claimed out of order with `claim`, which is how the identifiers the
+/// peer uses are checked; any that are skipped can never be used.
+///
+/// [spec]: http://tools.ietf.org/html/rfc7540#section-5.1.1
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct StreamIdAllocator {
+    /// The lowest identifier that may yet be used. This goes past `MAX_STREAM_ID` once all have
+    /// been used.
+    next: u32,
+}
+
+impl StreamIdAllocator {
+    /// An allocator of the odd-numbered identifiers, for streams a client initiates.
+    pub fn client() -> StreamIdAllocator {
+        StreamIdAllocator { next: 1 }
+    }
+
+    /// An allocator of the even-numbered identifiers, for streams a server initiates.
+    pub fn server() -> StreamIdAllocator {
+        StreamIdAllocator { next: 2 }
+    }
+
+    /// The identifier that will be handed out next, or `None` if they have all been used.
+    pub fn peek(&self) -> Option<StreamId> {
+        StreamId::new(self.next)
+    }
+
+    /// Whether a stream identifier is of the kind this hands out.
+    pub fn owns(&self, stream_id: StreamId) -> bool {
+        stream_id.0 != 0 && stream_id.0 % 2 == self.next % 2
+    }
+
+    /// Whether a stream identifier of the kind this hands out has been used or skipped.
+    pub fn is_used(&self, stream_id: StreamId) -> bool {
+        self.owns(stream_id) && stream_id.0 < self.next
+    }
+
+    /// Claim a particular stream identifier, skipping any lower ones that have not been used.
+    ///
+    /// This fails, returning false, if the identifier is not of the kind this hands out, or if it
+    /// or a higher one has already been used.
+    pub fn claim(&mut self, stream_id: StreamId) -> bool {
+        if !self.owns(stream_id) || self.is_used(stream_id) || stream_id > MAX_STREAM_ID {
+            return false;
+        }
+        self.next = stream_id.0 + 2;
+        true
+    }
+}
+
+impl Iterator for StreamIdAllocator {
+    type Item = StreamId;
+
+    fn next(&mut self) -> Option<StreamId> {
+        let stream_id = self.peek();
+        if stream_id.is_some() {
+            self.next += 2;
+        }
+        stream_id
+    }
+}
+
 macro_rules! stream_id_from_be_slice {
     ($slice:expr, $offset:expr) => {{
         let slice = $slice;
@@ -16,3 +128,527 @@ macro_rules! stream_id_from_be_slice {
                                         (slice[$offset + 3] as u32))
     }}
 }
+
+/// The types of frame which affect stream state.
+///
+/// CONTINUATION frames are not included, being part of the HEADERS or PUSH_PROMISE frame they
+/// follow; nor are SETTINGS, PING and GOAWAY, which apply to the connection as a whole.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Kind {
+    /// DATA
+    Data,
+    /// HEADERS
+    Headers,
+    /// PRIORITY
+    Priority,
+    /// RST_STREAM
+    RstStream,
+    /// PUSH_PROMISE, on the stream it is associated with (not the promised stream).
+    PushPromise,
+    /// WINDOW_UPDATE
+    WindowUpdate,
+}
+
+/// How a stream came to be closed.
+///
+/// This matters because of the different treatment that frames received afterwards get.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Closed {
+    /// The peer sent END_STREAM, after we had already done so.
+    ReceivedEndStream,
+    /// We sent END_STREAM, after the peer had already done so.
+    SentEndStream,
+    /// We sent RST_STREAM.
+    ResetLocally,
+    /// The peer sent RST_STREAM.
+    ResetRemotely,
+    /// The stream was never used, but a stream with a higher identifier initiated by the same end
+    /// has been, which closed it implicitly.
+    Skipped,
+    /// The stream closed so long ago that how is no longer remembered.
+    Forgotten,
+}
+
+/// The state of a stream. See [RFC 7540, section 5.1][spec].
+///
+/// > ```text
+/// >                              +--------+
+/// >                      send PP |        | recv PP
+/// >                     ,--------|  idle  |--------.
+/// >                    /         |        |         \
+/// >                   v          +--------+          v
+/// >            +----------+          |           +----------+
+/// >            |          |          | send H /  |          |
+/// >     ,------| reserved |          | recv H    | reserved |------.
+/// >     |      | (local)  |          |           | (remote) |      |
+/// >     |      +----------+          v           +----------+      |
+/// >     |          |             +--------+             |          |
+/// >     |          |     recv ES |        | send ES     |          |
+/// >     |   send H |     ,-------|  open  |-------.     | recv H   |
+/// >     |          |    /        |        |        \    |          |
+/// >     |          v   v         +--------+         v   v          |
+/// >     |      +----------+          |           +----------+      |
+/// >     |      |   half   |          |           |   half   |      |
+/// >     |      |  closed  |          | send R /  |  closed  |      |
+/// >     |      | (remote) |          | recv R    | (local)  |      |
+/// >     |      +----------+          |           +----------+      |
+/// >     |           |                |                 |           |
+/// >     |           | send ES /      |       recv ES / |           |
+/// >     |           | send R /       v        send R / |           |
+/// >     |           | recv R     +--------+   recv R   |           |
+/// >     | send R /  `----------->|        |<-----------'  send R / |
+/// >     | recv R                 | closed |               recv R   |
+/// >     `----------------------->|        |<----------------------'
+/// >                              +--------+
+/// > ```
+///
+/// The transitions are made by `receive` and `send`, which also check that the frame is permitted
+/// in the current state, and by `reserve_local` and `reserve_remote` for the promised stream of a
+/// PUSH_PROMISE.
+///
+/// [spec]: http://tools.ietf.org/html/rfc7540#section-5.1
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum State {
+    /// The stream has not been used yet.
+    Idle,
+    /// We have promised the stream with a PUSH_PROMISE.
+    ReservedLocal,
+    /// The peer has promised the stream with a PUSH_PROMISE.
+    ReservedRemote,
+    /// Both ends may send frames.
+    Open,
+    /// We have sent END_STREAM.
+    HalfClosedLocal,
+    /// The peer has sent END_STREAM.
+    HalfClosedRemote,
+    /// The stream is finished with.
+    Closed(Closed),
+}
+
+impl Default for State {
+    fn default() -> State {
+        State::Idle
+    }
+}
+
+impl State {
+    /// Apply a frame received on the stream, with its END_STREAM flag.
+    ///
+    /// If the frame is permitted, this returns whether it should be acted upon; frames that
+    /// arrive after we have reset the stream, for example, must be ignored. If the frame is not
+    /// permitted, the error returned will be a stream error or a connection error as appropriate.
+    ///
+    /// Whether the stream is one that the peer may initiate is not checked here.
+    pub fn receive(&mut self, stream_id: StreamId, kind: Kind, end_stream: bool)
+            -> Result<bool, Error> {
+        let end_stream = end_stream && (kind == Kind::Data || kind == Kind::Headers);
+        let next = match (*self, kind) {
+            // > Receiving any frame other than HEADERS or PRIORITY on a stream in this
+            // > state MUST be treated as a connection error (Section 5.4.1) of type
+            // > PROTOCOL_ERROR.
+            (State::Idle, Kind::Headers) => if end_stream {
+                State::HalfClosedRemote
+            } else {
+                State::Open
+            },
+            (State::Idle, Kind::Priority) => State::Idle,
+            (State::Idle, _) => return Err(Error::Connection(ErrorCode::PROTOCOL_ERROR)),
+
+            // > A PRIORITY or WINDOW_UPDATE frame MAY be received in this state.
+            // > Receiving any type of frame other than RST_STREAM, PRIORITY, or
+            // > WINDOW_UPDATE on a stream in this state MUST be treated as a
+            // > connection error (Section 5.4.1) of type PROTOCOL_ERROR.
+            (State::ReservedLocal, Kind::Priority) |
+            (State::ReservedLocal, Kind::WindowUpdate) => State::ReservedLocal,
+            (State::ReservedLocal, Kind::RstStream) => State::Closed(Closed::ResetRemotely),
+            (State::ReservedLocal, _) => return Err(Error::Connection(ErrorCode::PROTOCOL_ERROR)),
+
+            // > Receiving any type of frame other than HEADERS, RST_STREAM, or
+            // > PRIORITY on a stream in this state MUST be treated as a connection
+            // > error (Section 5.4.1) of type PROTOCOL_ERROR.
+            (State::ReservedRemote, Kind::Headers) => if end_stream {
+                State::Closed(Closed::ReceivedEndStream)
+            } else {
+                State::HalfClosedLocal
+            },
+            (State::ReservedRemote, Kind::Priority) => State::ReservedRemote,
+            (State::ReservedRemote, Kind::RstStream) => State::Closed(Closed::ResetRemotely),
+            (State::ReservedRemote, _) => {
+                return Err(Error::Connection(ErrorCode::PROTOCOL_ERROR));
+            },
+
+            // > A stream in the "open" state may be used by both peers to send
+            // > frames of any type.
+            (State::Open, Kind::RstStream) => State::Closed(Closed::ResetRemotely),
+            (State::Open, _) => if end_stream {
+                State::HalfClosedRemote
+            } else {
+                State::Open
+            },
+
+            // > A stream that is in the "half-closed (local)" state cannot be used
+            // > for sending frames other than WINDOW_UPDATE, PRIORITY, and
+            // > RST_STREAM.
+            //
+            // (But it can receive anything.)
+            (State::HalfClosedLocal, Kind::RstStream) => State::Closed(Closed::ResetRemotely),
+            (State::HalfClosedLocal, _) => if end_stream {
+                State::Closed(Closed::ReceivedEndStream)
+            } else {
+                State::HalfClosedLocal
+            },
+
+            // > If an endpoint receives additional frames, other than
+            // > WINDOW_UPDATE, PRIORITY, or RST_STREAM, for a stream that is in
+            // > this state, it MUST respond with a stream error (Section 5.4.2) of
+            // > type STREAM_CLOSED.
+            (State::HalfClosedRemote, Kind::Priority) |
+            (State::HalfClosedRemote, Kind::WindowUpdate) => State::HalfClosedRemote,
+            (State::HalfClosedRemote, Kind::RstStream) => State::Closed(Closed::ResetRemotely),
+            (State::HalfClosedRemote, _) => {
+                return Err(Error::Stream(stream_id, ErrorCode::STREAM_CLOSED));
+            },
+
+            // > An endpoint MUST NOT send frames other than PRIORITY on a closed
+            // > stream.
+            (State::Closed(_), Kind::Priority) => return Ok(true),
+            // > An endpoint that receives an unexpected stream identifier MUST respond with a
+            // > connection error (Section 5.4.1) of type PROTOCOL_ERROR.
+            //
+            // (That’s from section 5.1.1; the stream was never opened.)
+            (State::Closed(Closed::Skipped), _) => {
+                return Err(Error::Connection(ErrorCode::PROTOCOL_ERROR));
+            },
+            // > If this state is reached as a result of sending a RST_STREAM
+            // > frame, the peer that receives the RST_STREAM might have already
+            // > sent -- or enqueued for sending -- frames on the stream that
+            // > cannot be withdrawn.  An endpoint MUST ignore frames that it
+            // > receives on closed streams after it has sent a RST_STREAM frame.
+            (State::Closed(Closed::ResetLocally), _) => return Ok(false),
+            // Whether it was that or the peer who closed the stream, frames could be arriving on
+            // it for good reason, so they are ignored just the same.
+            (State::Closed(Closed::Forgotten), _) => return Ok(false),
+            // > WINDOW_UPDATE or RST_STREAM frames can be received in this state
+            // > for a short period after a DATA or HEADERS frame containing an
+            // > END_STREAM flag is sent.
+            (State::Closed(Closed::SentEndStream), Kind::WindowUpdate) |
+            (State::Closed(Closed::SentEndStream), Kind::RstStream) => return Ok(false),
+            // > An endpoint that receives any frame other than PRIORITY after
+            // > receiving a RST_STREAM MUST treat that as a stream error (Section
+            // > 5.4.2) of type STREAM_CLOSED.  Similarly, an endpoint that
+            // > receives any frames after receiving a frame with the END_STREAM
+            // > flag set MUST treat that as a connection error (Section 5.4.1) of
+            // > type STREAM_CLOSED, unless the frame is permitted as described
+            // > below.
+            (State::Closed(Closed::ResetRemotely), _) => {
+                return Err(Error::Stream(stream_id, ErrorCode::STREAM_CLOSED));
+            },
+            (State::Closed(_), _) => return Err(Error::Connection(ErrorCode::STREAM_CLOSED)),
+        };
+        *self = next;
+        Ok(true)
+    }
+
+    /// Apply a frame to be sent on the stream, with its END_STREAM flag.
+    ///
+    /// If the frame is not permitted in the current state, the state is left unchanged and a
+    /// stream error of type STREAM_CLOSED (for a closed or half-closed (local) stream) or
+    /// PROTOCOL_ERROR (otherwise) is returned; such a frame should not be sent.
+    pub fn send(&mut self, stream_id: StreamId, kind: Kind, end_stream: bool)
+            -> Result<(), Error> {
+        let end_stream = end_stream && (kind == Kind::Data || kind == Kind::Headers);
+        let next = match (*self, kind) {
+            (_, Kind::Priority) => return Ok(()),
+
+            (State::Idle, Kind::Headers) => if end_stream {
+                State::HalfClosedLocal
+            } else {
+                State::Open
+            },
+
+            (State::ReservedLocal, Kind::Headers) => if end_stream {
+                State::Closed(Closed::SentEndStream)
+            } else {
+                State::HalfClosedRemote
+            },
+            (State::ReservedLocal, Kind::RstStream) => State::Closed(Closed::ResetLocally),
+
+            (State::ReservedRemote, Kind::WindowUpdate) => State::ReservedRemote,
+            (State::ReservedRemote, Kind::RstStream) => State::Closed(Closed::ResetLocally),
+
+            (State::Open, Kind::RstStream) => State::Closed(Closed::ResetLocally),
+            (State::Open, _) => if end_stream {
+                State::HalfClosedLocal
+            } else {
+                State::Open
+            },
+
+            (State::HalfClosedLocal, Kind::WindowUpdate) => State::HalfClosedLocal,
+            (State::HalfClosedLocal, Kind::RstStream) => State::Closed(Closed::ResetLocally),
+            (State::HalfClosedLocal, _) => {
+                return Err(Error::Stream(stream_id, ErrorCode::STREAM_CLOSED));
+            },
+
+            (State::HalfClosedRemote, Kind::RstStream) => State::Closed(Closed::ResetLocally),
+            (State::HalfClosedRemote, _) => if end_stream {
+                State::Closed(Closed::SentEndStream)
+            } else {
+                State::HalfClosedRemote
+            },
+
+            (State::Closed(Closed::Skipped), _) => {
+                return Err(Error::Stream(stream_id, ErrorCode::PROTOCOL_ERROR));
+            },
+            (State::Closed(_), _) => {
+                return Err(Error::Stream(stream_id, ErrorCode::STREAM_CLOSED));
+            },
+
+            (_, _) => return Err(Error::Stream(stream_id, ErrorCode::PROTOCOL_ERROR)),
+        };
+        *self = next;
+        Ok(())
+    }
+
+    /// Reserve the stream for a PUSH_PROMISE that we are sending.
+    ///
+    /// The stream must be idle; if it is not, a connection error of type PROTOCOL_ERROR is
+    /// returned and the PUSH_PROMISE should not be sent.
+    pub fn reserve_local(&mut self) -> Result<(), Error> {
+        self.reserve(State::ReservedLocal)
+    }
+
+    /// Reserve the stream for a PUSH_PROMISE that we have received.
+    ///
+    /// The stream must be idle; if it is not, a connection error of type PROTOCOL_ERROR is
+    /// returned.
+    pub fn reserve_remote(&mut self) -> Result<(), Error> {
+        self.reserve(State::ReservedRemote)
+    }
+
+    fn reserve(&mut self, state: State) -> Result<(), Error> {
+        // > A receiver MUST treat the receipt of a PUSH_PROMISE that promises an illegal stream
+        // > identifier (Section 5.1.1) as a connection error (Section 5.4.1) of type
+        // > PROTOCOL_ERROR.  Note that an illegal stream identifier is an identifier for a
+        // > stream that is not currently in the "idle" state.
+        if *self == State::Idle {
+            *self = state;
+            Ok(())
+        } else {
+            Err(Error::Connection(ErrorCode::PROTOCOL_ERROR))
+        }
+    }
+
+    /// Close the stream because we are resetting it, whatever state it was in.
+    pub fn reset_locally(&mut self) {
+        *self = State::Closed(Closed::ResetLocally);
+    }
+
+    /// Whether the stream is open or half-closed; that is, whether it counts towards the
+    /// SETTINGS_MAX_CONCURRENT_STREAMS limit.
+    pub fn is_active(&self) -> bool {
+        match *self {
+            State::Open | State::HalfClosedLocal | State::HalfClosedRemote => true,
+            _ => false,
+        }
+    }
+
+    /// Whether the stream is closed.
+    pub fn is_closed(&self) -> bool {
+        match *self {
+            State::Closed(_) => true,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Closed, Kind, State, StreamId, StreamIdAllocator, MAX_STREAM_ID};
+    use http2::Error;
+    use http2::frame::ErrorCode;
+
+    const ID: StreamId = StreamId(1);
+
+    /// Receive each frame in turn, checking the state after each.
+    fn receive(mut state: State, frames: &[(Kind, bool, Result<State, Error>)]) {
+        for &(kind, end_stream, expected) in frames {
+            let result = state.receive(ID, kind, end_stream).map(|_| state);
+            assert_eq!(result, expected);
+        }
+    }
+
+    #[test]
+    fn request_and_response() {
+        let mut state = State::Idle;
+        assert_eq!(state.receive(ID, Kind::Headers, false), Ok(true));
+        assert_eq!(state, State::Open);
+        assert_eq!(state.receive(ID, Kind::Data, true), Ok(true));
+        assert_eq!(state, State::HalfClosedRemote);
+        assert_eq!(state.send(ID, Kind::Headers, false), Ok(()));
+        assert_eq!(state.send(ID, Kind::Data, true), Ok(()));
+        assert_eq!(state, State::Closed(Closed::SentEndStream));
+        // The peer may not have seen our END_STREAM yet.
+        assert_eq!(state.receive(ID, Kind::WindowUpdate, false), Ok(false));
+        assert_eq!(state.send(ID, Kind::Data, false),
+                   Err(Error::Stream(ID, ErrorCode::STREAM_CLOSED)));
+    }
+
+    #[test]
+    fn idle() {
+        receive(State::Idle, &[
+            (Kind::Priority, false, Ok(State::Idle)),
+            (Kind::Data, false, Err(Error::Connection(ErrorCode::PROTOCOL_ERROR))),
+            (Kind::RstStream, false, Err(Error::Connection(ErrorCode::PROTOCOL_ERROR))),
+            (Kind::WindowUpdate, false, Err(Error::Connection(ErrorCode::PROTOCOL_ERROR))),
+            (Kind::Headers, true, Ok(State::HalfClosedRemote)),
+        ]);
+        let mut state = State::Idle;
+        assert_eq!(state.send(ID, Kind::Data, false),
+                   Err(Error::Stream(ID, ErrorCode::PROTOCOL_ERROR)));
+        assert_eq!(state.send(ID, Kind::RstStream, false),
+                   Err(Error::Stream(ID, ErrorCode::PROTOCOL_ERROR)));
+        assert_eq!(state.send(ID, Kind::Headers, true), Ok(()));
+        assert_eq!(state, State::HalfClosedLocal);
+    }
+
+    #[test]
+    fn half_closed_remote() {
+        receive(State::HalfClosedRemote, &[
+            (Kind::WindowUpdate, false, Ok(State::HalfClosedRemote)),
+            (Kind::Priority, false, Ok(State::HalfClosedRemote)),
+            (Kind::Data, false, Err(Error::Stream(ID, ErrorCode::STREAM_CLOSED))),
+            (Kind::Headers, true, Err(Error::Stream(ID, ErrorCode::STREAM_CLOSED))),
+            (Kind::RstStream, false, Ok(State::Closed(Closed::ResetRemotely))),
+        ]);
+    }
+
+    #[test]
+    fn half_closed_local() {
+        receive(State::HalfClosedLocal, &[
+            (Kind::Data, false, Ok(State::HalfClosedLocal)),
+            (Kind::Headers, true, Ok(State::Closed(Closed::ReceivedEndStream))),
+            (Kind::Priority, false, Ok(State::Closed(Closed::ReceivedEndStream))),
+            (Kind::Data, false, Err(Error::Connection(ErrorCode::STREAM_CLOSED))),
+            (Kind::WindowUpdate, false, Err(Error::Connection(ErrorCode::STREAM_CLOSED))),
+        ]);
+        let mut state = State::HalfClosedLocal;
+        assert_eq!(state.send(ID, Kind::Data, false),
+                   Err(Error::Stream(ID, ErrorCode::STREAM_CLOSED)));
+        assert_eq!(state.send(ID, Kind::WindowUpdate, false), Ok(()));
+    }
+
+    #[test]
+    fn reset() {
+        receive(State::Open, &[
+            (Kind::RstStream, false, Ok(State::Closed(Closed::ResetRemotely))),
+            (Kind::Priority, false, Ok(State::Closed(Closed::ResetRemotely))),
+            (Kind::Data, false, Err(Error::Stream(ID, ErrorCode::STREAM_CLOSED))),
+        ]);
+
+        let mut state = State::Open;
+        assert_eq!(state.send(ID, Kind::RstStream, false), Ok(()));
+        assert_eq!(state.receive(ID, Kind::Data, true), Ok(false));
+        assert_eq!(state.receive(ID, Kind::Headers, false), Ok(false));
+        assert_eq!(state, State::Closed(Closed::ResetLocally));
+    }
+
+    #[test]
+    fn reserved() {
+        let mut state = State::Idle;
+        assert_eq!(state.reserve_local(), Ok(()));
+        assert_eq!(state.reserve_local(), Err(Error::Connection(ErrorCode::PROTOCOL_ERROR)));
+        receive(state, &[
+            (Kind::WindowUpdate, false, Ok(State::ReservedLocal)),
+            (Kind::Data, false, Err(Error::Connection(ErrorCode::PROTOCOL_ERROR))),
+        ]);
+        assert_eq!(state.send(ID, Kind::Headers, false), Ok(()));
+        assert_eq!(state, State::HalfClosedRemote);
+
+        let mut state = State::Idle;
+        assert_eq!(state.reserve_remote(), Ok(()));
+        receive(state, &[
+            (Kind::WindowUpdate, false, Err(Error::Connection(ErrorCode::PROTOCOL_ERROR))),
+            (Kind::Headers, false, Ok(State::HalfClosedLocal)),
+        ]);
+        assert_eq!(state.send(ID, Kind::Headers, false),
+                   Err(Error::Stream(ID, ErrorCode::PROTOCOL_ERROR)));
+    }
+
+    #[test]
+    fn skipped() {
+        receive(State::Closed(Closed::Skipped), &[
+            (Kind::Priority, false, Ok(State::Closed(Closed::Skipped))),
+            (Kind::Headers, false, Err(Error::Connection(ErrorCode::PROTOCOL_ERROR))),
+            (Kind::Data, false, Err(Error::Connection(ErrorCode::PROTOCOL_ERROR))),
+        ]);
+        let mut state = State::Closed(Closed::Skipped);
+        assert_eq!(state.send(ID, Kind::Headers, false),
+                   Err(Error::Stream(ID, ErrorCode::PROTOCOL_ERROR)));
+        assert_eq!(state.reserve_local(), Err(Error::Connection(ErrorCode::PROTOCOL_ERROR)));
+    }
+
+    #[test]
+    fn forgotten() {
+        receive(State::Closed(Closed::Forgotten), &[
+            (Kind::Priority, false, Ok(State::Closed(Closed::Forgotten))),
+            (Kind::Headers, true, Ok(State::Closed(Closed::Forgotten))),
+            (Kind::Data, false, Ok(State::Closed(Closed::Forgotten))),
+            (Kind::RstStream, false, Ok(State::Closed(Closed::Forgotten))),
+        ]);
+        let mut state = State::Closed(Closed::Forgotten);
+        assert_eq!(state.send(ID, Kind::Data, false),
+                   Err(Error::Stream(ID, ErrorCode::STREAM_CLOSED)));
+    }
+
+    #[test]
+    fn stream_id() {
+        assert_eq!(StreamId::new(0), Some(StreamId(0)));
+        assert_eq!(StreamId::new(0x7fffffff), Some(MAX_STREAM_ID));
+        assert_eq!(StreamId::new(0x80000000), None);
+        assert_eq!(StreamId::new(0xffffffff), None);
+        assert!(!StreamId(0).is_client_initiated() && !StreamId(0).is_server_initiated());
+        assert!(StreamId(1).is_client_initiated() && !StreamId(1).is_server_initiated());
+        assert!(!StreamId(2).is_client_initiated() && StreamId(2).is_server_initiated());
+    }
+
+    #[test]
+    fn allocator() {
+        let mut client = StreamIdAllocator::client();
+        assert_eq!(client.peek(), Some(StreamId(1)));
+        assert_eq!(client.next(), Some(StreamId(1)));
+        assert_eq!(client.next(), Some(StreamId(3)));
+        assert!(client.is_used(StreamId(3)) && !client.is_used(StreamId(5)));
+        assert!(!client.owns(StreamId(0)) && !client.owns(StreamId(4)));
+
+        // Skipping some.
+        assert!(client.claim(StreamId(9)));
+        assert!(client.is_used(StreamId(5)) && client.is_used(StreamId(7)));
+        assert_eq!(client.next(), Some(StreamId(11)));
+        // Going back, or using the other end’s identifiers.
+        assert!(!client.claim(StreamId(11)));
+        assert!(!client.claim(StreamId(7)));
+        assert!(!client.claim(StreamId(14)));
+        assert!(!client.claim(StreamId(0x80000001)));
+
+        let mut server = StreamIdAllocator::server();
+        assert_eq!(server.take(3).collect::<Vec<_>>(),
+                   vec![StreamId(2), StreamId(4), StreamId(6)]);
+        assert!(!server.claim(StreamId(0)));
+    }
+
+    #[test]
+    fn allocator_exhaustion() {
+        let mut client = StreamIdAllocator::client();
+        assert!(client.claim(StreamId(0x7ffffffd)));
+        assert_eq!(client.next(), Some(MAX_STREAM_ID));
+        assert_eq!(client.peek(), None);
+        assert_eq!(client.next(), None);
+        assert_eq!(client.next(), None);
+        assert!(client.is_used(MAX_STREAM_ID));
+
+        let mut server = StreamIdAllocator::server();
+        assert!(server.claim(StreamId(0x7ffffffe)));
+        assert_eq!(server.next(), None);
+        assert!(!server.claim(StreamId(0x80000000)));
+    }
+}
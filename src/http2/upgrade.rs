@@ -0,0 +1,136 @@
+//! Starting HTTP/2 by upgrading an HTTP/1.1 connection. See [RFC 7540, section 3.2][spec].
+//!
+//! The client sends an HTTP/1.1 request with the `Upgrade: h2c` and `HTTP2-Settings` header fields
+//! (and `Connection: Upgrade, HTTP2-Settings`); the latter carries its SETTINGS, made with
+//! `encode_settings`. A server willing to upgrade decodes them with `decode_settings`, responds
+//! with `101 Switching Protocols` and then carries on with HTTP/2, as does the client;
+//! `Connection::upgrade` starts each end off in the right state.
+//!
+//! After the 101 response, each end still sends its connection preface as usual.
+//!
+//! [spec]: http://tools.ietf.org/html/rfc7540#section-3.2
+
+use ByteTendril;
+use http2::frame::{Frame, Header, Payload};
+use http2::frame::settings::Settings;
+use http2::stream::StreamId;
+
+/// The base64url alphabet, RFC 4648, section 5.
+static ALPHABET: &'static [u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+/// Encode settings as the value of the `HTTP2-Settings` header field.
+///
+/// > The content of the HTTP2-Settings header field is the payload of a SETTINGS frame (Section
+/// > 6.5), encoded as a base64url string (that is, the URL- and filename-safe Base64 encoding
+/// > described in Section 5 of [RFC4648], with any trailing '=' characters omitted).
+///
+/// # Panics
+///
+/// If `settings` is `Settings::Acknowledgment`.
+pub fn encode_settings(settings: Settings) -> String {
+    if let Settings::Acknowledgment = settings {
+        panic!("upgrade::encode_settings called with Settings::Acknowledgment");
+    }
+    let mut payload = vec![];
+    // Writing to a vector can’t fail.
+    settings.encode(&mut payload).unwrap();
+    let mut output = String::with_capacity((payload.len() * 4 + 2) / 3);
+    for chunk in payload.chunks(3) {
+        let buffer = chunk.iter().fold(0u32, |buffer, &b| buffer << 8 | b as u32)
+                     << (8 * (3 - chunk.len()));
+        for i in 0..chunk.len() + 1 {
+            output.push(ALPHABET[(buffer >> (18 - 6 * i) & 0x3f) as usize] as char);
+        }
+    }
+    output
+}
+
+/// Decode the value of the `HTTP2-Settings` header field.
+///
+/// This returns `None` if the value is not valid base64url or not a valid SETTINGS payload; in
+/// either case the server must not upgrade the connection.
+///
+/// > A server decodes and interprets these values as it would any other SETTINGS frame.
+pub fn decode_settings(value: &[u8]) -> Option<Settings> {
+    let mut payload = ByteTendril::new();
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for &c in value {
+        let sextet = match c {
+            b'A'...b'Z' => c - b'A',
+            b'a'...b'z' => c - b'a' + 26,
+            b'0'...b'9' => c - b'0' + 52,
+            b'-' => 62,
+            b'_' => 63,
+            _ => return None,
+        };
+        buffer = buffer << 6 | sextet as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            payload.push_slice(&[(buffer >> bits) as u8]);
+        }
+    }
+    // One character left over can’t make up an octet.
+    if bits == 6 {
+        return None;
+    }
+    let header = Header {
+        length: payload.len32(),
+        type_: <Settings as Frame>::TYPE,
+        flags: 0,
+        stream_identifier: StreamId(0),
+    };
+    match Payload::decode(header, payload) {
+        Ok(Payload::Settings(settings)) => Some(settings),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_settings, encode_settings};
+    use http2::frame::settings::Settings;
+
+    fn settings(max_concurrent_streams: Option<u32>, initial_window_size: Option<u32>)
+            -> Settings {
+        Settings::Parameters {
+            header_table_size: None,
+            enable_push: None,
+            max_concurrent_streams: max_concurrent_streams,
+            initial_window_size: initial_window_size,
+            max_frame_size: None,
+            max_header_list_size: None,
+            enable_connect_protocol: None,
+            no_rfc7540_priorities: None,
+            unknown: vec![],
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        for &(ref settings, encoded) in &[
+            (settings(None, None), ""),
+            (settings(Some(100), None), "AAMAAABk"),
+            (settings(Some(100), Some(65535)), "AAMAAABkAAQAAP__"),
+            (settings(Some(0xfbfbfbfb), Some(0x3ffffff)), "AAP7-_v7AAQD____"),
+        ] {
+            assert_eq!(encode_settings(settings.clone()), encoded);
+            assert_eq!(decode_settings(encoded.as_bytes()).as_ref(), Some(settings));
+        }
+    }
+
+    #[test]
+    fn decode() {
+        // Not base64url. (A SETTINGS payload is a multiple of three octets, so it is never padded
+        // and the omission of padding doesn’t come into it.)
+        assert_eq!(decode_settings(b"AAMAAABkAAQAAP//"), None);
+        assert_eq!(decode_settings(b"AAMAAABkAAQAAP_"), None);
+        assert_eq!(decode_settings(b"AAMAA=BkAAQAAP__"), None);
+        // Not a whole number of settings.
+        assert_eq!(decode_settings(b"AAMAAAB"), None);
+        // Not valid settings.
+        assert_eq!(decode_settings(b"AAIAAAAC"), None);
+    }
+}
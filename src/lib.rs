@@ -44,6 +44,9 @@ pub mod grammar;
 
 pub mod http2;
 
+#[cfg(test)]
+mod seed_corpus;
+
 /// I don’t care about non-atomic byte tendrils, so let’s just call it ByteTendril.
 pub type ByteTendril = tendril::Tendril<tendril::fmt::Bytes, tendril::Atomic>;
 
@@ -0,0 +1,146 @@
+//! A seed corpus for the fuzz targets in `fuzz/`, drawn from the test vectors here and in RFC 7541,
+//! so that fuzzing starts from inputs that are known to matter.
+//!
+//! It is written to `fuzz/corpus` by a test which is ignored unless asked for:
+//!
+//! ```text
+//! cargo test seed_corpus -- --ignored
+//! ```
+//!
+//! Files are named after a hash of their contents, so writing the corpus again is harmless.
+
+use std::collections::hash_map::DefaultHasher;
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::io::Write;
+use std::path::Path;
+
+use http2::frame::Header;
+use http2::stream::StreamId;
+
+/// A whole frame, header and all.
+fn frame(type_: u8, flags: u8, stream: u32, payload: &[u8]) -> Vec<u8> {
+    let header = Header {
+        length: payload.len() as u32,
+        type_: type_,
+        flags: flags,
+        stream_identifier: StreamId(stream),
+    };
+    [&header.encode()[..], payload].concat()
+}
+
+fn frames() -> Vec<Vec<u8>> {
+    vec![
+        // DATA: plain, padded, and with padding longer than the payload.
+        frame(0x0, 0x1, 1, b"hello"),
+        frame(0x0, 0x8, 1, &[3, b'h', b'i', 0, 0, 0]),
+        frame(0x0, 0x8, 1, &[1]),
+        // HEADERS: plain, padded with priority, and too short for the priority.
+        frame(0x1, 0x5, 1, &[0x82]),
+        frame(0x1, 0x2c, 3, &[2, 0x80, 0, 0, 1, 15, 0x82, 0, 0]),
+        frame(0x1, 0x28, 1, &[0, 0, 0, 0, 1]),
+        // PRIORITY, RST_STREAM.
+        frame(0x2, 0x0, 3, &[0x80, 0, 0, 1, 15]),
+        frame(0x3, 0x0, 1, &[0, 0, 0, 8]),
+        // SETTINGS: parameters, including an unknown one, and an acknowledgement.
+        frame(0x4, 0x0, 0, &[0, 1, 0, 0, 0x10, 0, 0, 3, 0, 0, 0, 100, 0xff, 0xff, 0, 0, 0, 0]),
+        frame(0x4, 0x1, 0, &[]),
+        // PUSH_PROMISE: plain, and with padding that leaves no room for the promised stream.
+        frame(0x5, 0x4, 1, &[0, 0, 0, 2, 0x82]),
+        frame(0x5, 0x8, 1, &[1, 0, 0, 0, 2]),
+        // PING, and its acknowledgement.
+        frame(0x6, 0x0, 0, &[0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef]),
+        frame(0x6, 0x1, 0, &[0; 8]),
+        // GOAWAY, with debug data.
+        frame(0x7, 0x0, 0, &[0, 0, 0, 1, 0, 0, 0, 2, b'b', b'y', b'e']),
+        // WINDOW_UPDATE, CONTINUATION.
+        frame(0x8, 0x0, 1, &[0, 0, 0x40, 0]),
+        frame(0x9, 0x4, 1, &[0x84]),
+        // ALTSVC, on the connection and on a stream.
+        frame(0xa, 0x0, 0, &[0, 5, b'h', b't', b't', b'p', b':', b'c', b'l', b'e', b'a', b'r']),
+        frame(0xa, 0x0, 1, &[0, 0, b'h', b'3', b'=', b'"', b':', b'4', b'4', b'3', b'"']),
+        // ORIGIN.
+        frame(0xc, 0x0, 0, &[0, 8, b'h', b't', b't', b'p', b':', b'/', b'/', b'a']),
+        // PRIORITY_UPDATE.
+        frame(0x10, 0x0, 0, &[0, 0, 0, 1, b'u', b'=', b'5', b',', b' ', b'i']),
+        // A frame of an unknown type.
+        frame(0xfe, 0xff, 1, b"unknown"),
+    ]
+}
+
+/// The header blocks of RFC 7541, Appendix C, each of which is decoded with fresh tables.
+const HEADER_BLOCKS: &'static [&'static [u8]] = &[
+    b"\x40\x0acustom-key\x0dcustom-header",
+    b"\x04\x0c/sample/path",
+    b"\x10\x08password\x06secret",
+    b"\x82",
+    b"\x82\x86\x84\x41\x0fwww.example.com",
+    b"\x82\x86\x84\x41\x8c\xf1\xe3\xc2\xe5\xf2\x3a\x6b\xa0\xab\x90\xf4\xff",
+    b"\x82\x86\x84\xbe\x58\x08no-cache",
+    b"\x82\x86\x84\xbe\x58\x86\xa8\xeb\x10\x64\x9c\xbf",
+    b"\x82\x87\x85\xbf\x40\x0acustom-key\x0ccustom-value",
+    b"\x82\x87\x85\xbf\x40\x88\x25\xa8\x49\xe9\x5b\xa9\x7d\x7f\x89\x25\xa8\x49\xe9\x5b\xb8\xe8\xb4\
+      \xbf",
+    b"\x48\x03302\x58\x07private\x61\x1dMon, 21 Oct 2013 20:13:21 GMT\x6e\x17\
+      https://www.example.com",
+    b"\x48\x03307\xc1\xc0\xbf",
+    // A dynamic table size update, then a literal added to the table.
+    b"\x3f\xe1\x1f\x40\x01a\x01b",
+];
+
+/// Integers with the prefix sizes of RFC 7541, C.1, and one too large for a `u32`.
+const HPACK_INTEGERS: &'static [&'static [u8]] = &[
+    &[0b11101010],
+    &[0b11111111, 0b10011010, 0b00001010],
+    &[0b00101010],
+    &[0b00011111, 0xff, 0xff, 0xff, 0xff, 0x0f],
+];
+
+/// String literals: plain, Huffman coded, empty, and Huffman coded with bad padding.
+const HPACK_STRINGS: &'static [&'static [u8]] = &[
+    b"\x0fwww.example.com",
+    b"\x8c\xf1\xe3\xc2\xe5\xf2\x3a\x6b\xa0\xab\x90\xf4\xff",
+    b"\x96\xd0\x7a\xbe\x94\x10\x54\xd4\x44\xa8\x20\x05\x95\x04\x0b\x81\x66\xe0\x84\xa6\x2d\x1b\
+      \xff",
+    b"\x00",
+    b"\x80",
+    b"\x82\x07\xff",
+];
+
+/// Field values, good and bad, for the types that implement `ToHeader`.
+const HEADER_VALUES: &'static [&'static [u8]] = &[
+    b"0",
+    b"123456789",
+    b"-1",
+    b"1234567890123467901245790",
+    b"",
+    b"u=5",
+    b"u=0, i",
+    b"i=?0, u=1;foo",
+    b"u=8, i=1, x=(1 2)",
+    b"u=-1, i=\"yes\"",
+    b"u=1,",
+];
+
+fn save(dir: &Path, target: &str, inputs: &[&[u8]]) {
+    let dir = dir.join(target);
+    fs::create_dir_all(&dir).unwrap();
+    for input in inputs {
+        let mut hasher = DefaultHasher::new();
+        input.hash(&mut hasher);
+        let mut file = File::create(dir.join(format!("{:016x}", hasher.finish()))).unwrap();
+        file.write_all(input).unwrap();
+    }
+}
+
+#[test]
+#[ignore]
+fn seed_corpus() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("fuzz").join("corpus");
+    let frames = frames();
+    save(&dir, "frame", &frames.iter().map(|f| &f[..]).collect::<Vec<_>>());
+    save(&dir, "header_block", HEADER_BLOCKS);
+    save(&dir, "hpack_integer", HPACK_INTEGERS);
+    save(&dir, "hpack_string", HPACK_STRINGS);
+    save(&dir, "header_value", HEADER_VALUES);
+}
@@ -0,0 +1,1097 @@
+//! A conformance suite for the HTTP/2 stack, after the manner of [h2spec].
+//!
+//! Each case plays a client writing raw frames, octet by octet as h2spec does, to a server made of
+//! a `Connection` with a `FrameReader` and `FrameWriter` over in-memory pipes. It then checks the
+//! error that the server found: its code, whether it was an error on the stream or on the whole
+//! connection, and that the server told the client so with RST_STREAM or GOAWAY.
+//!
+//! The cases are grouped and numbered by the sections of RFC 7540 and RFC 7541 that they cover,
+//! as in h2spec.
+//!
+//! [h2spec]: https://github.com/summerwind/h2spec
+
+extern crate teepee;
+
+use std::cell::RefCell;
+use std::cmp;
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::rc::Rc;
+
+use teepee::ByteTendril;
+use teepee::http2::{Connection, Error, Event, Role};
+use teepee::http2::frame::{CONNECTION_PRELUDE, ErrorCode, Header, Payload};
+use teepee::http2::frame::codec::{FrameReader, FrameWriter, ReadError};
+use teepee::http2::frame::goaway::GoAway;
+use teepee::http2::frame::hpack::Entry;
+use teepee::http2::frame::ping::Ping;
+use teepee::http2::frame::rst_stream::RstStream;
+use teepee::http2::frame::settings::Settings;
+use teepee::http2::flow_control::UpdatePolicy;
+use teepee::http2::stream::StreamId;
+
+const DATA: u8 = 0x0;
+const HEADERS: u8 = 0x1;
+const PRIORITY: u8 = 0x2;
+const RST_STREAM: u8 = 0x3;
+const SETTINGS: u8 = 0x4;
+const PING: u8 = 0x6;
+const GOAWAY: u8 = 0x7;
+const WINDOW_UPDATE: u8 = 0x8;
+const CONTINUATION: u8 = 0x9;
+
+const END_STREAM: u8 = 0x1;
+const ACK: u8 = 0x1;
+const END_HEADERS: u8 = 0x4;
+const PADDED: u8 = 0x8;
+const PRIORITY_FLAG: u8 = 0x20;
+
+const SETTINGS_HEADER_TABLE_SIZE: u16 = 0x1;
+const SETTINGS_ENABLE_PUSH: u16 = 0x2;
+const SETTINGS_INITIAL_WINDOW_SIZE: u16 = 0x4;
+const SETTINGS_MAX_FRAME_SIZE: u16 = 0x5;
+
+/// A header block for `GET / HTTP/2` on localhost: `:method: GET`, `:scheme: http` and
+/// `:path: /` from the static table, then `:authority` as a literal without indexing.
+const REQUEST: &'static [u8] = b"\x82\x86\x84\x01\x09localhost";
+
+/// A 32-bit integer in network byte order, as in a stream dependency, an error code, a setting’s
+/// value or a window size increment.
+fn u32_octets(n: u32) -> [u8; 4] {
+    [(n >> 24) as u8, (n >> 16) as u8, (n >> 8) as u8, n as u8]
+}
+
+/// One direction of an in-memory connection. Reading when there is nothing to read is the end
+/// of input, so whole frames must be written before they are read.
+#[derive(Clone, Default)]
+struct Pipe(Rc<RefCell<VecDeque<u8>>>);
+
+impl Read for Pipe {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut queue = self.0.borrow_mut();
+        let len = cmp::min(buf.len(), queue.len());
+        for (byte, octet) in buf.iter_mut().zip(queue.drain(..len)) {
+            *byte = octet;
+        }
+        Ok(len)
+    }
+}
+
+impl Write for Pipe {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend(buf.iter().cloned());
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The settings of a server with nothing out of the ordinary.
+fn settings() -> Settings {
+    Settings::Parameters {
+        header_table_size: None,
+        enable_push: None,
+        max_concurrent_streams: None,
+        initial_window_size: None,
+        max_frame_size: None,
+        max_header_list_size: None,
+        enable_connect_protocol: None,
+        no_rfc7540_priorities: None,
+        unknown: vec![],
+    }
+}
+
+/// A server, and the client end of its pipes.
+struct Session {
+    connection: Connection,
+    /// What the server reads from the client.
+    reader: FrameReader<Pipe>,
+    /// What the server writes to the client.
+    writer: FrameWriter<Pipe>,
+    read_preface: bool,
+    /// Whether the server answers each request once it has been sent in full.
+    respond: bool,
+    /// The errors that the server found, in order.
+    errors: Vec<Error>,
+
+    to_server: Pipe,
+    from_server: FrameReader<Pipe>,
+    /// The highest odd-numbered stream that the client has sent a frame on.
+    last_client_stream_id: u32,
+}
+
+impl Session {
+    /// Connect to a server with ordinary settings.
+    fn new() -> Session {
+        Session::with_settings(settings())
+    }
+
+    /// Connect to a server with the given settings: send the preface, then acknowledge the
+    /// server’s SETTINGS frame, leaving nothing in either direction.
+    fn with_settings(settings: Settings) -> Session {
+        let mut session = Session::unconnected(settings);
+        session.send(SETTINGS, 0, 0, b"");
+        let frames = session.received();
+        assert!(frames.iter().any(|&(header, _)| header.type_ == SETTINGS && header.flags == 0));
+        assert!(frames.iter().any(|&(header, _)| header.type_ == SETTINGS && header.flags == ACK));
+        session.send(SETTINGS, ACK, 0, b"");
+        assert_eq!(session.received(), vec![]);
+        assert_eq!(session.errors, vec![]);
+        session
+    }
+
+    /// A server which has been sent the client connection preface, but not the SETTINGS frame
+    /// that must follow it.
+    fn unconnected(settings: Settings) -> Session {
+        let (to_server, to_client) = (Pipe::default(), Pipe::default());
+        let mut session = Session {
+            connection: Connection::new(Role::Server, settings),
+            reader: FrameReader::new(to_server.clone()),
+            writer: FrameWriter::new(to_client.clone()),
+            read_preface: false,
+            respond: true,
+            errors: vec![],
+            to_server: to_server,
+            from_server: FrameReader::new(to_client),
+            last_client_stream_id: 0,
+        };
+        session.to_server.write_all(CONNECTION_PRELUDE).unwrap();
+        session
+    }
+
+    /// Send a frame to the server as it is, and let the server deal with it.
+    fn send(&mut self, type_: u8, flags: u8, stream_id: u32, payload: &[u8]) {
+        self.send_header(Header {
+            length: payload.len() as u32,
+            type_: type_,
+            flags: flags,
+            stream_identifier: StreamId(stream_id),
+        }, payload);
+    }
+
+    /// Send a frame whose header need not agree with its payload.
+    fn send_header(&mut self, header: Header<u8>, payload: &[u8]) {
+        let StreamId(stream_id) = header.stream_identifier;
+        if stream_id % 2 == 1 && stream_id > self.last_client_stream_id {
+            self.last_client_stream_id = stream_id;
+        }
+        self.to_server.write_all(&header.encode()).unwrap();
+        self.to_server.write_all(payload).unwrap();
+        self.pump();
+    }
+
+    /// Send a complete request with HEADERS, with END_STREAM if there is to be no body.
+    fn send_request(&mut self, stream_id: u32, end_stream: bool) {
+        let flags = END_HEADERS | if end_stream { END_STREAM } else { 0 };
+        self.send(HEADERS, flags, stream_id, REQUEST);
+    }
+
+    /// Send a SETTINGS frame with the given parameters.
+    fn send_settings(&mut self, parameters: &[(u16, u32)]) {
+        let mut payload = vec![];
+        for &(identifier, value) in parameters {
+            payload.extend_from_slice(&[(identifier >> 8) as u8, identifier as u8]);
+            payload.extend_from_slice(&u32_octets(value));
+        }
+        self.send(SETTINGS, 0, 0, &payload);
+    }
+
+    /// Send a WINDOW_UPDATE frame.
+    fn send_window_update(&mut self, stream_id: u32, increment: u32) {
+        self.send(WINDOW_UPDATE, 0, stream_id, &u32_octets(increment));
+    }
+
+    /// Have the server read and handle everything the client has sent, and write whatever it
+    /// has to say in return.
+    fn pump(&mut self) {
+        if !self.read_preface {
+            self.reader.read_preface().unwrap();
+            self.read_preface = true;
+        }
+        while !self.connection_failed() {
+            let error = match self.reader.read_frame() {
+                Ok(Some((header, payload))) => {
+                    self.connection.receive_frame(header.stream_identifier, payload).err()
+                },
+                Ok(None) => break,
+                Err(ReadError::Frame(header, error_code)) => {
+                    Some(self.connection.receive_invalid_frame(header, error_code))
+                },
+                Err(error) => panic!("reading from the client failed: {}", error),
+            };
+            self.errors.extend(error);
+            self.handle_events();
+            while let Some((stream_id, payload)) = self.connection.next_frame() {
+                self.writer.write_payload(stream_id, payload).unwrap();
+            }
+        }
+    }
+
+    fn handle_events(&mut self) {
+        while let Some(event) = self.connection.next_event() {
+            match event {
+                Event::Headers { stream_id, end_stream: true, .. } |
+                Event::Data { stream_id, end_stream: true, .. } if self.respond => {
+                    let status = Entry {
+                        name: ByteTendril::from_slice(b":status"),
+                        value: ByteTendril::from_slice(b"200"),
+                    };
+                    self.connection.send_headers(stream_id, vec![status], true).unwrap();
+                },
+                Event::SettingsChanged(values) => {
+                    self.writer.set_max_frame_size(values.max_frame_size);
+                },
+                Event::SettingsAcknowledged(values) => {
+                    self.reader.set_max_frame_size(values.max_frame_size);
+                },
+                _ => (),
+            }
+        }
+    }
+
+    fn connection_failed(&self) -> bool {
+        match self.errors.last() {
+            Some(&Error::Connection(_)) => true,
+            _ => false,
+        }
+    }
+
+    /// Take the frames that the server has written.
+    fn received(&mut self) -> Vec<(Header<u8>, Payload)> {
+        let mut frames = vec![];
+        while let Some(frame) = self.from_server.read_frame().unwrap() {
+            frames.push(frame);
+        }
+        frames
+    }
+
+    /// Check that nothing has gone wrong, returning the frames that the server has written.
+    fn expect_no_error(&mut self) -> Vec<(Header<u8>, Payload)> {
+        assert_eq!(self.errors, vec![]);
+        let frames = self.received();
+        for &(_, ref payload) in &frames {
+            match *payload {
+                Payload::RstStream(_) | Payload::GoAway(_) => panic!("unexpected {:?}", payload),
+                _ => (),
+            }
+        }
+        frames
+    }
+
+    /// Check that the server found a connection error of the given type, and sent GOAWAY.
+    fn expect_connection_error(&mut self, error_code: ErrorCode) {
+        assert_eq!(self.errors, vec![Error::Connection(error_code)]);
+        let goaways = self.received().into_iter().filter_map(|(header, payload)| match payload {
+            Payload::GoAway(GoAway { error_code, .. }) => {
+                assert_eq!(header.stream_identifier, StreamId(0));
+                Some(error_code)
+            },
+            _ => None,
+        }).collect::<Vec<_>>();
+        assert_eq!(goaways, vec![error_code]);
+    }
+
+    /// Check that the server found a stream error of the given type, sent RST_STREAM, and
+    /// carried on.
+    fn expect_stream_error(&mut self, stream_id: u32, error_code: ErrorCode) {
+        assert_eq!(self.errors, vec![Error::Stream(StreamId(stream_id), error_code)]);
+        let resets = self.received().into_iter().filter_map(|(header, payload)| match payload {
+            Payload::RstStream(RstStream { error_code }) => {
+                Some((header.stream_identifier, error_code))
+            },
+            Payload::GoAway(_) => panic!("unexpected {:?}", payload),
+            _ => None,
+        }).collect::<Vec<_>>();
+        assert_eq!(resets, vec![(StreamId(stream_id), error_code)]);
+
+        // The connection is still good for another request.
+        self.errors.clear();
+        self.respond = true;
+        let stream_id = self.last_client_stream_id + 2;
+        self.send_request(stream_id, true);
+        assert!(self.expect_no_error().iter().any(|&(header, _)| {
+            header.type_ == HEADERS && header.stream_identifier == StreamId(stream_id)
+        }));
+    }
+}
+
+/// 3.5. HTTP/2 Connection Preface
+mod connection_preface {
+    use super::*;
+
+    /// Sends a client connection preface followed by something other than SETTINGS.
+    #[test]
+    fn not_settings() {
+        let mut session = Session::unconnected(settings());
+        session.send(PING, 0, 0, b"abcdefgh");
+        session.expect_connection_error(ErrorCode::PROTOCOL_ERROR);
+    }
+}
+
+/// 4.2. Frame Size
+mod frame_size {
+    use super::*;
+
+    /// Sends a DATA frame with 2^14 octets in length.
+    #[test]
+    fn data_max_frame_size() {
+        let mut session = Session::new();
+        session.send_request(1, false);
+        session.send(DATA, END_STREAM, 1, &[0; 16384]);
+        assert!(session.expect_no_error().iter().any(|&(header, _)| header.type_ == HEADERS));
+    }
+
+    /// Sends a large size DATA frame that exceeds the SETTINGS_MAX_FRAME_SIZE.
+    #[test]
+    fn data_too_large() {
+        let mut session = Session::new();
+        session.send_request(1, false);
+        session.send(DATA, END_STREAM, 1, &[0; 16385]);
+        session.expect_connection_error(ErrorCode::FRAME_SIZE_ERROR);
+    }
+
+    /// Sends a large size HEADERS frame that exceeds the SETTINGS_MAX_FRAME_SIZE.
+    #[test]
+    fn headers_too_large() {
+        let mut session = Session::new();
+        let mut block = REQUEST.to_vec();
+        // Many of `x-h2spec: a…a`, a literal header field without indexing.
+        while block.len() <= 16384 {
+            block.extend_from_slice(b"\x00\x08x-h2spec\x7f\x00");
+            block.extend_from_slice(&[b'a'; 127]);
+        }
+        session.send(HEADERS, END_STREAM | END_HEADERS, 1, &block);
+        session.expect_connection_error(ErrorCode::FRAME_SIZE_ERROR);
+    }
+
+    /// A larger SETTINGS_MAX_FRAME_SIZE takes effect once acknowledged.
+    #[test]
+    fn larger_max_frame_size() {
+        let mut session = Session::with_settings(Settings::Parameters {
+            header_table_size: None,
+            enable_push: None,
+            max_concurrent_streams: None,
+            initial_window_size: None,
+            max_frame_size: Some(20000),
+            max_header_list_size: None,
+            enable_connect_protocol: None,
+            no_rfc7540_priorities: None,
+            unknown: vec![],
+        });
+        session.send_request(1, false);
+        session.send(DATA, END_STREAM, 1, &[0; 20000]);
+        let _ = session.expect_no_error();
+    }
+}
+
+/// 4.3. Header Compression and Decompression
+mod header_compression {
+    use super::*;
+
+    /// Sends invalid header block fragment.
+    #[test]
+    fn invalid_header_block() {
+        let mut session = Session::new();
+        // A literal header field with indexing whose name is an index with no entry.
+        session.send(HEADERS, END_STREAM | END_HEADERS, 1, b"\x82\x86\x84\x7f\x7f\x01a");
+        session.expect_connection_error(ErrorCode::COMPRESSION_ERROR);
+    }
+}
+
+/// 5.1. Stream States
+mod stream_states {
+    use super::*;
+
+    /// idle: Sends a DATA frame.
+    #[test]
+    fn idle_data() {
+        let mut session = Session::new();
+        session.send(DATA, END_STREAM, 1, b"test");
+        session.expect_connection_error(ErrorCode::PROTOCOL_ERROR);
+    }
+
+    /// idle: Sends a RST_STREAM frame.
+    #[test]
+    fn idle_rst_stream() {
+        let mut session = Session::new();
+        session.send(RST_STREAM, 0, 1, &u32_octets(0x8));
+        session.expect_connection_error(ErrorCode::PROTOCOL_ERROR);
+    }
+
+    /// idle: Sends a WINDOW_UPDATE frame.
+    #[test]
+    fn idle_window_update() {
+        let mut session = Session::new();
+        session.send_window_update(1, 100);
+        session.expect_connection_error(ErrorCode::PROTOCOL_ERROR);
+    }
+
+    /// idle: Sends a CONTINUATION frame.
+    #[test]
+    fn idle_continuation() {
+        let mut session = Session::new();
+        session.send(CONTINUATION, END_HEADERS, 1, REQUEST);
+        session.expect_connection_error(ErrorCode::PROTOCOL_ERROR);
+    }
+
+    /// idle: Sends a PRIORITY frame, which is allowed.
+    #[test]
+    fn idle_priority() {
+        let mut session = Session::new();
+        let mut payload = u32_octets(0).to_vec();
+        payload.push(15);
+        session.send(PRIORITY, 0, 3, &payload);
+        assert_eq!(session.expect_no_error(), vec![]);
+    }
+
+    /// half closed (remote): Sends a DATA frame.
+    #[test]
+    fn half_closed_remote_data() {
+        let mut session = Session::new();
+        session.respond = false;
+        session.send_request(1, true);
+        session.send(DATA, END_STREAM, 1, b"test");
+        session.expect_stream_error(1, ErrorCode::STREAM_CLOSED);
+    }
+
+    /// half closed (remote): Sends a HEADERS frame.
+    #[test]
+    fn half_closed_remote_headers() {
+        let mut session = Session::new();
+        session.respond = false;
+        session.send_request(1, true);
+        session.send_request(1, true);
+        session.expect_stream_error(1, ErrorCode::STREAM_CLOSED);
+    }
+
+    /// half closed (remote): Sends a CONTINUATION frame.
+    #[test]
+    fn half_closed_remote_continuation() {
+        let mut session = Session::new();
+        session.respond = false;
+        session.send_request(1, true);
+        session.send(CONTINUATION, END_HEADERS, 1, REQUEST);
+        session.expect_connection_error(ErrorCode::PROTOCOL_ERROR);
+    }
+
+    /// closed: Sends a DATA frame after sending RST_STREAM frame.
+    #[test]
+    fn closed_data_after_reset() {
+        let mut session = Session::new();
+        session.send_request(1, false);
+        session.send(RST_STREAM, 0, 1, &u32_octets(0x8));
+        let _ = session.expect_no_error();
+        session.send(DATA, END_STREAM, 1, b"test");
+        session.expect_stream_error(1, ErrorCode::STREAM_CLOSED);
+    }
+
+    /// closed: Sends a HEADERS frame after sending RST_STREAM frame.
+    #[test]
+    fn closed_headers_after_reset() {
+        let mut session = Session::new();
+        session.send_request(1, false);
+        session.send(RST_STREAM, 0, 1, &u32_octets(0x8));
+        let _ = session.expect_no_error();
+        session.send_request(1, true);
+        session.expect_stream_error(1, ErrorCode::STREAM_CLOSED);
+    }
+
+    /// closed: Sends a DATA frame once the stream has ended in both directions.
+    #[test]
+    fn closed_data() {
+        let mut session = Session::new();
+        session.send_request(1, true);
+        let _ = session.expect_no_error();
+        session.send(DATA, END_STREAM, 1, b"test");
+        session.expect_connection_error(ErrorCode::STREAM_CLOSED);
+    }
+
+    /// closed: Sends a HEADERS frame once the stream has ended in both directions.
+    #[test]
+    fn closed_headers() {
+        let mut session = Session::new();
+        session.send_request(1, true);
+        let _ = session.expect_no_error();
+        session.send_request(1, true);
+        session.expect_connection_error(ErrorCode::STREAM_CLOSED);
+    }
+
+    /// closed: Sends a PRIORITY frame, which is allowed.
+    #[test]
+    fn closed_priority() {
+        let mut session = Session::new();
+        session.send_request(1, true);
+        let _ = session.expect_no_error();
+        let mut payload = u32_octets(0).to_vec();
+        payload.push(15);
+        session.send(PRIORITY, 0, 1, &payload);
+        assert_eq!(session.expect_no_error(), vec![]);
+    }
+
+    /// 5.1.1. Stream Identifiers: Sends even-numbered stream identifier.
+    #[test]
+    fn even_stream_identifier() {
+        let mut session = Session::new();
+        session.send_request(2, true);
+        session.expect_connection_error(ErrorCode::PROTOCOL_ERROR);
+    }
+
+    /// 5.1.1. Stream Identifiers: Sends stream identifier that is numerically smaller than
+    /// previous.
+    #[test]
+    fn smaller_stream_identifier() {
+        let mut session = Session::new();
+        session.send_request(5, true);
+        let _ = session.expect_no_error();
+        session.send_request(3, true);
+        session.expect_connection_error(ErrorCode::PROTOCOL_ERROR);
+    }
+}
+
+/// 5.4. Error Handling
+mod error_handling {
+    use super::*;
+
+    /// After a connection error, nothing more is read.
+    #[test]
+    fn after_connection_error() {
+        let mut session = Session::new();
+        session.send(DATA, 0, 0, b"test");
+        session.send_request(1, true);
+        session.expect_connection_error(ErrorCode::PROTOCOL_ERROR);
+    }
+}
+
+/// 6.1. DATA
+mod data {
+    use super::*;
+
+    /// Sends a DATA frame with 0x0 stream identifier.
+    #[test]
+    fn stream_zero() {
+        let mut session = Session::new();
+        session.send(DATA, END_STREAM, 0, b"test");
+        session.expect_connection_error(ErrorCode::PROTOCOL_ERROR);
+    }
+
+    /// Sends a DATA frame with invalid pad length.
+    #[test]
+    fn invalid_pad_length() {
+        let mut session = Session::new();
+        session.send_request(1, false);
+        session.send(DATA, PADDED | END_STREAM, 1, b"\x06test\0");
+        session.expect_connection_error(ErrorCode::PROTOCOL_ERROR);
+    }
+
+    /// Sends a padded DATA frame, padding and all counting against flow control.
+    #[test]
+    fn padded() {
+        let mut session = Session::new();
+        session.send_request(1, false);
+        session.send(DATA, PADDED | END_STREAM, 1, b"\x04test\0\0\0\0");
+        assert!(session.expect_no_error().iter().any(|&(header, _)| header.type_ == HEADERS));
+    }
+}
+
+/// 6.2. HEADERS
+mod headers {
+    use super::*;
+
+    /// Sends a HEADERS frame without the END_HEADERS flag, and a PRIORITY frame.
+    #[test]
+    fn interrupted_by_priority() {
+        let mut session = Session::new();
+        session.send(HEADERS, END_STREAM, 1, REQUEST);
+        let mut payload = u32_octets(0).to_vec();
+        payload.push(15);
+        session.send(PRIORITY, 0, 1, &payload);
+        session.expect_connection_error(ErrorCode::PROTOCOL_ERROR);
+    }
+
+    /// Sends a HEADERS frame to another stream while sending a HEADERS frame.
+    #[test]
+    fn interrupted_by_headers() {
+        let mut session = Session::new();
+        session.send(HEADERS, END_STREAM, 1, REQUEST);
+        session.send_request(3, true);
+        session.expect_connection_error(ErrorCode::PROTOCOL_ERROR);
+    }
+
+    /// Sends a HEADERS frame with 0x0 stream identifier.
+    #[test]
+    fn stream_zero() {
+        let mut session = Session::new();
+        session.send_request(0, true);
+        session.expect_connection_error(ErrorCode::PROTOCOL_ERROR);
+    }
+
+    /// Sends a HEADERS frame with invalid pad length.
+    #[test]
+    fn invalid_pad_length() {
+        let mut session = Session::new();
+        let mut payload = vec![REQUEST.len() as u8 + 1];
+        payload.extend_from_slice(REQUEST);
+        session.send(HEADERS, PADDED | END_STREAM | END_HEADERS, 1, &payload);
+        session.expect_connection_error(ErrorCode::PROTOCOL_ERROR);
+    }
+
+    /// Sends a HEADERS frame that makes the stream depend on itself.
+    #[test]
+    fn self_dependency() {
+        let mut session = Session::new();
+        let mut payload = u32_octets(1).to_vec();
+        payload.push(15);
+        payload.extend_from_slice(REQUEST);
+        session.send(HEADERS, PRIORITY_FLAG | END_STREAM | END_HEADERS, 1, &payload);
+        session.expect_stream_error(1, ErrorCode::PROTOCOL_ERROR);
+    }
+}
+
+/// 6.3. PRIORITY
+mod priority {
+    use super::*;
+
+    /// Sends a PRIORITY frame with 0x0 stream identifier.
+    #[test]
+    fn stream_zero() {
+        let mut session = Session::new();
+        session.send(PRIORITY, 0, 0, b"\0\0\0\0\x0f");
+        session.expect_connection_error(ErrorCode::PROTOCOL_ERROR);
+    }
+
+    /// Sends a PRIORITY frame with a length other than 5 octets.
+    #[test]
+    fn invalid_length() {
+        let mut session = Session::new();
+        session.send_request(1, false);
+        session.send(PRIORITY, 0, 1, b"\0\0\0\0");
+        session.expect_stream_error(1, ErrorCode::FRAME_SIZE_ERROR);
+    }
+
+    /// Sends a PRIORITY frame that makes the stream depend on itself.
+    #[test]
+    fn self_dependency() {
+        let mut session = Session::new();
+        session.send_request(1, false);
+        session.send(PRIORITY, 0, 1, b"\0\0\0\x01\x0f");
+        session.expect_stream_error(1, ErrorCode::PROTOCOL_ERROR);
+    }
+}
+
+/// 6.4. RST_STREAM
+mod rst_stream {
+    use super::*;
+
+    /// Sends a RST_STREAM frame with 0x0 stream identifier.
+    #[test]
+    fn stream_zero() {
+        let mut session = Session::new();
+        session.send(RST_STREAM, 0, 0, &u32_octets(0x8));
+        session.expect_connection_error(ErrorCode::PROTOCOL_ERROR);
+    }
+
+    /// Sends a RST_STREAM frame with a length other than 4 octets.
+    #[test]
+    fn invalid_length() {
+        let mut session = Session::new();
+        session.send_request(1, false);
+        session.send(RST_STREAM, 0, 1, b"\0\0\0");
+        session.expect_connection_error(ErrorCode::FRAME_SIZE_ERROR);
+    }
+}
+
+/// 6.5. SETTINGS
+mod settings {
+    use super::*;
+
+    /// Sends a SETTINGS frame, which must be acknowledged.
+    #[test]
+    fn acknowledged() {
+        let mut session = Session::new();
+        session.send_settings(&[(SETTINGS_HEADER_TABLE_SIZE, 0), (SETTINGS_ENABLE_PUSH, 0)]);
+        let frames = session.expect_no_error();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].1, Payload::Settings(Settings::Acknowledgment));
+        assert_eq!(session.connection.remote_settings().header_table_size, 0);
+        assert!(!session.connection.remote_settings().enable_push);
+    }
+
+    /// Sends a SETTINGS frame with ACK flag and payload.
+    #[test]
+    fn ack_with_payload() {
+        let mut session = Session::new();
+        session.send(SETTINGS, ACK, 0, b"\0\x02\0\0\0\0");
+        session.expect_connection_error(ErrorCode::FRAME_SIZE_ERROR);
+    }
+
+    /// Sends a SETTINGS frame with a stream identifier other than 0x0.
+    #[test]
+    fn stream_nonzero() {
+        let mut session = Session::new();
+        session.send(SETTINGS, 0, 1, b"\0\x02\0\0\0\0");
+        session.expect_connection_error(ErrorCode::PROTOCOL_ERROR);
+    }
+
+    /// Sends a SETTINGS frame with a length other than a multiple of 6 octets.
+    #[test]
+    fn invalid_length() {
+        let mut session = Session::new();
+        session.send(SETTINGS, 0, 0, b"\0\x02\0\0\0");
+        session.expect_connection_error(ErrorCode::FRAME_SIZE_ERROR);
+    }
+
+    /// SETTINGS_ENABLE_PUSH (0x2): Sends the value other than 0 or 1.
+    #[test]
+    fn invalid_enable_push() {
+        let mut session = Session::new();
+        session.send_settings(&[(SETTINGS_ENABLE_PUSH, 2)]);
+        session.expect_connection_error(ErrorCode::PROTOCOL_ERROR);
+    }
+
+    /// SETTINGS_INITIAL_WINDOW_SIZE (0x4): Sends the value above the maximum flow control window
+    /// size.
+    #[test]
+    fn invalid_initial_window_size() {
+        let mut session = Session::new();
+        session.send_settings(&[(SETTINGS_INITIAL_WINDOW_SIZE, 1 << 31)]);
+        session.expect_connection_error(ErrorCode::FLOW_CONTROL_ERROR);
+    }
+
+    /// SETTINGS_MAX_FRAME_SIZE (0x5): Sends the value below the initial value.
+    #[test]
+    fn max_frame_size_too_small() {
+        let mut session = Session::new();
+        session.send_settings(&[(SETTINGS_MAX_FRAME_SIZE, 16383)]);
+        session.expect_connection_error(ErrorCode::PROTOCOL_ERROR);
+    }
+
+    /// SETTINGS_MAX_FRAME_SIZE (0x5): Sends the value above the maximum allowed frame size.
+    #[test]
+    fn max_frame_size_too_large() {
+        let mut session = Session::new();
+        session.send_settings(&[(SETTINGS_MAX_FRAME_SIZE, 1 << 24)]);
+        session.expect_connection_error(ErrorCode::PROTOCOL_ERROR);
+    }
+
+    /// Sends a SETTINGS frame with unknown identifier, which must be ignored but acknowledged.
+    #[test]
+    fn unknown_identifier() {
+        let mut session = Session::new();
+        session.send_settings(&[(0xff, 1)]);
+        let frames = session.expect_no_error();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].1, Payload::Settings(Settings::Acknowledgment));
+        assert_eq!(session.connection.remote_unknown_setting(0xff), Some(1));
+    }
+}
+
+/// 6.7. PING
+mod ping {
+    use super::*;
+
+    /// Sends a PING frame, which must be answered with the same payload.
+    #[test]
+    fn answered() {
+        let mut session = Session::new();
+        session.send(PING, 0, 0, b"h2spec\0\0");
+        let frames = session.expect_no_error();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].0.flags, ACK);
+        assert_eq!(frames[0].1, Payload::Ping(Ping {
+            is_response: true,
+            data: *b"h2spec\0\0",
+        }));
+    }
+
+    /// Sends a PING frame with ACK, which must not be answered.
+    #[test]
+    fn ack_not_answered() {
+        let mut session = Session::new();
+        session.send(PING, ACK, 0, b"h2spec\0\0");
+        assert_eq!(session.expect_no_error(), vec![]);
+    }
+
+    /// Sends a PING frame with a stream identifier field value other than 0x0.
+    #[test]
+    fn stream_nonzero() {
+        let mut session = Session::new();
+        session.send(PING, 0, 1, b"h2spec\0\0");
+        session.expect_connection_error(ErrorCode::PROTOCOL_ERROR);
+    }
+
+    /// Sends a PING frame with a length field value other than 8.
+    #[test]
+    fn invalid_length() {
+        let mut session = Session::new();
+        session.send(PING, 0, 0, b"h2spec\0");
+        session.expect_connection_error(ErrorCode::FRAME_SIZE_ERROR);
+    }
+}
+
+/// 6.8. GOAWAY
+mod goaway {
+    use super::*;
+
+    /// Sends a GOAWAY frame with a stream identifier other than 0x0.
+    #[test]
+    fn stream_nonzero() {
+        let mut session = Session::new();
+        session.send(GOAWAY, 0, 1, b"\0\0\0\0\0\0\0\0");
+        session.expect_connection_error(ErrorCode::PROTOCOL_ERROR);
+    }
+}
+
+/// 6.9. WINDOW_UPDATE
+mod window_update {
+    use super::*;
+
+    /// Sends a WINDOW_UPDATE frame with a flow control window increment of 0.
+    #[test]
+    fn zero_increment() {
+        let mut session = Session::new();
+        session.send_window_update(0, 0);
+        session.expect_connection_error(ErrorCode::PROTOCOL_ERROR);
+    }
+
+    /// Sends a WINDOW_UPDATE frame with a flow control window increment of 0 on a stream.
+    #[test]
+    fn zero_increment_on_stream() {
+        let mut session = Session::new();
+        session.send_request(1, false);
+        session.send_window_update(1, 0);
+        session.expect_stream_error(1, ErrorCode::PROTOCOL_ERROR);
+    }
+
+    /// Sends a WINDOW_UPDATE frame with a length other than 4 octets.
+    #[test]
+    fn invalid_length() {
+        let mut session = Session::new();
+        session.send(WINDOW_UPDATE, 0, 0, b"\0\0\x01");
+        session.expect_connection_error(ErrorCode::FRAME_SIZE_ERROR);
+    }
+
+    /// 6.9.1. Sends multiple WINDOW_UPDATE frames increasing the flow control window to above
+    /// 2^31-1.
+    #[test]
+    fn connection_window_overflow() {
+        let mut session = Session::new();
+        session.send_window_update(0, 0x7fffffff - 65535);
+        let _ = session.expect_no_error();
+        session.send_window_update(0, 1);
+        session.expect_connection_error(ErrorCode::FLOW_CONTROL_ERROR);
+    }
+
+    /// 6.9.1. Sends multiple WINDOW_UPDATE frames increasing the flow control window to above
+    /// 2^31-1 on a stream.
+    #[test]
+    fn stream_window_overflow() {
+        let mut session = Session::new();
+        session.send_request(1, false);
+        session.send_window_update(1, 0x7fffffff - 65535);
+        let _ = session.expect_no_error();
+        session.send_window_update(1, 1);
+        session.expect_stream_error(1, ErrorCode::FLOW_CONTROL_ERROR);
+    }
+
+    /// 6.9.2. Changes SETTINGS_INITIAL_WINDOW_SIZE so that a stream’s window goes above 2^31-1.
+    #[test]
+    fn initial_window_size_overflow() {
+        let mut session = Session::new();
+        session.send_request(1, false);
+        session.send_window_update(1, 0x7fffffff - 65535);
+        let _ = session.expect_no_error();
+        session.send_settings(&[(SETTINGS_INITIAL_WINDOW_SIZE, 65536)]);
+        session.expect_connection_error(ErrorCode::FLOW_CONTROL_ERROR);
+    }
+}
+
+/// 6.9.1. The Flow-Control Window, as the receiver of DATA
+mod flow_control {
+    use super::*;
+
+    /// Sends more DATA than the connection’s flow-control window allows.
+    #[test]
+    fn connection_window_exceeded() {
+        let mut session = Session::new();
+        // Otherwise the window would be replenished as the data arrived.
+        session.connection.set_update_policy(UpdatePolicy::Manual);
+        // Each stream’s window has room for 16,384 octets more than the connection’s.
+        for &stream_id in &[1, 3, 5] {
+            session.send_request(stream_id, false);
+            session.send(DATA, 0, stream_id, &[0; 16384]);
+        }
+        session.send(DATA, 0, 5, &[0; 16383]);
+        assert_eq!(session.expect_no_error(), vec![]);
+        session.send(DATA, 0, 5, &[0; 1]);
+        session.expect_connection_error(ErrorCode::FLOW_CONTROL_ERROR);
+    }
+
+    /// Sends more DATA than a stream’s flow-control window allows.
+    #[test]
+    fn stream_window_exceeded() {
+        let mut session = Session::with_settings(Settings::Parameters {
+            header_table_size: None,
+            enable_push: None,
+            max_concurrent_streams: None,
+            initial_window_size: Some(100),
+            max_frame_size: None,
+            max_header_list_size: None,
+            enable_connect_protocol: None,
+            no_rfc7540_priorities: None,
+            unknown: vec![],
+        });
+        session.connection.set_update_policy(UpdatePolicy::Manual);
+        session.send_request(1, false);
+        session.send(DATA, 0, 1, &[0; 100]);
+        let _ = session.expect_no_error();
+        session.send(DATA, 0, 1, &[0; 1]);
+        session.expect_stream_error(1, ErrorCode::FLOW_CONTROL_ERROR);
+    }
+}
+
+/// 6.10. CONTINUATION
+mod continuation {
+    use super::*;
+
+    /// Sends multiple CONTINUATION frames preceded by a HEADERS frame.
+    #[test]
+    fn split() {
+        let mut session = Session::new();
+        session.send(HEADERS, END_STREAM, 1, &REQUEST[..2]);
+        session.send(CONTINUATION, 0, 1, &REQUEST[2..5]);
+        session.send(CONTINUATION, END_HEADERS, 1, &REQUEST[5..]);
+        assert!(session.expect_no_error().iter().any(|&(header, _)| header.type_ == HEADERS));
+    }
+
+    /// Sends a CONTINUATION frame followed by any frame other than CONTINUATION.
+    #[test]
+    fn interrupted_by_data() {
+        let mut session = Session::new();
+        session.send(HEADERS, 0, 1, &REQUEST[..2]);
+        session.send(CONTINUATION, 0, 1, &REQUEST[2..]);
+        session.send(DATA, END_STREAM, 1, b"test");
+        session.expect_connection_error(ErrorCode::PROTOCOL_ERROR);
+    }
+
+    /// Sends a CONTINUATION frame with 0x0 stream identifier.
+    #[test]
+    fn stream_zero() {
+        let mut session = Session::new();
+        session.send(HEADERS, END_STREAM, 1, &REQUEST[..2]);
+        session.send(CONTINUATION, END_HEADERS, 0, &REQUEST[2..]);
+        session.expect_connection_error(ErrorCode::PROTOCOL_ERROR);
+    }
+
+    /// Sends a CONTINUATION frame preceded by a HEADERS frame with END_HEADERS flag.
+    #[test]
+    fn after_end_headers() {
+        let mut session = Session::new();
+        session.send(HEADERS, END_HEADERS, 1, REQUEST);
+        session.send(CONTINUATION, END_HEADERS, 1, REQUEST);
+        session.expect_connection_error(ErrorCode::PROTOCOL_ERROR);
+    }
+
+    /// Sends a CONTINUATION frame on another stream.
+    #[test]
+    fn other_stream() {
+        let mut session = Session::new();
+        session.send(HEADERS, END_STREAM, 1, &REQUEST[..2]);
+        session.send(CONTINUATION, END_HEADERS, 3, &REQUEST[2..]);
+        session.expect_connection_error(ErrorCode::PROTOCOL_ERROR);
+    }
+}
+
+/// RFC 7541, HPACK
+mod hpack {
+    use super::*;
+
+    /// Sends a request whose header block ends with `instructions`.
+    fn send_request_with(session: &mut Session, instructions: &[u8]) {
+        let mut block = REQUEST.to_vec();
+        block.extend_from_slice(instructions);
+        session.send(HEADERS, END_STREAM | END_HEADERS, 1, &block);
+    }
+
+    /// 2.3.3. Index Address Space: Sends an indexed header field representation with index 0.
+    #[test]
+    fn index_zero() {
+        let mut session = Session::new();
+        send_request_with(&mut session, b"\x80");
+        session.expect_connection_error(ErrorCode::COMPRESSION_ERROR);
+    }
+
+    /// 2.3.3. Index Address Space: Sends an indexed header field representation with an index
+    /// beyond the dynamic table.
+    #[test]
+    fn index_too_large() {
+        let mut session = Session::new();
+        send_request_with(&mut session, b"\xbe");
+        session.expect_connection_error(ErrorCode::COMPRESSION_ERROR);
+    }
+
+    /// 4.2. Maximum Table Size: Sends a dynamic table size update larger than the value of
+    /// SETTINGS_HEADER_TABLE_SIZE.
+    #[test]
+    fn table_size_update_too_large() {
+        let mut session = Session::new();
+        // 4097, over the default of 4,096.
+        session.send(HEADERS, END_STREAM | END_HEADERS, 1, b"\x3f\xe2\x1f\x82\x86\x84");
+        session.expect_connection_error(ErrorCode::COMPRESSION_ERROR);
+    }
+
+    /// 4.2. Maximum Table Size: Sends a dynamic table size update within the limit.
+    #[test]
+    fn table_size_update() {
+        let mut session = Session::new();
+        session.send(HEADERS, END_STREAM | END_HEADERS, 1, b"\x3f\xe1\x1f\x82\x86\x84");
+        let _ = session.expect_no_error();
+    }
+
+    /// 5.2. String Literal Representation: Sends a Huffman-encoded string literal
+    /// representation with padding longer than 7 bits.
+    #[test]
+    fn huffman_padding_too_long() {
+        let mut session = Session::new();
+        // `:path` with the value `a` (00011) and nine bits of padding.
+        send_request_with(&mut session, b"\x04\x82\x1f\xff");
+        session.expect_connection_error(ErrorCode::COMPRESSION_ERROR);
+    }
+
+    /// 5.2. String Literal Representation: Sends a Huffman-encoded string literal
+    /// representation padded by zero.
+    #[test]
+    fn huffman_padding_zero() {
+        let mut session = Session::new();
+        send_request_with(&mut session, b"\x04\x81\x18");
+        session.expect_connection_error(ErrorCode::COMPRESSION_ERROR);
+    }
+
+    /// 5.2. String Literal Representation: Sends a Huffman-encoded string literal
+    /// representation containing the EOS symbol.
+    #[test]
+    fn huffman_eos() {
+        let mut session = Session::new();
+        send_request_with(&mut session, b"\x04\x84\xff\xff\xff\xff");
+        session.expect_connection_error(ErrorCode::COMPRESSION_ERROR);
+    }
+
+    /// 5.2. String Literal Representation: Sends a Huffman-encoded string literal correctly
+    /// padded.
+    #[test]
+    fn huffman() {
+        let mut session = Session::new();
+        send_request_with(&mut session, b"\x04\x81\x1f");
+        let _ = session.expect_no_error();
+    }
+
+    /// 6.3. Dynamic Table Size Update: Sends a dynamic table size update at the end of a header
+    /// block.
+    #[test]
+    fn table_size_update_at_end() {
+        let mut session = Session::new();
+        send_request_with(&mut session, b"\x3f\xe1\x1f");
+        session.expect_connection_error(ErrorCode::COMPRESSION_ERROR);
+    }
+}